@@ -9,6 +9,8 @@ pub struct Port {
     pub dimension: Vec<i64>,
     pub default: Option<toml::Value>,
     pub specialized: Vec<String>,
+    /// unit attribute, empty if the field has none
+    pub unit: String,
 
     pub is_generic: bool,
     pub is_specialized: bool,
@@ -45,6 +45,22 @@ pub struct MsgPackTemplate {
     pub imports: BTreeSet<String>,
 }
 
+#[derive(Template)]
+#[template(path = "plugin.rs", escape="none")]
+pub struct PluginTemplate {
+    pub name: String,
+    pub metadata: String,
+    pub specializations: Vec<Specialization>,
+}
+
+/// Generic specialization of the model struct that the plugin can create
+pub struct Specialization {
+    /// match pattern for the specialization string
+    pub pattern: String,
+    /// fully specialized model type
+    pub typename: String,
+}
+
 pub fn rust_dimstr(typename: &String, dims: &Vec<i64>) -> String {
     let txtarr: Vec<String> = dims
         .into_iter()
@@ -92,6 +108,121 @@ pub fn join_string(strings: &Vec<String>) -> String {
     strings.join(",")
 }
 
+// all combinations of the generic options of the model struct,
+// with the default combination first
+pub fn model_specializations(ctxt: &Context) -> Vec<Specialization> {
+    let model = &ctxt.structinfo[&ctxt.name];
+    let mut combos: Vec<Vec<String>> = vec![vec![]];
+    for gen in model.generics.values() {
+        let options = if gen.options.is_empty() {
+            vec![gen.default.clone()]
+        } else {
+            gen.options.clone()
+        };
+        let mut next = vec![];
+        for combo in &combos {
+            for opt in &options {
+                let mut c = combo.clone();
+                c.push(opt.clone());
+                next.push(c);
+            }
+        }
+        combos = next;
+    }
+    let defaults: Vec<String> = model.generics.values().map(|g| g.default.clone()).collect();
+    combos.sort_by_key(|c| *c != defaults);
+
+    combos.into_iter().map(|c| {
+        let spec = c.join(",");
+        let pattern = if c == defaults {
+            format!("\"\" | \"{}\"", spec)
+        } else {
+            format!("\"{}\"", spec)
+        };
+        let typename = if c.is_empty() {
+            ctxt.name.clone()
+        } else {
+            format!("{}<{}>", ctxt.name, spec)
+        };
+        Specialization { pattern, typename }
+    }).collect()
+}
+
+// substitute generic names with the types they were specialized with
+fn resolve_type(name: &String, generics: &BTreeMap<String, String>) -> String {
+    match generics.get(name) {
+        Some(t) => t.clone(),
+        None => name.clone(),
+    }
+}
+
+// attributes of a leaf field given by the fields enclosing it
+struct Inherited<'a> {
+    tag: &'a str,
+    unit: &'a str,
+}
+
+// walk the struct definition, appending a table for each leaf field
+fn flatten_struct(ctxt: &Context, sd: &RsisStruct, generics: &BTreeMap<String, String>,
+                  path: &[String], ind: &[i64], parent: &Inherited, fields: &mut Vec<toml::Value>) {
+    for (i, f) in sd.fields.iter().enumerate() {
+        let mut fpath = path.to_vec();
+        fpath.push(f.name.clone());
+        let mut find = ind.to_vec();
+        find.push(i as i64);
+        let ftag = if path.is_empty() {
+            match ctxt.tags.iter().find(|(_, v)| **v == f.name) {
+                Some((k, _)) => k.as_str(),
+                None => "",
+            }
+        } else {
+            parent.tag
+        };
+        let specialized: Vec<String> = f.specialized.iter().map(|t| resolve_type(t, generics)).collect();
+        // the unit of a struct field applies to its leaves without their own
+        let funit = if f.unit.is_empty() { parent.unit } else { f.unit.as_str() };
+
+        if f.is_struct && !f.is_ndarray {
+            let child = &ctxt.structinfo[&f.typename];
+            let cgenerics: BTreeMap<String, String> = child.generics.keys().cloned().zip(specialized).collect();
+            flatten_struct(ctxt, child, &cgenerics, &fpath, &find, &Inherited { tag: ftag, unit: funit }, fields);
+            continue;
+        }
+
+        let typename = if specialized.is_empty() {
+            resolve_type(&f.typename, generics)
+        } else {
+            format!("{}<{}>", f.typename, specialized.join(","))
+        };
+        let mut field = toml::Table::new();
+        field.insert("path".to_string(), toml::Value::from(fpath.join(".")));
+        field.insert("index".to_string(), toml::Value::from(find));
+        field.insert("type".to_string(), toml::Value::from(typename));
+        field.insert("dims".to_string(), toml::Value::from(f.dimension.clone()));
+        field.insert("tag".to_string(), toml::Value::from(ftag));
+        if !funit.is_empty() {
+            field.insert("unit".to_string(), toml::Value::from(funit));
+        }
+        fields.push(toml::Value::from(field));
+    }
+}
+
+/// Flattened description of the model interface, one entry per leaf field.
+/// Generic types are left unresolved, to be specialized by the loader
+pub fn model_metadata(ctxt: &Context) -> String {
+    let model = &ctxt.structinfo[&ctxt.name];
+    let mut fields = vec![];
+    let generics: BTreeMap<String, String> = BTreeMap::new();
+    flatten_struct(ctxt, model, &generics, &[], &[], &Inherited { tag: "", unit: "" }, &mut fields);
+
+    let mut meta = toml::Table::new();
+    meta.insert("name".to_string(), toml::Value::from(ctxt.name.clone()));
+    meta.insert("generics".to_string(), toml::Value::from(model.generics.keys().cloned().collect::<Vec<String>>()));
+    meta.insert("defaults".to_string(), toml::Value::from(model.generics.values().map(|g| g.default.clone()).collect::<Vec<String>>()));
+    meta.insert("fields".to_string(), toml::Value::from(fields));
+    toml::to_string(&meta).unwrap()
+}
+
 pub fn generate_template(ctxt: &Context, filename: &str, template: &str) -> bool {
     let path = PathBuf::from(filename);
     let dir = path.parent().unwrap();
@@ -128,6 +259,21 @@ pub fn generate_template(ctxt: &Context, filename: &str, template: &str) -> bool
         "interface.cxx" => {
             return false
         },
+        "plugin.rs" => {
+            if !ctxt.structinfo.contains_key(&ctxt.name) {
+                println!("Model struct [{}] is not defined", ctxt.name);
+                return false
+            }
+            let plg_int = PluginTemplate {
+                name: ctxt.name.clone(),
+                metadata: model_metadata(ctxt),
+                specializations: model_specializations(ctxt),
+            };
+            match plg_int.render() {
+                Ok(t) => txt = t,
+                Err(_e) => return false,
+            }
+        },
         "msgpack.rs" => {
             let msp_int = MsgPackTemplate {
                 structs: ctxt.structs.clone(),
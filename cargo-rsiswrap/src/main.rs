@@ -2,6 +2,7 @@ use clap::Parser;
 use toml::Table;
 use std::fs;
 use std::collections::{HashMap, BTreeMap, BTreeSet};
+use rmodel::units::Unit;
 
 mod context;
 use crate::context::*;
@@ -200,6 +201,17 @@ fn parse_field(ctxt : &mut Context, sd : &mut RsisStruct, data : &toml::Table) -
         }
     }
 
+    let mut unit = "".to_string();
+    if data.contains_key("unit") {
+        match data["unit"].as_str() {
+            Some(val) => unit = val.to_string(),
+            None => return Err(format!("Field {} [unit] is not a string", name))
+        }
+        if let Err(e) = Unit::parse(&unit) {
+            return Err(format!("Field {} [unit]: {}", name, e))
+        }
+    }
+
     if data.contains_key("tag") {
         let tag = match data["tag"].as_str() {
             Some(val) => val.to_string(),
@@ -218,6 +230,7 @@ fn parse_field(ctxt : &mut Context, sd : &mut RsisStruct, data : &toml::Table) -
         base_type: "".to_string(),
         default: None,
         specialized: specialized,
+        unit,
         is_generic: is_generic,
         is_specialized: is_specialized,
         is_struct: is_struct,
@@ -255,6 +268,7 @@ fn parse_generic(name : String, data : &toml::Table) -> Result<GenericType, Stri
                 if !gen.options.contains(&defs) {
                     return Err(format!("Generic [{}] has default {} which is not included in the defined options", name, defs));
                 }
+                gen.default = defs;
             },
             None => return Err(format!("Generic [{}] `default` is not a string", name))
         }
@@ -411,7 +425,7 @@ fn main() -> Result<(), String> {
     }.to_string();
 
     match args.target.as_str() {
-        "interface.rs" | "interface.hxx" | "msgpack.rs" => {},
+        "interface.rs" | "interface.hxx" | "msgpack.rs" | "plugin.rs" => {},
         _ => {
             return Err("Unsupported target".to_string())
         }
@@ -20,6 +20,7 @@ pub fn add_predefined_structures(ctxt: &mut Context) {
                 dimension: vec![],
                 default: Some(toml::Value::from(0)),
                 specialized: vec![],
+                unit: "".to_string(),
                 is_generic: false,
                 is_specialized: false,
                 is_struct: false,
@@ -32,6 +33,7 @@ pub fn add_predefined_structures(ctxt: &mut Context) {
                 dimension: vec![],
                 default: Some(toml::Value::from(0)),
                 specialized: vec![],
+                unit: "".to_string(),
                 is_generic: false,
                 is_specialized: false,
                 is_struct: false,
@@ -7,6 +7,8 @@ use {{imp}};
 use nalgebra::SMatrix;
 {%- endif %}
 {%- macro write_struct(name, structdef) %}
+// type names follow the IDL
+#[allow(non_camel_case_types)]
 #[derive(Default, Clone)]
 {%- if structdef.is_generic %}
 pub struct {{name}} <{{self::generics_join(structdef.generics)}}> {
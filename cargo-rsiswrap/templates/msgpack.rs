@@ -12,18 +12,24 @@ use serde::{Deserialize, Serialize};
 use crate::{{name}}_interface::*;
 
 {%- macro write_from_mp(name, structdef) %}
+// returns 0 on success, 1 if the index is empty, 2 if it is out of range and 3 if the value does not decode
 {%- if structdef.is_generic %}
 pub fn {{name}}_from_msgpack<{{self::generics_deserialize(structdef.generics)}}>(obj: &mut {{name}}<{{self::generics_join(structdef.generics)}}>, mp: &[u8], ind: &[i32]) -> i32 {
 {%- else %}
 pub fn {{name}}_from_msgpack(obj: &mut {{name}}, mp: &[u8], ind: &[i32]) -> i32 {
 {%- endif %}
-    if ind.len() == 0 { return 1; }
+    if ind.is_empty() { return 1; }
     match ind[0] {
     {%- for f in structdef.fields %}
         {%- if f.is_struct %}
         {{loop.index0}} => return {{f.typename}}_from_msgpack(&mut obj.{{f.name}}, mp, &ind[1..]),
         {%- else %}
-        {{loop.index0}} => { obj.{{f.name}} = rmp_serde::from_slice(mp).unwrap(); },
+        {{loop.index0}} => {
+            match rmp_serde::from_slice(mp) {
+                Ok(val) => obj.{{f.name}} = val,
+                Err(_) => return 3,
+            }
+        },
         {%- endif %}
     {%- endfor %}
         _ => return 2,
@@ -38,6 +44,7 @@ pub fn {{name}}_to_msgpack<T: Serialize>(obj: &mut {{name}}<{{self::generics_joi
 {%- else %}
 pub fn {{name}}_to_msgpack(obj: &mut {{name}}, ind: &[i32]) -> Result<Vec<u8>, Error> {
 {%- endif %}
+    if ind.is_empty() { return Err(Error::Syntax("RSIS > index is empty".to_string())); }
     match ind[0] {
     {%- for f in structdef.fields %}
         {%- if f.is_struct %}
@@ -46,7 +53,7 @@ pub fn {{name}}_to_msgpack(obj: &mut {{name}}, ind: &[i32]) -> Result<Vec<u8>, E
         {{loop.index0}} => rmp_serde::to_vec(&obj.{{f.name}}),
         {%- endif %}
     {%- endfor %}
-        _ => return Err(Error::Syntax("RSIS > index exceeded length".to_string())),
+        _ => Err(Error::Syntax("RSIS > index exceeded length".to_string())),
     }
 }
 {%- endmacro %}
@@ -0,0 +1,189 @@
+// Generated with rsiswrap
+// model : {{name}}
+use std::ffi::{c_char, c_void, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use rmodel::*;
+use crate::{{name}}_interface::*;
+use crate::{{name}}_msgpack::*;
+
+type Instance = Box<dyn RPlugin>;
+
+// flattened model interface, NUL terminated
+static METADATA: &str = concat!(r#"{{metadata}}"#, "\0");
+{% for s in specializations %}
+impl RInterface for {{s.typename}} {
+    fn get_msgpack(&mut self, ind: &[i32]) -> Result<Vec<u8>, String> {
+        match {{name}}_to_msgpack(self, ind) {
+            Ok(mp) => Ok(mp),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+    fn set_msgpack(&mut self, ind: &[i32], mp: &[u8]) -> i32 {
+        {{name}}_from_msgpack(self, mp, ind)
+    }
+}
+{% endfor %}
+unsafe fn instance<'a>(obj: *mut c_void) -> &'a mut Instance {
+    &mut *(obj as *mut Instance)
+}
+
+unsafe fn framework(time: *const RFrameTime) -> Box<dyn RFrameWork> {
+    if time.is_null() {
+        Box::new(RFrameTime::default())
+    } else {
+        Box::new(*time)
+    }
+}
+
+unsafe fn index<'a>(ind: *const i32, len: usize) -> &'a [i32] {
+    if ind.is_null() || len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ind, len)
+    }
+}
+
+/// Version of the plugin ABI, checked by the loader before any other call
+///
+/// # Safety
+/// Always safe, unsafe only to match the other entry points
+#[no_mangle]
+pub unsafe extern "C" fn rsis_abi_version() -> u32 {
+    RSIS_ABI_VERSION
+}
+
+/// Flattened model interface as a NUL terminated string
+///
+/// # Safety
+/// The pointer is static and must not be freed
+#[no_mangle]
+pub unsafe extern "C" fn rsis_metadata() -> *const c_char {
+    METADATA.as_ptr() as *const c_char
+}
+
+/// Create a model instance with the comma separated generic specialization.
+/// An empty string selects the default specialization
+///
+/// # Safety
+/// `generic` must be null or a NUL terminated string.
+/// The instance must be released with `rsis_delete`
+#[no_mangle]
+pub unsafe extern "C" fn rsis_new(generic: *const c_char) -> *mut c_void {
+    let spec = if generic.is_null() {
+        ""
+    } else {
+        match CStr::from_ptr(generic).to_str() {
+            Ok(txt) => txt,
+            Err(_) => return std::ptr::null_mut(),
+        }
+    };
+    let obj : Instance = match spec {
+    {%- for s in specializations %}
+        {{s.pattern}} => Box::new(<{{s.typename}}>::default()),
+    {%- endfor %}
+        _ => return std::ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(obj)) as *mut c_void
+}
+
+/// Release an instance
+///
+/// # Safety
+/// `obj` must be null or an instance of `rsis_new` that is not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn rsis_delete(obj: *mut c_void) {
+    if !obj.is_null() {
+        drop(Box::from_raw(obj as *mut Instance));
+    }
+}
+
+/// Call config on the model, a panic is reported as an error
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new` and `time` must be null or valid for the call
+#[no_mangle]
+pub unsafe extern "C" fn rsis_config(obj: *mut c_void, time: *const RFrameTime) -> u32 {
+    let mut fw = framework(time);
+    match catch_unwind(AssertUnwindSafe(|| instance(obj).config(&mut fw))) {
+        Ok(status) => status as u32,
+        Err(_) => ConfigStatus::ERR as u32,
+    }
+}
+
+/// Call init on the model, a panic is reported as an error
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new` and `time` must be null or valid for the call
+#[no_mangle]
+pub unsafe extern "C" fn rsis_init(obj: *mut c_void, time: *const RFrameTime) -> u32 {
+    let mut fw = framework(time);
+    match catch_unwind(AssertUnwindSafe(|| instance(obj).init(&mut fw))) {
+        Ok(status) => status as u32,
+        Err(_) => ConfigStatus::ERR as u32,
+    }
+}
+
+/// Call step on the model, a panic is reported as an error
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new` and `time` must be null or valid for the call
+#[no_mangle]
+pub unsafe extern "C" fn rsis_step(obj: *mut c_void, time: *const RFrameTime) -> u32 {
+    let mut fw = framework(time);
+    match catch_unwind(AssertUnwindSafe(|| instance(obj).step(&mut fw))) {
+        Ok(status) => status as u32,
+        Err(_) => RunStatus::ERR as u32,
+    }
+}
+
+/// Call halt on the model, a panic is reported as an error
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new` and `time` must be null or valid for the call
+#[no_mangle]
+pub unsafe extern "C" fn rsis_halt(obj: *mut c_void, time: *const RFrameTime) -> u32 {
+    let mut fw = framework(time);
+    match catch_unwind(AssertUnwindSafe(|| instance(obj).halt(&mut fw))) {
+        Ok(status) => status as u32,
+        Err(_) => RunStatus::ERR as u32,
+    }
+}
+
+/// Encode the element at the index into the buffer.
+/// Returns the encoded length, which is not copied if it exceeds the capacity.
+/// Returns -1 on failure
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new`, `ind` must point to `len` indices
+/// and `buf` to `cap` writable bytes, or be null
+#[no_mangle]
+pub unsafe extern "C" fn rsis_get(obj: *mut c_void, ind: *const i32, len: usize, buf: *mut u8, cap: usize) -> i64 {
+    let ind = index(ind, len);
+    match catch_unwind(AssertUnwindSafe(|| instance(obj).get_msgpack(ind))) {
+        Ok(Ok(mp)) => {
+            if !buf.is_null() && mp.len() <= cap {
+                std::ptr::copy_nonoverlapping(mp.as_ptr(), buf, mp.len());
+            }
+            mp.len() as i64
+        },
+        _ => -1,
+    }
+}
+
+/// Decode the buffer into the element at the index.
+/// Returns 0 on success
+///
+/// # Safety
+/// `obj` must be an instance of `rsis_new`, `ind` must point to `len` indices
+/// and `buf` to `size` bytes, or be null
+#[no_mangle]
+pub unsafe extern "C" fn rsis_set(obj: *mut c_void, ind: *const i32, len: usize, buf: *const u8, size: usize) -> i32 {
+    let ind = index(ind, len);
+    let mp = if buf.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(buf, size)
+    };
+    catch_unwind(AssertUnwindSafe(|| instance(obj).set_msgpack(ind, mp))).unwrap_or(-1)
+}
@@ -0,0 +1,459 @@
+extern crate toml;
+
+use crate::compare::{Cell, SignalTable};
+use crate::dispersion::{case_seed, Dispersion};
+use crate::engine::Engine;
+use crate::monitor::{MonitorResult, Verdict};
+use crate::plugin::PluginLoader;
+use crate::random::Random;
+use crate::runner::{init_failure, run_to_stop};
+use crate::scene::{load_scene, parse_duration, to_msgpack_value, Scene};
+use crate::serial::start_serial_engine;
+use crate::sweep::Sweep;
+use crate::trace::write_chrome_trace;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Case
+/// One run of a batch, with the values written to the scene before init
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub number : usize,
+    pub seed : u64,
+    /// signal, value
+    pub values : Vec<(String, toml::Value)>,
+}
+
+impl Case {
+    /// Write the values of the case as initial values of the scene, which are kept
+    /// with the models when the scene is built so that a reset runs the case again
+    pub fn apply(&self, scene : &mut Scene) -> Result<(), String> {
+        // noise differs between cases, and repeats for the same case
+        scene.reseed(self.seed);
+        for (signal, val) in self.values.iter() {
+            scene.initial.push((signal.clone(), to_msgpack_value(val)?));
+        }
+        Ok(())
+    }
+}
+
+/// Result of a case
+#[derive(Clone, Debug)]
+pub struct CaseResult {
+    pub case : Case,
+    pub verdict : Verdict,
+    /// simulation time reached, seconds
+    pub time : f64,
+    /// directory of the case logs
+    pub dir : PathBuf,
+    /// value of each batch metric, None if the case failed to execute
+    pub metrics : Vec<Option<f64>>,
+}
+
+/// Statistic of a logged signal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stat {
+    FINAL,
+    MIN,
+    MAX,
+    MEAN,
+    RMS,
+}
+
+/// Metric
+/// Output of a case computed from its logs, entry of the [metrics] table of a batch file
+#[derive(Clone, Debug)]
+pub struct Metric {
+    pub name : String,
+    /// log column, e.g. `gen2.output` or `imu.rate[2]`
+    pub signal : String,
+    pub stat : Stat,
+}
+
+impl Metric {
+    /// Parse an entry of the [metrics] table, e.g. `gen2_rms = {signal = "gen2.output", stat = "rms"}`
+    pub fn parse(name : &str, val : &toml::Value) -> Result<Metric, String> {
+        let ctxt = format!("[metrics].{}", name);
+        let signal = match val.get("signal").and_then(|s| s.as_str()) {
+            Some(s) => s.to_string(),
+            None => return Err(format!("{}.signal is missing or not a string", ctxt)),
+        };
+        let stat = match val.get("stat").and_then(|s| s.as_str()) {
+            Some("final") => Stat::FINAL,
+            Some("min") => Stat::MIN,
+            Some("max") => Stat::MAX,
+            Some("mean") => Stat::MEAN,
+            Some("rms") => Stat::RMS,
+            _ => return Err(format!("{}.stat must be final, min, max, mean or rms", ctxt)),
+        };
+        if val.as_table().is_some_and(|t| t.len() != 2) {
+            return Err(format!("{} has keys other than signal and stat", ctxt));
+        }
+        Ok(Metric { name : name.to_string(), signal, stat })
+    }
+
+    /// Compute the metric from the logs of a case
+    pub fn compute(&self, logs : &[SignalTable]) -> Result<f64, String> {
+        let column = match logs.iter().find_map(|t| t.names.iter().position(|n| *n == self.signal).map(|i| &t.columns[i])) {
+            Some(c) => c,
+            None => return Err(format!("Metric {}: [{}] is not logged by the scene", self.name, self.signal)),
+        };
+        let mut xs = Vec::new();
+        for c in column {
+            match c {
+                Cell::NUM(x) => xs.push(*x),
+                Cell::TEXT(t) => return Err(format!("Metric {}: [{}] logged {} which is not a number", self.name, self.signal, t)),
+            }
+        }
+        if xs.is_empty() {
+            return Err(format!("Metric {}: [{}] has no samples", self.name, self.signal));
+        }
+        let n = xs.len() as f64;
+        Ok(match self.stat {
+            Stat::FINAL => xs[xs.len() - 1],
+            Stat::MIN => xs.iter().cloned().fold(f64::INFINITY, f64::min),
+            Stat::MAX => xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            Stat::MEAN => xs.iter().sum::<f64>() / n,
+            Stat::RMS => (xs.iter().map(|x| x * x).sum::<f64>() / n).sqrt(),
+        })
+    }
+}
+
+/// Batch
+/// Cases of a scene run in parallel, loaded from a sidecar TOML file:
+///
+/// ```toml
+/// scene = "st_2m.toml"
+/// cases = 100
+/// seed = 7
+/// [dispersions]
+/// "gen1.params.amplitude" = {dist = "uniform", min = 0.5, max = 1.5}
+/// [metrics]
+/// gen2_max = {signal = "gen2.output", stat = "max"}
+/// ```
+pub struct Batch {
+    pub scene : PathBuf,
+    /// number of cases
+    pub cases : usize,
+    pub seed : u64,
+    /// seconds, the scene stop time if not given
+    pub stop : Option<f64>,
+    /// directory of the summary and of one log directory per case
+    pub output : PathBuf,
+    pub dispersions : Vec<Dispersion>,
+    /// deterministic cases, which set the number of cases
+    pub sweep : Option<Sweep>,
+    pub metrics : Vec<Metric>,
+}
+
+impl Batch {
+    pub fn load(file : &Path) -> Result<Batch, String> {
+        let txt = match fs::read_to_string(file) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Unable to read batch {}: {}", file.display(), e)),
+        };
+        let data = match txt.parse::<toml::Table>() {
+            Ok(d) => d,
+            Err(e) => return Err(format!("Failed to parse batch {}: {}", file.display(), e)),
+        };
+        // paths are relative to the batch file
+        let dir = match file.parent() {
+            Some(d) => d.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        for key in data.keys() {
+            if !["scene", "cases", "seed", "stop", "output", "dispersions", "sweep", "metrics"].contains(&key.as_str()) {
+                return Err(format!("{} is not a batch setting", key));
+            }
+        }
+        let scene = match data.get("scene").and_then(|v| v.as_str()) {
+            Some(s) => dir.join(s),
+            None => return Err("Missing scene or it is not a string".to_string()),
+        };
+        let seed = match data.get("seed") {
+            Some(toml::Value::Integer(n)) if *n >= 0 => *n as u64,
+            Some(_) => return Err("seed must be a non-negative integer".to_string()),
+            None => 0,
+        };
+        let sweep = match data.get("sweep") {
+            Some(s) => Some(Sweep::parse(s, seed)?),
+            None => None,
+        };
+        let cases = match (data.get("cases"), &sweep) {
+            (Some(_), Some(_)) => return Err("cases is set by the sweep".to_string()),
+            (Some(toml::Value::Integer(n)), None) if *n > 0 => *n as usize,
+            (Some(_), None) => return Err("cases must be a positive integer".to_string()),
+            (None, Some(s)) => s.cases.len(),
+            (None, None) => 1,
+        };
+        let stop = match data.get("stop") {
+            Some(s) => Some(parse_duration(s)?),
+            None => None,
+        };
+        let output = match data.get("output") {
+            Some(toml::Value::String(s)) => dir.join(s),
+            Some(_) => return Err("output is not a string".to_string()),
+            None => file.with_extension(""),
+        };
+        let mut dispersions = Vec::new();
+        if let Some(val) = data.get("dispersions") {
+            let tbl = match val.as_table() {
+                Some(t) => t,
+                None => return Err("[dispersions] is not a table".to_string()),
+            };
+            for (signal, d) in tbl {
+                if sweep.as_ref().is_some_and(|s| s.parameters.iter().any(|p| p.signal == *signal)) {
+                    return Err(format!("[{}] is both swept and dispersed", signal));
+                }
+                dispersions.push(Dispersion::parse(signal, d)?);
+            }
+        }
+        let mut metrics = Vec::new();
+        if let Some(val) = data.get("metrics") {
+            let tbl = match val.as_table() {
+                Some(t) => t,
+                None => return Err("[metrics] is not a table".to_string()),
+            };
+            for (name, m) in tbl {
+                metrics.push(Metric::parse(name, m)?);
+            }
+        }
+        Ok(Batch {
+            scene,
+            cases,
+            seed,
+            stop,
+            output,
+            dispersions,
+            sweep,
+            metrics,
+        })
+    }
+
+    /// Values of a case, which only depend on the batch file and the case number
+    pub fn case(&self, number : usize) -> Result<Case, String> {
+        let seed = case_seed(self.seed, number);
+        let mut rng = Random::new(seed);
+        let mut values = match &self.sweep {
+            Some(s) => match s.cases.get(number) {
+                Some(v) => v.clone(),
+                None => return Err(format!("Case {} is not one of the {} cases of the sweep", number, s.cases.len())),
+            },
+            None => Vec::new(),
+        };
+        for d in self.dispersions.iter() {
+            values.push((d.signal.clone(), d.sample(&mut rng)?));
+        }
+        Ok(Case { number, seed, values })
+    }
+
+    /// Signals set by the cases, swept then dispersed
+    pub fn parameters(&self) -> Vec<String> {
+        let swept = self.sweep.iter().flat_map(|s| s.parameters.iter().map(|p| p.signal.clone()));
+        swept.chain(self.dispersions.iter().map(|d| d.signal.clone())).collect()
+    }
+
+    /// Directory of the logs of a case
+    pub fn case_dir(&self, number : usize) -> PathBuf {
+        self.output.join(format!("case_{:04}", number))
+    }
+
+    /// Run a case on its own engine
+    pub fn run_case(&self, case : &Case) -> CaseResult {
+        let dir = self.case_dir(case.number);
+        let mut result = CaseResult {
+            case : case.clone(),
+            verdict : Verdict::default(),
+            time : 0.0,
+            dir : dir.clone(),
+            metrics : vec![None; self.metrics.len()],
+        };
+        let logs = match self.execute(case, &dir, &mut result) {
+            Ok(logs) => logs,
+            Err(e) => {
+                result.verdict.error = Some(e);
+                return result;
+            },
+        };
+        // logs are complete once the engine is dropped
+        let mut tables = Vec::new();
+        for l in logs.iter() {
+            match SignalTable::read(l) {
+                Ok(t) => tables.push(t),
+                Err(e) => {
+                    result.verdict.error = Some(e);
+                    return result;
+                },
+            }
+        }
+        for (i, m) in self.metrics.iter().enumerate() {
+            match m.compute(&tables) {
+                Ok(x) => result.metrics[i] = Some(x),
+                Err(e) => result.verdict.error = Some(e),
+            }
+        }
+        result
+    }
+
+    // runs the case, returning its log files
+    fn execute(&self, case : &Case, dir : &Path, result : &mut CaseResult) -> Result<Vec<PathBuf>, String> {
+        let mut scene = load_scene(&self.scene)?;
+        let stop = match self.stop {
+            Some(s) => s,
+            None if scene.stop > 0.0 => scene.stop,
+            None => return Err(format!("Scene {} has no stop time and the batch does not set one", self.scene.display())),
+        };
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Unable to create {}: {}", dir.display(), e));
+        }
+        scene.relocate(dir);
+        // noise differs between cases, and repeats for the same case
+        case.apply(&mut scene)?;
+
+        let mut loader = PluginLoader::new();
+        let mut engine : Box<dyn Engine + Send> = Box::new(start_serial_engine(scene.build(&mut loader)?)?);
+        engine.set_timeline(scene.timeline());
+        if engine.init() != 0 {
+            return Err(init_failure(engine.as_mut()));
+        }
+        result.verdict = run_to_stop(&mut engine, stop);
+        result.time = engine.get_time();
+        if let Some(t) = &scene.tracing {
+            write_chrome_trace(&t.file, &engine.trace()?)?;
+        }
+        engine.end();
+        Ok(scene.logging.iter().map(|l| l.file.clone()).collect())
+    }
+
+    /// Run cases in parallel, each on its own engine
+    /// @param[in] numbers - case numbers to run
+    /// @param[in] jobs - cases run at the same time
+    /// @param[in] done - called with the result of every case as it completes, from the thread that ran it
+    pub fn run(&self, numbers : &[usize], jobs : usize, done : &(dyn Fn(&CaseResult) + Sync)) -> Result<Vec<CaseResult>, String> {
+        let mut cases = Vec::new();
+        for n in numbers {
+            cases.push(self.case(*n)?);
+        }
+        let next = AtomicUsize::new(0);
+        let results : Mutex<Vec<Option<CaseResult>>> = Mutex::new(vec![None; cases.len()]);
+        thread::scope(|s| {
+            for _ in 0..jobs.max(1).min(cases.len()) {
+                s.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let case = match cases.get(i) {
+                        Some(c) => c,
+                        None => break,
+                    };
+                    let r = self.run_case(case);
+                    done(&r);
+                    results.lock().unwrap()[i] = Some(r);
+                });
+            }
+        });
+        Ok(results.into_inner().unwrap().into_iter().flatten().collect())
+    }
+
+    /// Write the summary table of the results as CSV, one row per case with its
+    /// parameters and metrics. Parameters a case leaves at their scene value are empty
+    pub fn write_summary(&self, results : &[CaseResult]) -> Result<PathBuf, String> {
+        let parameters = self.parameters();
+        let mut header = vec!["case".to_string(), "seed".to_string()];
+        header.extend(parameters.iter().cloned());
+        header.extend(self.metrics.iter().map(|m| m.name.clone()));
+        header.extend(["result", "exit_code", "time", "failed", "first_violation", "error"].iter().map(|s| s.to_string()));
+        let mut lines = vec![header.join(",")];
+        for r in results {
+            let mut row = vec![r.case.number.to_string(), r.case.seed.to_string()];
+            for p in parameters.iter() {
+                row.push(r.case.values.iter().find(|(s, _)| s == p).map(|(_, v)| cell(v)).unwrap_or_default());
+            }
+            row.extend(r.metrics.iter().map(|m| m.map(|x| x.to_string()).unwrap_or_default()));
+            let failed : Vec<&MonitorResult> = r.verdict.monitors.iter().filter(|m| m.violation.is_some()).collect();
+            let first = failed.iter().filter_map(|m| m.violation.as_ref().map(|v| v.time)).reduce(f64::min);
+            row.push(status(&r.verdict).to_string());
+            row.push(r.verdict.exit_code().to_string());
+            row.push(((r.time * 1e9).round() / 1e9).to_string());
+            row.push(quote(&failed.iter().map(|m| m.name.clone()).collect::<Vec<String>>().join(";")));
+            row.push(first.map(|t| ((t * 1e9).round() / 1e9).to_string()).unwrap_or_default());
+            row.push(quote(r.verdict.error.as_deref().unwrap_or_default()));
+            lines.push(row.join(","));
+        }
+        if let Err(e) = fs::create_dir_all(&self.output) {
+            return Err(format!("Unable to create {}: {}", self.output.display(), e));
+        }
+        let path = self.output.join("summary.csv");
+        match fs::write(&path, lines.join("\n") + "\n") {
+            Ok(_) => Ok(path),
+            Err(e) => Err(format!("Unable to write {}: {}", path.display(), e)),
+        }
+    }
+}
+
+/// pass, fail or error
+pub fn status(verdict : &Verdict) -> &'static str {
+    match verdict.exit_code() {
+        0 => "pass",
+        1 => "fail",
+        _ => "error",
+    }
+}
+
+// CSV cell of a case value
+fn cell(val : &toml::Value) -> String {
+    match val {
+        toml::Value::String(s) => quote(s),
+        v => quote(&v.to_string()),
+    }
+}
+
+// CSV cell of free text
+fn quote(txt : &str) -> String {
+    match txt.contains(',') || txt.contains('"') {
+        true => format!("\"{}\"", txt.replace('"', "\"\"")),
+        false => txt.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(txt : &str) -> Result<Metric, String> {
+        Metric::parse("m", &toml::Value::Table(txt.parse::<toml::Table>().unwrap()))
+    }
+
+    fn log(column : Vec<Cell>) -> SignalTable {
+        SignalTable {
+            file : PathBuf::from("log.csv"),
+            names : vec!["gen.x".to_string()],
+            time : (0..column.len()).map(|i| i as f64).collect(),
+            columns : vec![column],
+        }
+    }
+
+    #[test]
+    fn metrics_compute_their_statistic() {
+        let logs = [log(vec![Cell::NUM(3.0), Cell::NUM(-4.0), Cell::NUM(1.0)])];
+        let compute = |stat : &str| metric(&format!("signal = \"gen.x\"\nstat = \"{}\"", stat)).unwrap().compute(&logs).unwrap();
+        assert_eq!(compute("final"), 1.0);
+        assert_eq!(compute("min"), -4.0);
+        assert_eq!(compute("max"), 3.0);
+        assert_eq!(compute("mean"), 0.0);
+        assert_eq!(compute("rms"), (26.0f64 / 3.0).sqrt());
+    }
+
+    #[test]
+    fn metrics_need_logged_numbers() {
+        let m = metric("signal = \"gen.x\"\nstat = \"max\"").unwrap();
+        assert!(m.compute(&[log(vec![])]).is_err());
+        assert!(m.compute(&[log(vec![Cell::NUM(1.0), Cell::TEXT("on".to_string())])]).is_err());
+        assert!(metric("signal = \"gen.y\"\nstat = \"max\"").unwrap().compute(&[log(vec![Cell::NUM(1.0)])]).is_err());
+        assert!(metric("signal = \"gen.x\"\nstat = \"median\"").is_err());
+        assert!(metric("stat = \"max\"").is_err());
+        assert!(metric("signal = \"gen.x\"\nstat = \"max\"\nunit = \"m\"").is_err());
+    }
+}
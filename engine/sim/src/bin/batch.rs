@@ -0,0 +1,60 @@
+use clap::Parser;
+use sim::batch::{status, Batch};
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+
+/// Run the cases of a batch file in parallel and write their summary table.
+/// Exits with 0 if every case passed, 1 if a case failed and 2 on errors
+#[derive(Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    /// batch TOML file
+    batch: PathBuf,
+
+    /// cases run at the same time, the number of cores by default
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    /// run only these case numbers, e.g. to reproduce a failure
+    #[arg(short, long)]
+    case: Vec<usize>,
+
+    /// directory of the summary and case logs, overrides the batch file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn run(args : &Args) -> Result<i32, String> {
+    let mut batch = Batch::load(&args.batch)?;
+    if let Some(out) = &args.output {
+        batch.output = out.clone();
+    }
+    let numbers : Vec<usize> = match args.case.is_empty() {
+        true => (0..batch.cases).collect(),
+        false => args.case.clone(),
+    };
+    let jobs = args.jobs.unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+    let results = batch.run(&numbers, jobs, &|r| println!("case {} {}", r.case.number, status(&r.verdict)))?;
+    let summary = batch.write_summary(&results)?;
+
+    let count = |s : &str| results.iter().filter(|r| status(&r.verdict) == s).count();
+    println!("{} cases: {} passed, {} failed, {} errors", results.len(), count("pass"), count("fail"), count("error"));
+    for r in results.iter().filter(|r| !r.verdict.passed()) {
+        println!("case {} seed {}:\n{}", r.case.number, r.case.seed, r.verdict.report());
+    }
+    println!("summary written to {}", summary.display());
+    Ok(results.iter().map(|r| r.verdict.exit_code()).max().unwrap_or(0))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(code) => ExitCode::from(code as u8),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        },
+    }
+}
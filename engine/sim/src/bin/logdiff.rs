@@ -0,0 +1,76 @@
+use clap::Parser;
+use sim::compare::{Align, Comparison, SignalTable, Tolerance};
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Compare a candidate signal log against a baseline log.
+/// Exits with 0 if they match, 1 if they differ and 2 on errors
+#[derive(Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    /// baseline CSV log
+    baseline: PathBuf,
+
+    /// candidate CSV log
+    candidate: PathBuf,
+
+    /// TOML file of default and per-signal tolerances
+    #[arg(short, long)]
+    tolerances: Option<PathBuf>,
+
+    /// default absolute tolerance, unless set by the tolerance file
+    #[arg(long, default_value_t = 0.0)]
+    abs: f64,
+
+    /// default relative tolerance, unless set by the tolerance file
+    #[arg(long, default_value_t = 0.0)]
+    rel: f64,
+
+    /// match samples at the same time or interpolate the candidate, unless set by the tolerance file
+    #[arg(long)]
+    interpolate: bool,
+
+    /// write the TOML report to a file, `-` for stdout
+    #[arg(short, long)]
+    report: Option<PathBuf>,
+}
+
+fn run(args : &Args) -> Result<bool, String> {
+    let mut cmp = Comparison {
+        default : Tolerance { abs : args.abs, rel : args.rel },
+        align : if args.interpolate { Align::INTERPOLATE } else { Align::EXACT },
+        ..Default::default()
+    };
+    if let Some(f) = &args.tolerances {
+        cmp = Comparison::load(f, cmp)?;
+    }
+
+    let baseline = SignalTable::read(&args.baseline)?;
+    let candidate = SignalTable::read(&args.candidate)?;
+    let report = cmp.compare(&baseline, &candidate);
+    match &args.report {
+        Some(f) if f.as_os_str() == "-" => print!("{}", report.to_toml()),
+        Some(f) => {
+            if let Err(e) = fs::write(f, report.to_toml()) {
+                return Err(format!("Unable to write report {}: {}", f.display(), e));
+            }
+            println!("{}", report.summary());
+        },
+        None => println!("{}", report.summary()),
+    }
+    Ok(report.passed())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        },
+    }
+}
@@ -0,0 +1,191 @@
+use clap::{Parser, Subcommand};
+use sim::plugin::PluginLoader;
+use sim::compose::{split_key, SCENE_KEYS};
+use sim::runner::{compose_scene, parse_assignment, parse_override, parse_time, run_scene, RunOptions};
+use sim::scene::load_scene;
+use sim::validate::validate;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Run scenes headless, without the Julia front end.
+/// Exits with 0 if the scenario passed or the scene is valid, 1 if a monitor failed
+/// or the scene has problems, and 2 on errors
+#[derive(Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a scene until its stop time and report its monitors
+    Run {
+        /// scene TOML file
+        scene: PathBuf,
+
+        /// simulation time to run in seconds or with a unit, e.g. "2 min", the scene stop time by default
+        #[arg(short, long)]
+        stop: Option<String>,
+
+        /// directory of the logs, hashes, trace and recorder dumps, next to the scene by default
+        #[arg(short, long)]
+        log: Option<PathBuf>,
+
+        /// seed of the fault noise
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// scene file applied on top of the scene, repeatable
+        #[arg(short, long)]
+        overlay: Vec<PathBuf>,
+
+        /// value written before init, e.g. gen1.params.amplitude=2, or value of the scene
+        /// file, e.g. scene.stop=10 or schedule.gen1.freq=50, repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        set: Vec<String>,
+
+        /// execute every thread on the calling thread
+        #[arg(long)]
+        serial: bool,
+
+        /// pace the frames to wall time
+        #[arg(long, conflicts_with = "serial")]
+        real_time: bool,
+    },
+    /// Check a scene without running it, reporting every problem
+    Check {
+        /// scene TOML file
+        scene: PathBuf,
+    },
+    /// Print the scene with its includes, overlays and overrides merged, and where every value came from
+    Resolve {
+        /// scene TOML file
+        scene: PathBuf,
+
+        /// scene file applied on top of the scene, repeatable
+        #[arg(short, long)]
+        overlay: Vec<PathBuf>,
+
+        /// value of the scene file, e.g. scene.stop=10, repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        set: Vec<String>,
+    },
+    /// Describe the threads, models and interfaces of a scene
+    Info {
+        /// scene TOML file
+        scene: PathBuf,
+    },
+}
+
+// keys of a scene file override the scene, others are signals written before init
+fn scene_key(txt : &str) -> bool {
+    let key = txt.split_once('=').map_or(txt, |(k, _)| k.trim());
+    split_key(key).is_ok_and(|parts| SCENE_KEYS.contains(&parts[0].as_str()))
+}
+
+fn run(args : &Args) -> Result<i32, String> {
+    match &args.command {
+        Command::Run { scene, stop, log, seed, overlay, set, serial, real_time } => {
+            let mut options = RunOptions {
+                stop : None,
+                output : log.clone(),
+                seed : *seed,
+                overlays : overlay.clone(),
+                overrides : Vec::new(),
+                set : Vec::new(),
+                serial : *serial,
+                real_time : *real_time,
+            };
+            if let Some(s) = stop {
+                options.stop = Some(parse_time(s)?);
+            }
+            for s in set.iter() {
+                match scene_key(s) {
+                    true => options.overrides.push(parse_override(s)?),
+                    false => options.set.push(parse_assignment(s)?),
+                }
+            }
+            let outcome = run_scene(scene, &options)?;
+            let mut events = outcome.events.clone();
+            for f in outcome.faults.iter() {
+                events.push((f.time, format!("fault {} on {}: {}", if f.active { "active" } else { "cleared" }, f.signal, f.fault)));
+            }
+            events.sort_by(|a, b| a.0.total_cmp(&b.0));
+            for (time, e) in events {
+                println!("t={:.6} {}", time, e);
+            }
+            println!("stopped at t={:.6}", outcome.time);
+            println!("{}", outcome.verdict.report());
+            Ok(outcome.verdict.exit_code())
+        },
+        Command::Check { scene } => {
+            let v = validate(scene);
+            if v.is_valid() {
+                println!("{} is valid", scene.display());
+                return Ok(0);
+            }
+            println!("{}", v.report());
+            println!("{} problems", v.problems.len());
+            Ok(1)
+        },
+        Command::Resolve { scene, overlay, set } => {
+            let mut options = RunOptions { overlays : overlay.clone(), ..Default::default() };
+            for s in set.iter() {
+                options.overrides.push(parse_override(s)?);
+            }
+            let c = compose_scene(scene, &options)?;
+            print!("{}", c.resolved());
+            Ok(0)
+        },
+        Command::Info { scene } => {
+            let s = load_scene(scene)?;
+            println!("scene {}", s.name);
+            if !s.desc.is_empty() {
+                println!("  {}", s.desc);
+            }
+            match s.stop > 0.0 {
+                true => println!("stop {} s", s.stop),
+                false => println!("no stop time"),
+            }
+            for (i, t) in s.threads.iter().enumerate() {
+                let models : Vec<String> = s.schedule.iter().filter(|m| m.thread == i)
+                    .map(|m| format!("{} ({} {} Hz)", m.name, m.lib, m.freq)).collect();
+                println!("thread {} at {} Hz: {}", i, t.freq, models.join(", "));
+            }
+            println!("{} connections, {} faults, {} timeline entries, {} logs, {} monitors",
+                s.connections.len(), s.faults.len(), s.timeline.len(), s.logging.len(), s.monitors.len());
+
+            let mut loader = PluginLoader::new();
+            for tc in s.build(&mut loader)? {
+                for name in tc.model_names() {
+                    println!("[{}]", name);
+                    for f in tc.fields(&name)? {
+                        let dims = match f.dims.is_empty() {
+                            true => "".to_string(),
+                            false => format!("{:?}", f.dims),
+                        };
+                        let unit = match f.unit.is_empty() {
+                            true => "".to_string(),
+                            false => format!(" [{}]", f.unit),
+                        };
+                        println!("  {:<6} {} : {}{}{}", f.tag, f.path, f.typename, dims, unit);
+                    }
+                }
+            }
+            Ok(0)
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(code) => ExitCode::from(code as u8),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        },
+    }
+}
@@ -0,0 +1,74 @@
+use clap::Parser;
+use sim::runner::parse_time;
+use sim::scene::load_scene;
+use sim::verify::{verify, Layout};
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Run a scene twice in lockstep and report the first frame and field where the model states diverge.
+/// Exits with 0 if the runs are identical, 1 if they diverge and 2 on errors
+#[derive(Parser, Debug)]
+#[command(version, about, long_about=None)]
+struct Args {
+    /// scene TOML file
+    scene: PathBuf,
+
+    /// layout of the first run: serial, parallel or single
+    #[arg(long, default_value = "parallel")]
+    first: String,
+
+    /// layout of the second run, the same as the first by default
+    #[arg(long)]
+    second: Option<String>,
+
+    /// frames between comparisons of the state hashes
+    #[arg(short, long, default_value_t = 1)]
+    every: u64,
+
+    /// simulation time to verify in seconds or with a unit, e.g. "2 min", the scene stop time by default
+    #[arg(short, long)]
+    stop: Option<String>,
+}
+
+fn run(args : &Args) -> Result<bool, String> {
+    let first = Layout::parse(&args.first)?;
+    let second = match &args.second {
+        Some(s) => Layout::parse(s)?,
+        None => first,
+    };
+    if args.every == 0 {
+        return Err("--every must be at least 1".to_string());
+    }
+    let stop = match &args.stop {
+        Some(s) => parse_time(s)?,
+        None => load_scene(&args.scene)?.stop,
+    };
+    if stop <= 0.0 {
+        return Err(format!("Scene {} has no stop time, set one with --stop", args.scene.display()));
+    }
+    let verification = verify(&args.scene, (first, second), stop, args.every)?;
+    println!("compared {} frames", verification.frames);
+    match verification.mismatch {
+        Some(m) => {
+            println!("{}", m.report());
+            Ok(false)
+        },
+        None => {
+            println!("runs are identical");
+            Ok(true)
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        },
+    }
+}
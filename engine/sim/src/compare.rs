@@ -0,0 +1,572 @@
+extern crate toml;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Value of a log cell
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cell {
+    NUM(f64),
+    /// strings and booleans, compared exactly
+    TEXT(String),
+}
+
+impl Cell {
+    fn parse(txt : &str) -> Cell {
+        match txt.parse::<f64>() {
+            Ok(x) => Cell::NUM(x),
+            Err(_) => Cell::TEXT(txt.to_string()),
+        }
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        match self {
+            Cell::NUM(x) => toml::Value::Float(*x),
+            Cell::TEXT(s) => toml::Value::String(s.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cell::NUM(x) => write!(f, "{}", x),
+            Cell::TEXT(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// SignalTable
+/// Signal log read back from a CSV file written by a scene [[logging]] entry
+pub struct SignalTable {
+    pub file : PathBuf,
+    /// column names, without the time column
+    pub names : Vec<String>,
+    pub time : Vec<f64>,
+    /// one column per name
+    pub columns : Vec<Vec<Cell>>,
+}
+
+impl SignalTable {
+    pub fn read(file : &Path) -> Result<SignalTable, String> {
+        let txt = match fs::read_to_string(file) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Unable to read log {}: {}", file.display(), e)),
+        };
+        let mut lines = txt.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let header = match lines.next() {
+            Some((_, l)) => split_row(l),
+            None => return Err(format!("Log {} is empty", file.display())),
+        };
+        if header.first().map(|h| h.as_str()) != Some("time") {
+            return Err(format!("Log {} does not start with a time column", file.display()));
+        }
+        let names = header[1..].to_vec();
+        for (i, n) in names.iter().enumerate() {
+            if names[..i].contains(n) {
+                return Err(format!("Log {} has column [{}] twice", file.display(), n));
+            }
+        }
+
+        let mut table = SignalTable {
+            file : file.to_path_buf(),
+            columns : vec![Vec::new(); names.len()],
+            names,
+            time : Vec::new(),
+        };
+        for (line, l) in lines {
+            let row = split_row(l);
+            if row.len() != header.len() {
+                return Err(format!("{}:{} has {} columns, the header has {}", file.display(), line + 1, row.len(), header.len()));
+            }
+            let t = match row[0].parse::<f64>() {
+                Ok(t) => t,
+                Err(_) => return Err(format!("{}:{} time [{}] is not a number", file.display(), line + 1, row[0])),
+            };
+            if table.time.last().is_some_and(|last| t < *last) {
+                return Err(format!("{}:{} time {} goes backwards", file.display(), line + 1, t));
+            }
+            table.time.push(t);
+            for (col, cell) in table.columns.iter_mut().zip(row[1..].iter()) {
+                col.push(Cell::parse(cell));
+            }
+        }
+        Ok(table)
+    }
+
+    fn column(&self, name : &str) -> Option<&Vec<Cell>> {
+        self.names.iter().position(|n| n == name).map(|i| &self.columns[i])
+    }
+}
+
+// splits a CSV row, quoted cells may contain commas and doubled quotes
+fn split_row(line : &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => cells.push(std::mem::take(&mut cell)),
+            (c, _) => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+/// Tolerance
+/// A candidate value matches the baseline if |candidate - baseline| <= abs + rel * |baseline|
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    pub abs : f64,
+    pub rel : f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance { abs : 0.0, rel : 0.0 }
+    }
+}
+
+impl Tolerance {
+    fn parse(val : &toml::Value, base : Tolerance, ctxt : &str) -> Result<Tolerance, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table of abs and rel", ctxt)),
+        };
+        let mut tol = base;
+        for (key, v) in tbl {
+            let x = match v {
+                toml::Value::Float(f) if *f >= 0.0 => *f,
+                toml::Value::Integer(i) if *i >= 0 => *i as f64,
+                _ => return Err(format!("{}.{} must be a non-negative number", ctxt, key)),
+            };
+            match key.as_str() {
+                "abs" => tol.abs = x,
+                "rel" => tol.rel = x,
+                _ => return Err(format!("{}.{} is not abs or rel", ctxt, key)),
+            }
+        }
+        Ok(tol)
+    }
+
+    pub fn matches(&self, baseline : f64, candidate : f64) -> bool {
+        if baseline.is_nan() || candidate.is_nan() {
+            return baseline.is_nan() && candidate.is_nan();
+        }
+        if baseline == candidate {
+            // equal infinities
+            return true;
+        }
+        (candidate - baseline).abs() <= self.abs + self.rel * baseline.abs()
+    }
+}
+
+/// How candidate samples are matched to the baseline times
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    /// sample at the same time, within the time tolerance
+    EXACT,
+    /// linear interpolation between the candidate samples around the baseline time,
+    /// text holds the previous sample
+    INTERPOLATE,
+}
+
+/// Comparison
+/// Settings of a log comparison, tolerances per signal
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub default : Tolerance,
+    /// signal, tolerance. A signal also applies to the elements of an array, `imu.rate` to `imu.rate[2]`
+    pub signals : Vec<(String, Tolerance)>,
+    pub align : Align,
+    /// seconds, for exact alignment
+    pub time_tol : f64,
+}
+
+impl Default for Comparison {
+    fn default() -> Comparison {
+        Comparison {
+            default : Tolerance::default(),
+            signals : Vec::new(),
+            align : Align::EXACT,
+            time_tol : 1e-6,
+        }
+    }
+}
+
+impl Comparison {
+    /// Load the settings from a TOML file, tolerances not set by the file keep those of base:
+    ///
+    /// ```toml
+    /// align = "interpolate"
+    /// [default]
+    /// abs = 1e-9
+    /// [signals]
+    /// "gen2.output" = {abs = 1e-6, rel = 1e-3}
+    /// ```
+    pub fn load(file : &Path, base : Comparison) -> Result<Comparison, String> {
+        let txt = match fs::read_to_string(file) {
+            Ok(t) => t,
+            Err(e) => return Err(format!("Unable to read {}: {}", file.display(), e)),
+        };
+        let data = match txt.parse::<toml::Table>() {
+            Ok(d) => d,
+            Err(e) => return Err(format!("Unable to parse {}: {}", file.display(), e)),
+        };
+        let mut cmp = base;
+        for (key, val) in data.iter() {
+            match key.as_str() {
+                "default" => cmp.default = Tolerance::parse(val, cmp.default, "[default]")?,
+                "signals" => {},
+                "align" => cmp.align = match val.as_str() {
+                    Some("exact") => Align::EXACT,
+                    Some("interpolate") => Align::INTERPOLATE,
+                    _ => return Err("align must be exact or interpolate".to_string()),
+                },
+                "time_tol" => cmp.time_tol = match val {
+                    toml::Value::Float(f) if *f >= 0.0 => *f,
+                    toml::Value::Integer(i) if *i >= 0 => *i as f64,
+                    _ => return Err("time_tol must be a non-negative number".to_string()),
+                },
+                _ => return Err(format!("{} is not a comparison setting", key)),
+            }
+        }
+        // signal tolerances default to the [default] table, whatever the order of the keys
+        if let Some(signals) = data.get("signals") {
+            let tbl = match signals.as_table() {
+                Some(t) => t,
+                None => return Err("[signals] is not a table".to_string()),
+            };
+            for (name, val) in tbl {
+                let tol = Tolerance::parse(val, cmp.default, &format!("[signals].\"{}\"", name))?;
+                cmp.signals.push((name.clone(), tol));
+            }
+        }
+        Ok(cmp)
+    }
+
+    /// Tolerance of a log column, the most specific signal entry or the default
+    pub fn tolerance(&self, column : &str) -> Tolerance {
+        let mut best : Option<&(String, Tolerance)> = None;
+        for entry in self.signals.iter() {
+            let s = &entry.0;
+            let applies = column == s || (column.starts_with(s.as_str()) && column[s.len()..].starts_with('['));
+            if applies && best.is_none_or(|b| s.len() > b.0.len()) {
+                best = Some(entry);
+            }
+        }
+        best.map(|b| b.1).unwrap_or(self.default)
+    }
+
+    /// Compare a candidate log against its baseline
+    pub fn compare(&self, baseline : &SignalTable, candidate : &SignalTable) -> DiffReport {
+        let mut report = DiffReport {
+            baseline : baseline.file.clone(),
+            candidate : candidate.file.clone(),
+            missing : baseline.names.iter().filter(|n| !candidate.names.contains(n)).cloned().collect(),
+            added : candidate.names.iter().filter(|n| !baseline.names.contains(n)).cloned().collect(),
+            compared : 0,
+            unmatched : 0,
+            signals : Vec::new(),
+            first_divergence : None,
+        };
+
+        // candidate samples for each baseline time
+        let mut samples : Vec<Option<Sample>> = Vec::new();
+        let mut j = 0;
+        for &t in baseline.time.iter() {
+            while j + 1 < candidate.time.len() && candidate.time[j + 1] <= t + self.time_tol {
+                j += 1;
+            }
+            let sample = match candidate.time.get(j) {
+                Some(&tc) if (tc - t).abs() <= self.time_tol => Some(Sample { row : j, next : None }),
+                Some(&tc) if self.align == Align::INTERPOLATE && tc < t && j + 1 < candidate.time.len() => {
+                    let tn = candidate.time[j + 1];
+                    Some(Sample { row : j, next : Some((j + 1, (t - tc) / (tn - tc))) })
+                },
+                _ => None,
+            };
+            match sample {
+                Some(_) => report.compared += 1,
+                None => report.unmatched += 1,
+            }
+            samples.push(sample);
+        }
+
+        for (name, base) in baseline.names.iter().zip(baseline.columns.iter()) {
+            let cand = match candidate.column(name) {
+                Some(c) => c,
+                None => continue,
+            };
+            let tol = self.tolerance(name);
+            let mut diff = SignalDiff {
+                name : name.clone(),
+                tolerance : tol,
+                failures : 0,
+                max_abs : 0.0,
+                max_rel : 0.0,
+                first_divergence : None,
+            };
+            for (i, sample) in samples.iter().enumerate() {
+                let c = match sample {
+                    Some(s) => s.value(cand),
+                    None => continue,
+                };
+                let ok = match (&base[i], &c) {
+                    (Cell::NUM(b), Cell::NUM(x)) => {
+                        let err = (x - b).abs();
+                        if err.is_finite() {
+                            diff.max_abs = diff.max_abs.max(err);
+                            if *b != 0.0 {
+                                diff.max_rel = diff.max_rel.max(err / b.abs());
+                            }
+                        }
+                        tol.matches(*b, *x)
+                    },
+                    (b, x) => b == x,
+                };
+                if !ok {
+                    diff.failures += 1;
+                    if diff.first_divergence.is_none() {
+                        diff.first_divergence = Some(Divergence {
+                            time : baseline.time[i],
+                            baseline : base[i].clone(),
+                            candidate : c,
+                        });
+                    }
+                }
+            }
+            if let Some(d) = &diff.first_divergence {
+                if report.first_divergence.as_ref().is_none_or(|(t, _)| d.time < *t) {
+                    report.first_divergence = Some((d.time, name.clone()));
+                }
+            }
+            report.signals.push(diff);
+        }
+        report
+    }
+}
+
+// candidate row matched to a baseline time, with the next row and its weight when interpolating
+struct Sample {
+    row : usize,
+    next : Option<(usize, f64)>,
+}
+
+impl Sample {
+    fn value(&self, column : &[Cell]) -> Cell {
+        match (&column[self.row], self.next.map(|(n, w)| (&column[n], w))) {
+            (Cell::NUM(a), Some((Cell::NUM(b), w))) => Cell::NUM(a + (b - a) * w),
+            (c, _) => c.clone(),
+        }
+    }
+}
+
+/// First sample of a signal outside its tolerance
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    pub time : f64,
+    pub baseline : Cell,
+    pub candidate : Cell,
+}
+
+/// Comparison of one signal present in both logs
+#[derive(Clone, Debug)]
+pub struct SignalDiff {
+    pub name : String,
+    pub tolerance : Tolerance,
+    /// samples outside the tolerance
+    pub failures : usize,
+    /// largest absolute and relative errors of numeric samples
+    pub max_abs : f64,
+    pub max_rel : f64,
+    pub first_divergence : Option<Divergence>,
+}
+
+/// DiffReport
+/// Result of comparing a candidate log against its baseline
+#[derive(Clone, Debug)]
+pub struct DiffReport {
+    pub baseline : PathBuf,
+    pub candidate : PathBuf,
+    /// signals of the baseline the candidate does not log
+    pub missing : Vec<String>,
+    /// signals of the candidate the baseline does not log
+    pub added : Vec<String>,
+    /// baseline times compared
+    pub compared : usize,
+    /// baseline times without a candidate sample
+    pub unmatched : usize,
+    pub signals : Vec<SignalDiff>,
+    /// earliest divergence over all signals: time, signal
+    pub first_divergence : Option<(f64, String)>,
+}
+
+impl DiffReport {
+    /// True if every baseline signal and time was matched within its tolerance.
+    /// Added signals do not fail the comparison
+    pub fn passed(&self) -> bool {
+        self.missing.is_empty() && self.unmatched == 0 && self.signals.iter().all(|s| s.failures == 0)
+    }
+
+    /// Machine readable report
+    pub fn to_toml(&self) -> String {
+        let mut tbl = toml::Table::new();
+        tbl.insert("passed".to_string(), toml::Value::Boolean(self.passed()));
+        tbl.insert("baseline".to_string(), toml::Value::String(self.baseline.display().to_string()));
+        tbl.insert("candidate".to_string(), toml::Value::String(self.candidate.display().to_string()));
+        tbl.insert("compared".to_string(), toml::Value::Integer(self.compared as i64));
+        tbl.insert("unmatched".to_string(), toml::Value::Integer(self.unmatched as i64));
+        let strings = |v : &Vec<String>| toml::Value::Array(v.iter().map(|s| toml::Value::String(s.clone())).collect());
+        tbl.insert("missing".to_string(), strings(&self.missing));
+        tbl.insert("added".to_string(), strings(&self.added));
+        if let Some((time, signal)) = &self.first_divergence {
+            let mut first = toml::Table::new();
+            first.insert("time".to_string(), toml::Value::Float(*time));
+            first.insert("signal".to_string(), toml::Value::String(signal.clone()));
+            tbl.insert("first_divergence".to_string(), toml::Value::Table(first));
+        }
+        let mut signals = Vec::new();
+        for s in self.signals.iter() {
+            let mut sig = toml::Table::new();
+            sig.insert("name".to_string(), toml::Value::String(s.name.clone()));
+            sig.insert("passed".to_string(), toml::Value::Boolean(s.failures == 0));
+            sig.insert("failures".to_string(), toml::Value::Integer(s.failures as i64));
+            sig.insert("abs_tol".to_string(), toml::Value::Float(s.tolerance.abs));
+            sig.insert("rel_tol".to_string(), toml::Value::Float(s.tolerance.rel));
+            sig.insert("max_abs".to_string(), toml::Value::Float(s.max_abs));
+            sig.insert("max_rel".to_string(), toml::Value::Float(s.max_rel));
+            if let Some(d) = &s.first_divergence {
+                let mut first = toml::Table::new();
+                first.insert("time".to_string(), toml::Value::Float(d.time));
+                first.insert("baseline".to_string(), d.baseline.to_toml());
+                first.insert("candidate".to_string(), d.candidate.to_toml());
+                sig.insert("first_divergence".to_string(), toml::Value::Table(first));
+            }
+            signals.push(toml::Value::Table(sig));
+        }
+        tbl.insert("signals".to_string(), toml::Value::Array(signals));
+        tbl.to_string()
+    }
+
+    /// Human readable summary, one line per finding
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        for s in self.missing.iter() {
+            lines.push(format!("missing {}", s));
+        }
+        for s in self.added.iter() {
+            lines.push(format!("added {}", s));
+        }
+        if self.unmatched > 0 {
+            lines.push(format!("{} baseline times have no candidate sample", self.unmatched));
+        }
+        for s in self.signals.iter() {
+            if let Some(d) = &s.first_divergence {
+                lines.push(format!("{}: {} samples differ, first at t={} baseline {} candidate {}, max abs {:e} rel {:e}",
+                    s.name, s.failures, d.time, d.baseline, d.candidate, s.max_abs, s.max_rel));
+            }
+        }
+        if let Some((time, signal)) = &self.first_divergence {
+            lines.push(format!("first divergence at t={} on {}", time, signal));
+        }
+        lines.push(match self.passed() {
+            true => format!("PASS {} signals over {} samples", self.signals.len(), self.compared),
+            false => format!("FAIL {} vs {}", self.baseline.display(), self.candidate.display()),
+        });
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the text to a file of the temporary directory
+    fn temp_file(name : &str, txt : &str) -> PathBuf {
+        let file = std::env::temp_dir().join(format!("rsis_compare_{}_{}", std::process::id(), name));
+        fs::write(&file, txt).unwrap();
+        file
+    }
+
+    fn table(name : &str, csv : &str) -> SignalTable {
+        SignalTable::read(&temp_file(name, csv)).unwrap()
+    }
+
+    fn abs(abs : f64) -> Tolerance {
+        Tolerance { abs, rel : 0.0 }
+    }
+
+    #[test]
+    fn tolerances() {
+        let tol = Tolerance { abs : 1e-3, rel : 1e-2 };
+        assert!(tol.matches(100.0, 101.0));
+        assert!(!tol.matches(100.0, 101.1));
+        assert!(tol.matches(0.0, 1e-3));
+        assert!(!tol.matches(0.0, 2e-3));
+        assert!(Tolerance::default().matches(f64::INFINITY, f64::INFINITY));
+        assert!(Tolerance::default().matches(f64::NAN, f64::NAN));
+        assert!(!tol.matches(1.0, f64::NAN));
+    }
+
+    #[test]
+    fn signal_tolerances_apply_to_their_elements() {
+        let cmp = Comparison { default : abs(1e-3), signals : vec![("imu.rate".to_string(), abs(1e-6))], ..Default::default() };
+        assert_eq!(cmp.tolerance("imu.rate"), abs(1e-6));
+        assert_eq!(cmp.tolerance("imu.rate[2]"), abs(1e-6));
+        assert_eq!(cmp.tolerance("imu.rates"), abs(1e-3));
+
+        let baseline = table("tol_base.csv", "time,gen1.output,imu.rate[0],mode\n0,1.0,0.1,on\n0.1,2.0,0.1,on\n");
+        let candidate = table("tol_cand.csv", "time,gen1.output,imu.rate[0],mode\n0,1.0005,0.1001,on\n0.1,2.0,0.1,off\n");
+        let report = cmp.compare(&baseline, &candidate);
+        assert_eq!(report.compared, 2);
+        let failures : Vec<usize> = report.signals.iter().map(|s| s.failures).collect();
+        assert_eq!(failures, vec![0, 1, 1]);
+        assert!((report.signals[0].max_abs - 5e-4).abs() < 1e-12);
+        assert_eq!(report.first_divergence, Some((0.0, "imu.rate[0]".to_string())));
+        assert_eq!(report.signals[2].first_divergence.as_ref().unwrap().candidate, Cell::TEXT("off".to_string()));
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn alignment_of_the_candidate_samples() {
+        let baseline = table("align_base.csv", "time,x\n0,0\n0.1,1\n0.2,2\n");
+        let candidate = table("align_cand.csv", "time,x,extra\n0,0,0\n0.2,2,0\n");
+        let exact = Comparison::default().compare(&baseline, &candidate);
+        assert_eq!((exact.compared, exact.unmatched), (2, 1));
+        assert_eq!(exact.added, vec!["extra".to_string()]);
+        assert!(!exact.passed());
+        let interpolated = Comparison { align : Align::INTERPOLATE, default : abs(1e-9), ..Default::default() }.compare(&baseline, &candidate);
+        assert_eq!((interpolated.compared, interpolated.unmatched), (3, 0));
+        assert!(interpolated.passed());
+    }
+
+    #[test]
+    fn settings_file() {
+        let file = temp_file("settings.toml", "[signals]\n\"gen2.output\" = {abs = 1e-6}\n[default]\nabs = 1e-9\nrel = 1e-3\n");
+        let cmp = Comparison::load(&file, Comparison::default()).unwrap();
+        assert_eq!(cmp.default, Tolerance { abs : 1e-9, rel : 1e-3 });
+        // signal tolerances start from the default table, whatever the order of the keys
+        assert_eq!(cmp.tolerance("gen2.output"), Tolerance { abs : 1e-6, rel : 1e-3 });
+        let file = temp_file("invalid.toml", "[default]\nabs = -1.0\n");
+        assert!(Comparison::load(&file, Comparison::default()).is_err());
+    }
+
+    #[test]
+    fn csv_logs() {
+        let log = table("quoted.csv", "time,\"a,b\",c\n0,1,\"say \"\"hi\"\"\"\n");
+        assert_eq!(log.names, vec!["a,b".to_string(), "c".to_string()]);
+        assert_eq!(log.columns[1][0], Cell::TEXT("say \"hi\"".to_string()));
+        assert!(SignalTable::read(&temp_file("no_time.csv", "t,x\n0,1\n")).is_err());
+        assert!(SignalTable::read(&temp_file("columns.csv", "time,x\n0,1,2\n")).is_err());
+        assert!(SignalTable::read(&temp_file("backwards.csv", "time,x\n1,1\n0,1\n")).is_err());
+    }
+}
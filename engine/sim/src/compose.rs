@@ -0,0 +1,405 @@
+extern crate toml;
+
+use crate::scene::{scene_from_table, Scene};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Top level keys of a scene file, which `--set` overrides address instead of signals
+pub const SCENE_KEYS : [&str; 12] = ["scene", "threads", "schedule", "parameters", "connections", "faults",
+    "timeline", "logging", "monitors", "hashing", "tracing", "recorder"];
+
+/// Origin of the values set on the command line
+pub const COMMAND_LINE : &str = "--set";
+
+// arrays of tables, e.g. [[schedule]], as opposed to arrays of values
+fn is_table_array(val : &toml::Value) -> bool {
+    match val {
+        toml::Value::Array(arr) => !arr.is_empty() && arr.iter().all(|v| v.is_table()),
+        _ => false,
+    }
+}
+
+// what identifies an entry of an array of tables between files: its name, or its file
+fn identity(entry : &toml::Value) -> Option<(&'static str, String)> {
+    for key in ["name", "file"] {
+        if let Some(s) = entry.get(key).and_then(|v| v.as_str()) {
+            return Some((key, s.to_string()));
+        }
+    }
+    None
+}
+
+// a key of a dotted path, quoted unless it is a bare TOML key
+fn quote(key : &str) -> String {
+    match !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        true => key.to_string(),
+        false => toml::Value::String(key.to_string()).to_string(),
+    }
+}
+
+fn join(prefix : &str, key : &str) -> String {
+    match prefix.is_empty() {
+        true => quote(key),
+        false => format!("{}.{}", prefix, quote(key)),
+    }
+}
+
+/// Split a dotted key into its parts, e.g. `connections."gen1:gen2"` into `connections` and `gen1:gen2`
+pub fn split_key(key : &str) -> Result<Vec<String>, String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    for c in key.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(std::mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    parts.push(part);
+    if quoted || parts.iter().any(|p| p.is_empty()) {
+        return Err(format!("{} is not a dotted key", key));
+    }
+    Ok(parts)
+}
+
+/// Composition
+/// Scene file resolved from the fragments it includes, the overlays applied on top of it
+/// and command line overrides, with the file every value came from
+#[derive(Clone, Debug)]
+pub struct Composition {
+    pub file : PathBuf,
+    /// merged scene tables
+    pub table : toml::Table,
+    /// origin of every value, by dotted key with array indices, e.g. `schedule[1].freq`
+    pub origins : HashMap<String, String>,
+}
+
+impl Composition {
+    /// Load a scene file with the fragments it includes
+    pub fn load(file : &Path) -> Result<Composition, String> {
+        let mut c = Composition { file : file.to_path_buf(), table : toml::Table::new(), origins : HashMap::new() };
+        c.apply(file, &mut Vec::new())?;
+        Ok(c)
+    }
+
+    /// Apply an overlay file, with the fragments it includes, on top of the scene
+    pub fn overlay(&mut self, file : &Path) -> Result<(), String> {
+        self.apply(file, &mut Vec::new())
+    }
+
+    // merges the includes of a file, then the file itself
+    fn apply(&mut self, file : &Path, stack : &mut Vec<PathBuf>) -> Result<(), String> {
+        if stack.iter().any(|f| f == file) {
+            return Err(format!("{} includes itself through {}", file.display(),
+                stack.iter().map(|f| f.display().to_string()).collect::<Vec<String>>().join(" -> ")));
+        }
+        let contents = match fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Unable to read scene {}: {}", file.display(), e)),
+        };
+        let mut data = match contents.parse::<toml::Table>() {
+            Ok(d) => d,
+            Err(e) => return Err(format!("Failed to parse scene {}: {}", file.display(), e)),
+        };
+        let dir = file.parent().map_or(PathBuf::new(), |d| d.to_path_buf());
+
+        let includes = match data.remove("include") {
+            None => Vec::new(),
+            Some(toml::Value::String(s)) => vec![s],
+            Some(toml::Value::Array(arr)) => {
+                let mut out = Vec::new();
+                for v in arr {
+                    match v {
+                        toml::Value::String(s) => out.push(s),
+                        _ => return Err(format!("{}: include contains a value that is not a string", file.display())),
+                    }
+                }
+                out
+            },
+            Some(_) => return Err(format!("{}: include is not a file or an array of files", file.display())),
+        };
+        stack.push(file.to_path_buf());
+        for inc in includes {
+            self.apply(&dir.join(inc), stack)?;
+        }
+        stack.pop();
+
+        // model search paths and parameter files are relative to the file that lists them
+        let relative = |val : &mut toml::Value| match val {
+            toml::Value::String(s) => *s = dir.join(&*s).display().to_string(),
+            toml::Value::Array(arr) => arr.iter_mut().filter_map(|v| match v {
+                toml::Value::String(s) => Some(s),
+                _ => None,
+            }).for_each(|s| *s = dir.join(&*s).display().to_string()),
+            _ => {},
+        };
+        if let Some(paths) = data.get_mut("scene").and_then(|s| s.get_mut("paths")) {
+            relative(paths);
+        }
+        for (key, files) in [("schedule", "params"), ("parameters", "file")] {
+            if let Some(toml::Value::Array(entries)) = data.get_mut(key) {
+                entries.iter_mut().filter_map(|e| e.get_mut(files)).for_each(relative);
+            }
+        }
+        let origin = file.display().to_string();
+        merge(&mut self.table, data, "", &origin, &mut self.origins);
+        Ok(())
+    }
+
+    /// Override a value, e.g. `scene.stop` or `schedule.gen1.freq`. Entries of arrays of
+    /// tables are selected by their name, their file or their index, e.g. `logging.0.rate`
+    pub fn set(&mut self, key : &str, val : toml::Value) -> Result<(), String> {
+        let parts = split_key(key)?;
+        match set_value(&mut self.table, &parts, "", val, &mut self.origins) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("--set {}: {}", key, e)),
+        }
+    }
+
+    /// Scene of the merged tables, checked as a scene file is
+    pub fn scene(&self) -> Result<Scene, String> {
+        // relative model search paths were resolved when they were merged
+        scene_from_table(&self.table, Path::new(""))?.checked()
+    }
+
+    /// The merged scene as TOML, every value followed by the file it came from
+    pub fn resolved(&self) -> String {
+        let mut out = Vec::new();
+        write_table(&self.table, "", "", &self.origins, &mut out);
+        out.join("\n") + "\n"
+    }
+}
+
+// sets the value at the parts of a key, below the table at the path
+fn set_value(tbl : &mut toml::Table, parts : &[String], path : &str, val : toml::Value, origins : &mut HashMap<String, String>) -> Result<(), String> {
+    match parts {
+        [] => Ok(()),
+        [last] => {
+            let mut over = toml::Table::new();
+            over.insert(last.clone(), val);
+            merge(tbl, over, path, COMMAND_LINE, origins);
+            Ok(())
+        },
+        [first, rest @ ..] => {
+            let here = join(path, first);
+            match tbl.entry(first.clone()).or_insert_with(|| toml::Value::Table(toml::Table::new())) {
+                toml::Value::Table(t) => set_value(t, rest, &here, val, origins),
+                toml::Value::Array(arr) if arr.iter().all(|e| e.is_table()) => {
+                    let sel = &rest[0];
+                    let index = match sel.parse::<usize>() {
+                        Ok(n) if n < arr.len() => n,
+                        Ok(n) => return Err(format!("{} has {} entries, not {}", here, arr.len(), n + 1)),
+                        Err(_) => match arr.iter().position(|e| identity(e).is_some_and(|(_, id)| id == *sel)) {
+                            Some(n) => n,
+                            None => return Err(format!("{} has no entry [{}]", here, sel)),
+                        },
+                    };
+                    if rest.len() == 1 {
+                        return Err(format!("an entry of {} cannot be replaced, set its keys", here));
+                    }
+                    match arr[index].as_table_mut() {
+                        Some(t) => set_value(t, &rest[1..], &format!("{}[{}]", here, index), val, origins),
+                        None => Err(format!("{}[{}] is not a table", here, index)),
+                    }
+                },
+                _ => Err(format!("{} is not a table", here)),
+            }
+        },
+    }
+}
+
+// records the origin of every value of a merged value
+fn record(val : &toml::Value, path : &str, origin : &str, origins : &mut HashMap<String, String>) {
+    match val {
+        toml::Value::Table(t) => {
+            for (k, v) in t.iter() {
+                record(v, &join(path, k), origin, origins);
+            }
+        },
+        v if is_table_array(v) => {
+            for (i, e) in v.as_array().unwrap_or(&Vec::new()).iter().enumerate() {
+                record(e, &format!("{}[{}]", path, i), origin, origins);
+            }
+        },
+        _ => {
+            origins.insert(path.to_string(), origin.to_string());
+        },
+    }
+}
+
+// forgets the origins of a value that is replaced
+fn forget(path : &str, origins : &mut HashMap<String, String>) {
+    let (dot, bracket) = (format!("{}.", path), format!("{}[", path));
+    origins.retain(|k, _| k != path && !k.starts_with(&dot) && !k.starts_with(&bracket));
+}
+
+/// Merge tables: tables are merged key by key, entries of arrays of tables are merged into
+/// the entry with the same name, or file, and appended otherwise. Other values are replaced
+pub fn merge(base : &mut toml::Table, over : toml::Table, prefix : &str, origin : &str, origins : &mut HashMap<String, String>) {
+    for (k, v) in over {
+        let path = join(prefix, &k);
+        let merged = match (base.get_mut(&k), v) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => {
+                merge(b, o, &path, origin, origins);
+                None
+            },
+            (Some(toml::Value::Array(b)), toml::Value::Array(o))
+                if is_table_array(&toml::Value::Array(b.clone())) && o.iter().all(|e| e.is_table()) => {
+                for entry in o {
+                    let found = identity(&entry).and_then(|id| b.iter().position(|e| identity(e).as_ref() == Some(&id)));
+                    match (found, entry) {
+                        (Some(i), toml::Value::Table(t)) => {
+                            if let Some(existing) = b[i].as_table_mut() {
+                                merge(existing, t, &format!("{}[{}]", path, i), origin, origins);
+                            }
+                        },
+                        (_, entry) => {
+                            record(&entry, &format!("{}[{}]", path, b.len()), origin, origins);
+                            b.push(entry);
+                        },
+                    }
+                }
+                None
+            },
+            (_, v) => Some(v),
+        };
+        if let Some(v) = merged {
+            forget(&path, origins);
+            record(&v, &path, origin, origins);
+            base.insert(k, v);
+        }
+    }
+}
+
+// writes the values of a table, then its tables and arrays of tables
+fn write_table(tbl : &toml::Table, header : &str, path : &str, origins : &HashMap<String, String>, out : &mut Vec<String>) {
+    for (k, v) in tbl.iter().filter(|(_, v)| !v.is_table() && !is_table_array(v)) {
+        let key = join(path, k);
+        let origin = origins.get(&key).map(|o| o.as_str()).unwrap_or("?");
+        out.push(format!("{} = {}  # {}", quote(k), v, origin));
+    }
+    for (k, v) in tbl.iter() {
+        match v {
+            toml::Value::Table(t) => {
+                let h = join(header, k);
+                // tables with only tables need no header of their own
+                if t.values().any(|x| !x.is_table() && !is_table_array(x)) {
+                    out.push(format!("\n[{}]", h));
+                }
+                write_table(t, &h, &join(path, k), origins, out);
+            },
+            v if is_table_array(v) => {
+                for (i, e) in v.as_array().unwrap_or(&Vec::new()).iter().enumerate() {
+                    let h = join(header, k);
+                    out.push(format!("\n[[{}]]", h));
+                    if let Some(t) = e.as_table() {
+                        write_table(t, &h, &format!("{}[{}]", join(path, k), i), origins, out);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the files to a directory of the temporary directory
+    fn files(name : &str, contents : &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rsis_compose_{}_{}", std::process::id(), name));
+        fs::create_dir_all(dir.join("parts")).unwrap();
+        for (file, txt) in contents {
+            fs::write(dir.join(file), txt).unwrap();
+        }
+        dir
+    }
+
+    fn origin(c : &Composition, key : &str) -> String {
+        let o = &c.origins[key];
+        Path::new(o).file_name().map_or(o.clone(), |f| f.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn keys_split_outside_quotes() {
+        assert_eq!(split_key("connections.\"gen1:gen2\"").unwrap(), vec!["connections", "gen1:gen2"]);
+        assert_eq!(split_key("schedule.gen1.freq").unwrap(), vec!["schedule", "gen1", "freq"]);
+        assert!(split_key("scene..stop").is_err());
+        assert!(split_key("scene.\"stop").is_err());
+    }
+
+    #[test]
+    fn entries_merge_by_name_or_file() {
+        let mut origins = HashMap::new();
+        let mut base = toml::Table::new();
+        let first : toml::Table = "[scene]\nname = \"a\"\nstop = 1\n[[schedule]]\nname = \"gen1\"\nfreq = 10\n[[logging]]\nfile = \"x.csv\"\nrate = 1".parse().unwrap();
+        merge(&mut base, first, "", "base", &mut origins);
+        let over : toml::Table = "[scene]\nstop = 2\n[[schedule]]\nname = \"gen1\"\nfreq = 20\n[[schedule]]\nname = \"gen2\"\n[[logging]]\nfile = \"x.csv\"\nrate = 5".parse().unwrap();
+        merge(&mut base, over, "", "over", &mut origins);
+        assert_eq!(base["scene"]["name"].as_str(), Some("a"));
+        assert_eq!(base["scene"]["stop"].as_integer(), Some(2));
+        let schedule = base["schedule"].as_array().unwrap();
+        assert_eq!(schedule.len(), 2);
+        assert_eq!(schedule[0]["freq"].as_integer(), Some(20));
+        assert_eq!(base["logging"].as_array().unwrap().len(), 1);
+        assert_eq!(origins["scene.name"], "base");
+        assert_eq!(origins["scene.stop"], "over");
+        assert_eq!(origins["schedule[1].name"], "over");
+        assert_eq!(origins["logging[0].rate"], "over");
+    }
+
+    #[test]
+    fn includes_merge_before_the_file_relative_to_it() {
+        let dir = files("includes", &[
+            ("scene.toml", "include = [\"parts/models.toml\"]\n[scene]\nname = \"s\"\nstop = 2\n[[schedule]]\nname = \"gen1\"\nfreq = 20.0"),
+            ("parts/models.toml", "include = \"base.toml\"\n[[schedule]]\nname = \"gen1\"\nlib = \"sine\"\nfreq = 10.0\nparams = [\"gen1.toml\"]"),
+            ("parts/base.toml", "[scene]\nstop = 1\npaths = [\"lib\"]\nengine = \"sim\""),
+            ("overlay.toml", "[scene]\nstop = 3"),
+        ]);
+        let mut c = Composition::load(&dir.join("scene.toml")).unwrap();
+        assert_eq!(c.table["scene"]["stop"].as_integer(), Some(2));
+        assert_eq!(c.table["scene"]["paths"][0].as_str(), Some(dir.join("parts").join("lib").display().to_string().as_str()));
+        assert_eq!(c.table["schedule"][0]["params"][0].as_str(), Some(dir.join("parts").join("gen1.toml").display().to_string().as_str()));
+        assert_eq!(c.table["schedule"][0]["freq"].as_float(), Some(20.0));
+        assert_eq!(origin(&c, "scene.engine"), "base.toml");
+        assert_eq!(origin(&c, "schedule[0].lib"), "models.toml");
+        assert_eq!(origin(&c, "schedule[0].freq"), "scene.toml");
+
+        c.overlay(&dir.join("overlay.toml")).unwrap();
+        assert_eq!(c.table["scene"]["stop"].as_integer(), Some(3));
+        assert_eq!(origin(&c, "scene.stop"), "overlay.toml");
+        assert!(c.resolved().contains("stop = 3  # "));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let dir = files("cycle", &[("a.toml", "include = \"b.toml\""), ("b.toml", "include = [\"a.toml\"]")]);
+        let e = Composition::load(&dir.join("a.toml")).unwrap_err();
+        assert!(e.contains("includes itself"), "{}", e);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn overrides_select_entries_by_name_or_index() {
+        let dir = files("set", &[("scene.toml", "[scene]\nstop = 1\n[[schedule]]\nname = \"gen1\"\nfreq = 10.0\n[[logging]]\nrate = 1")]);
+        let mut c = Composition::load(&dir.join("scene.toml")).unwrap();
+        c.set("schedule.gen1.freq", toml::Value::Float(5.0)).unwrap();
+        c.set("logging.0.rate", toml::Value::Integer(2)).unwrap();
+        c.set("connections.\"gen1:gen2\"", toml::Value::Array(vec![])).unwrap();
+        assert_eq!(c.table["schedule"][0]["freq"].as_float(), Some(5.0));
+        assert_eq!(c.table["logging"][0]["rate"].as_integer(), Some(2));
+        assert!(c.table["connections"].get("gen1:gen2").is_some());
+        assert_eq!(c.origins["schedule[0].freq"], COMMAND_LINE);
+        assert_eq!(c.origins["connections.\"gen1:gen2\""], COMMAND_LINE);
+        assert!(c.set("schedule.gen2.freq", toml::Value::Float(1.0)).is_err());
+        assert!(c.set("logging.1.rate", toml::Value::Float(1.0)).is_err());
+        assert!(c.set("schedule.gen1", toml::Value::Float(1.0)).is_err());
+        assert!(c.set("scene.stop.value", toml::Value::Float(1.0)).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,810 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::metadata::{FieldInfo, ModelMetadata};
+use crate::TIME_EPS;
+use rmodel::units::Unit;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// RateTransition
+/// How values cross between a producer and a consumer stepping at different rates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateTransition {
+    /// most recent value of the producer
+    LATEST,
+    /// value sampled at a step of the consumer, held until its next step
+    HOLD,
+    /// mean of the values produced over one step of the consumer
+    AVERAGE,
+    /// every value produced over one step of the consumer, as an array
+    QUEUE,
+}
+
+impl RateTransition {
+    pub fn parse(txt : &str) -> Result<RateTransition, String> {
+        match txt {
+            "latest" => Ok(RateTransition::LATEST),
+            "hold" => Ok(RateTransition::HOLD),
+            "average" => Ok(RateTransition::AVERAGE),
+            "queue" => Ok(RateTransition::QUEUE),
+            _ => Err(format!("Unknown rate transition [{}], expected latest, hold, average or queue", txt)),
+        }
+    }
+}
+
+/// Connection
+/// Routes a field of one model instance to a field of another,
+/// declared in the scene [connections] table as `"src:dst" = ["srcfield dstfield"]`
+/// or `"src:dst" = [{fields = "srcfield dstfield", mode = "hold", delay = 1, scale = -1.0}]`
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub src_model : String,
+    pub src_path : String,
+    pub dst_model : String,
+    pub dst_path : String,
+    pub mode : RateTransition,
+    /// consumer steps between production and consumption, None for the default of one.
+    /// Zero is only allowed within a thread
+    pub delay : Option<u32>,
+    /// convert from the unit of the source field to the unit of the destination field,
+    /// None to convert whenever both fields have a unit
+    pub convert : Option<bool>,
+    /// applied after the unit conversion
+    pub scale : f64,
+    pub offset : f64,
+}
+
+impl Connection {
+    /// Parse an entry of the [connections] table
+    /// @param[in] models - key of the entry, `src:dst`
+    /// @param[in] entry - value of the entry, `srcfield dstfield` or an inline table
+    pub fn parse(models : &str, entry : &toml::Value) -> Result<Connection, String> {
+        let (src, dst) = match models.split_once(':') {
+            Some((s, d)) if !s.is_empty() && !d.is_empty() => (s.trim(), d.trim()),
+            _ => return Err(format!("[connections] key \"{}\" is not of the form \"src:dst\"", models)),
+        };
+        let mut conn = Connection {
+            src_model : src.to_string(),
+            src_path : "".to_string(),
+            dst_model : dst.to_string(),
+            dst_path : "".to_string(),
+            mode : RateTransition::LATEST,
+            delay : None,
+            convert : None,
+            scale : 1.0,
+            offset : 0.0,
+        };
+        let number = |tbl : &toml::Table, key : &str| -> Result<Option<f64>, String> {
+            match tbl.get(key) {
+                Some(toml::Value::Float(f)) => Ok(Some(*f)),
+                Some(toml::Value::Integer(i)) => Ok(Some(*i as f64)),
+                Some(_) => Err(format!("[connections].\"{}\" {} is not a number", models, key)),
+                None => Ok(None),
+            }
+        };
+        let fields = match entry {
+            toml::Value::String(txt) => txt.as_str(),
+            toml::Value::Table(tbl) => {
+                let fields = match tbl.get("fields").and_then(|v| v.as_str()) {
+                    Some(f) => f,
+                    None => return Err(format!("[connections].\"{}\" entry is missing the fields string", models)),
+                };
+                match tbl.get("mode") {
+                    Some(toml::Value::String(m)) => conn.mode = RateTransition::parse(m)?,
+                    Some(_) => return Err(format!("[connections].\"{}\" mode is not a string", models)),
+                    None => {},
+                }
+                match tbl.get("delay") {
+                    Some(toml::Value::Integer(d)) if *d >= 0 => conn.delay = Some(*d as u32),
+                    Some(_) => return Err(format!("[connections].\"{}\" delay is not a non-negative integer", models)),
+                    None => {},
+                }
+                match tbl.get("convert") {
+                    Some(toml::Value::Boolean(b)) => conn.convert = Some(*b),
+                    Some(_) => return Err(format!("[connections].\"{}\" convert is not a boolean", models)),
+                    None => {},
+                }
+                conn.scale = number(tbl, "scale")?.unwrap_or(1.0);
+                conn.offset = number(tbl, "offset")?.unwrap_or(0.0);
+                fields
+            },
+            _ => return Err(format!("[connections].\"{}\" contains a value that is not a string or table", models)),
+        };
+        let toks : Vec<&str> = fields.split_whitespace().collect();
+        if toks.len() != 2 {
+            return Err(format!("[connections].\"{}\" entry \"{}\" is not of the form \"srcfield dstfield\"", models, fields));
+        }
+        conn.src_path = toks[0].to_string();
+        conn.dst_path = toks[1].to_string();
+        Ok(conn)
+    }
+
+    /// Resolve the connection into pairs of leaf selections. A path naming a
+    /// struct connects each of its leaves to the leaf at the same relative path
+    /// of the destination. Types and dims are checked against the interfaces
+    pub fn resolve(&self, src : &ModelMetadata, dst : &ModelMetadata) -> Result<Vec<(Selection, Selection)>, String> {
+        let (src_path, src_slices) = parse_slices(&self.src_path)?;
+        let (dst_path, dst_slices) = parse_slices(&self.dst_path)?;
+
+        let mut pairs = Vec::new();
+        match src.find(src_path) {
+            Ok(f) => {
+                let d = match dst.find(dst_path) {
+                    Ok(d) => d,
+                    Err(_) if !dst.children(dst_path).is_empty() => {
+                        return Err(format!("{} -> {}: a field cannot be connected to a struct", self.src(), self.dst()));
+                    },
+                    Err(e) => return Err(e),
+                };
+                let sel = Selection::new(f, &src_slices).and_then(|s| Ok((s, Selection::new(d, &dst_slices)?)));
+                match sel {
+                    Ok(pair) => pairs.push(pair),
+                    Err(e) => return Err(format!("{} -> {}: {}", self.src(), self.dst(), e)),
+                }
+            },
+            Err(e) => {
+                let children = src.children(src_path);
+                if children.is_empty() {
+                    return Err(e);
+                }
+                if !src_slices.is_empty() || !dst_slices.is_empty() {
+                    return Err(format!("{} -> {}: structs cannot be sliced", self.src(), self.dst()));
+                }
+                for f in children {
+                    let path = format!("{}{}", dst_path, &f.path[src_path.len()..]);
+                    let d = match dst.find(&path) {
+                        Ok(d) => d,
+                        Err(_) => return Err(format!("{} -> {}: [{}] has no matching field [{}]",
+                            self.src(), self.dst(), f.path, path)),
+                    };
+                    pairs.push((Selection::new(f, &[])?, Selection::new(d, &[])?));
+                }
+            },
+        }
+
+        for (s, d) in pairs.iter() {
+            if s.field.typename != d.field.typename {
+                return Err(format!("{} -> {}: [{}] is {}, [{}] is {}",
+                    self.src(), self.dst(), s.field.path, s.field.typename, d.field.path, d.field.typename));
+            }
+            // a queue delivers an array of samples, its shape is checked against the rates
+            if self.mode != RateTransition::QUEUE && s.shape() != d.shape() {
+                return Err(format!("{} -> {}: [{}] selects dims {:?}, [{}] selects dims {:?}",
+                    self.src(), self.dst(), s.field.path, s.dims, d.field.path, d.dims));
+            }
+        }
+        Ok(pairs)
+    }
+
+    pub fn src(&self) -> String {
+        format!("{}.{}", self.src_model, self.src_path)
+    }
+
+    pub fn dst(&self) -> String {
+        format!("{}.{}", self.dst_model, self.dst_path)
+    }
+}
+
+/// Index or range of one dimension in a connection path, e.g. `pos[2]` or `q[0:3]`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slice {
+    INDEX(i64),
+    /// start and end, exclusive. open ends default to the bounds of the dimension
+    RANGE(Option<i64>, Option<i64>),
+}
+
+/// Split a connection path into the field path and the slices of its trailing `[...]`
+pub fn parse_slices(txt : &str) -> Result<(&str, Vec<Slice>), String> {
+    let (path, sel) = match txt.find('[') {
+        Some(i) if txt.ends_with(']') => (&txt[..i], &txt[i + 1..txt.len() - 1]),
+        Some(_) => return Err(format!("Connection path [{}] has an unterminated slice", txt)),
+        None => return Ok((txt, Vec::new())),
+    };
+    let bound = |b : &str| -> Result<Option<i64>, String> {
+        match b.trim() {
+            "" => Ok(None),
+            t => match t.parse::<i64>() {
+                Ok(v) if v >= 0 => Ok(Some(v)),
+                _ => Err(format!("Connection path [{}] has an invalid index [{}]", txt, t)),
+            },
+        }
+    };
+    let mut slices = Vec::new();
+    for part in sel.split(',') {
+        match part.split_once(':') {
+            Some((a, b)) => slices.push(Slice::RANGE(bound(a)?, bound(b)?)),
+            None => match bound(part)? {
+                Some(i) => slices.push(Slice::INDEX(i)),
+                None => return Err(format!("Connection path [{}] has an empty index", txt)),
+            },
+        }
+    }
+    Ok((path, slices))
+}
+
+/// Selection
+/// A leaf field, or elements of a leaf field, at one end of a connection
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub field : FieldInfo,
+    /// flat column-major element indices, None for the whole field
+    pub elements : Option<Vec<usize>>,
+    /// dims of the selected value, empty for a scalar
+    pub dims : Vec<i64>,
+}
+
+impl Selection {
+    /// Select the elements of an array field
+    pub fn new(field : &FieldInfo, slices : &[Slice]) -> Result<Selection, String> {
+        if slices.is_empty() {
+            return Ok(Selection { field : field.clone(), elements : None, dims : field.dims.clone() });
+        }
+        if field.dims.is_empty() {
+            return Err(format!("Field [{}] is not an array and cannot be sliced", field.path));
+        }
+        // trailing dimensions of length one may be omitted, `pos[2]` for a 3x1 vector
+        let mut slices = slices.to_vec();
+        while slices.len() < field.dims.len() && field.dims[slices.len()] == 1 {
+            slices.push(Slice::INDEX(0));
+        }
+        if slices.len() != field.dims.len() {
+            return Err(format!("Field [{}] has dims {:?}, selected with {} indices", field.path, field.dims, slices.len()));
+        }
+
+        let mut indices : Vec<Vec<i64>> = Vec::new();
+        let mut dims = Vec::new();
+        for (s, d) in slices.iter().zip(field.dims.iter()) {
+            // variable length arrays are only bounds checked when the value is routed
+            let (a, b) = match s {
+                Slice::INDEX(i) => (*i, *i + 1),
+                Slice::RANGE(a, b) => {
+                    let end = match (b, *d) {
+                        (Some(b), _) => *b,
+                        (None, -1) => return Err(format!("Field [{}] has variable length, ranges need an end", field.path)),
+                        (None, d) => d,
+                    };
+                    (a.unwrap_or(0), end)
+                },
+            };
+            if a >= b || (*d != -1 && b > *d) {
+                return Err(format!("Selection {:?} is out of bounds of field [{}] with dims {:?}", s, field.path, field.dims));
+            }
+            if let Slice::RANGE(..) = s {
+                dims.push(b - a);
+            }
+            indices.push((a..b).collect());
+        }
+
+        // column major, the first index varies fastest
+        let mut elements = vec![0usize];
+        let mut stride = 1usize;
+        for (ind, d) in indices.iter().zip(field.dims.iter()) {
+            elements = ind.iter().flat_map(|i| elements.iter().map(move |e| e + *i as usize * stride)).collect();
+            stride *= (*d).max(1) as usize;
+        }
+        Ok(Selection { field : field.clone(), elements : Some(elements), dims })
+    }
+
+    /// Dims without the dimensions of length one, for shape comparison
+    pub fn shape(&self) -> Vec<i64> {
+        self.dims.iter().cloned().filter(|d| *d != 1).collect()
+    }
+
+    /// Extract the selected elements from the encoded field
+    pub fn extract(&self, mp : Vec<u8>) -> Result<Vec<u8>, String> {
+        let elements = match &self.elements {
+            Some(e) => e,
+            None => return Ok(mp),
+        };
+        let arr = match decode(&mp)? {
+            rmpv::Value::Array(arr) => arr,
+            v => return Err(format!("Field [{}] is not an array: {}", self.field.path, v)),
+        };
+        let mut out = Vec::new();
+        for e in elements {
+            match arr.get(*e) {
+                Some(v) => out.push(v.clone()),
+                None => return Err(format!("Element {} is out of bounds of field [{}] with {} elements", e, self.field.path, arr.len())),
+            }
+        }
+        match self.shape().is_empty() {
+            true => encode(&out[0]),
+            false => encode(&rmpv::Value::Array(out)),
+        }
+    }
+
+    /// Write the value into the selected elements of the encoded field
+    pub fn insert(&self, field : Vec<u8>, value : &[u8]) -> Result<Vec<u8>, String> {
+        let elements = match &self.elements {
+            Some(e) => e,
+            None => return Ok(value.to_vec()),
+        };
+        let mut arr = match decode(&field)? {
+            rmpv::Value::Array(arr) => arr,
+            v => return Err(format!("Field [{}] is not an array: {}", self.field.path, v)),
+        };
+        let values = match decode(value)? {
+            rmpv::Value::Array(v) => v,
+            v => vec![v],
+        };
+        if values.len() != elements.len() {
+            return Err(format!("Received {} elements for {} selected elements of field [{}]", values.len(), elements.len(), self.field.path));
+        }
+        for (e, v) in elements.iter().zip(values) {
+            match arr.get_mut(*e) {
+                Some(slot) => *slot = v,
+                None => return Err(format!("Element {} is out of bounds of field [{}] with {} elements", e, self.field.path, arr.len())),
+            }
+        }
+        encode(&rmpv::Value::Array(arr))
+    }
+}
+
+/// ConnectionBuffer
+/// Time stamped values exchanged between the producer and the consumer of a
+/// connection. The producer stamps each value with the simulation time it was
+/// produced at, and the consumer reads the latest value produced before a time
+/// limit, so the exchange does not depend on the order in which threads execute
+/// a frame. Only the values the consumer can still ask for are kept: for a delay
+/// of up to one step this is a double buffer of the previous and current value
+pub struct ConnectionBuffer {
+    values : Mutex<VecDeque<(f64, Vec<u8>)>>,
+    /// how far behind the producer the consumer reads, seconds
+    history : f64,
+}
+
+impl ConnectionBuffer {
+    pub fn new(history : f64) -> ConnectionBuffer {
+        ConnectionBuffer {
+            values : Mutex::new(VecDeque::new()),
+            history : history.max(0.0),
+        }
+    }
+
+    /// Store the value produced at the simulation time
+    pub fn publish(&self, time : f64, mp : Vec<u8>) {
+        let mut values = self.values.lock().unwrap();
+        values.push_back((time, mp));
+        // the consumer is never behind the producer's frame, so the oldest
+        // value is dropped once a newer one is also out of its reach
+        while values.len() > 1 && values[1].0 < time - self.history - TIME_EPS {
+            values.pop_front();
+        }
+    }
+
+    /// Latest value produced strictly before the time limit, if any
+    pub fn latest_before(&self, limit : f64) -> Option<Vec<u8>> {
+        let values = self.values.lock().unwrap();
+        values.iter().rev().find(|v| v.0 < limit).map(|v| v.1.clone())
+    }
+
+    /// Clear the buffer, used when the engine is reset
+    pub fn clear(&self) {
+        self.values.lock().unwrap().clear();
+    }
+}
+
+/// Transform
+/// Linear map applied to the values of a connection, `y = x * scale + offset`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub scale : f64,
+    pub offset : f64,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { scale : 1.0, offset : 0.0 }
+    }
+
+    /// Transform of the connection between the fields, the unit conversion followed by the scale and offset
+    pub fn new(c : &Connection, src : &FieldInfo, dst : &FieldInfo) -> Result<Transform, String> {
+        let mut t = Transform::identity();
+        let has_units = !src.unit.is_empty() && !dst.unit.is_empty();
+        if c.convert == Some(true) && !has_units {
+            return Err(format!("{} -> {}: unit conversion requires units on [{}] and [{}]",
+                c.src(), c.dst(), src.path, dst.path));
+        }
+        // units are checked and converted unless explicitly disabled
+        if has_units && c.convert != Some(false) {
+            let (from, to) = (Unit::parse(&src.unit)?, Unit::parse(&dst.unit)?);
+            match from.conversion(&to) {
+                Ok((scale, offset)) => t = Transform { scale, offset },
+                Err(_) => return Err(format!("{} -> {}: unit [{}] of [{}] is not compatible with unit [{}] of [{}], set convert = false to connect them anyway",
+                    c.src(), c.dst(), src.unit, src.path, dst.unit, dst.path)),
+            }
+        }
+        t = Transform { scale : t.scale * c.scale, offset : t.offset * c.scale + c.offset };
+        if !t.is_identity() && !is_numeric(&src.typename) {
+            return Err(format!("{} -> {}: [{}] is {}, only numeric values can be scaled",
+                c.src(), c.dst(), src.path, src.typename));
+        }
+        Ok(t)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.scale == 1.0 && self.offset == 0.0
+    }
+
+    /// Apply to a numeric value or every element of an array, integers are rounded.
+    /// Integers out of the 64 bit range are an error, the field setter rejects values out of its own range
+    pub fn apply(&self, val : &rmpv::Value) -> Result<rmpv::Value, String> {
+        match val {
+            rmpv::Value::F64(x) => Ok(rmpv::Value::F64(x * self.scale + self.offset)),
+            rmpv::Value::F32(x) => Ok(rmpv::Value::F32((*x as f64 * self.scale + self.offset) as f32)),
+            rmpv::Value::Integer(i) => {
+                let x = match (i.as_i64(), i.as_u64()) {
+                    (Some(v), _) => v as f64,
+                    (None, Some(v)) => v as f64,
+                    _ => return Err(format!("Cannot transform {}", val)),
+                };
+                let y = (x * self.scale + self.offset).round();
+                // the casts would saturate, a value outside the 64 bit range is an error
+                if !(y >= i64::MIN as f64 && y < u64::MAX as f64) {
+                    return Err(format!("{} transformed to {} is out of the integer range", val, y));
+                }
+                match y < 0.0 {
+                    true => Ok(rmpv::Value::from(y as i64)),
+                    false => Ok(rmpv::Value::from(y as u64)),
+                }
+            },
+            rmpv::Value::Array(arr) => {
+                let mut out = Vec::new();
+                for v in arr {
+                    out.push(self.apply(v)?);
+                }
+                Ok(rmpv::Value::Array(out))
+            },
+            _ => Err(format!("Cannot transform non-numeric value {}", val)),
+        }
+    }
+}
+
+pub(crate) fn is_numeric(typename : &str) -> bool {
+    matches!(typename, "f32" | "f64" | "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64")
+}
+
+/// Sample grid of a model, the times it steps at
+#[derive(Clone, Copy, Debug)]
+pub struct SampleGrid {
+    /// seconds between steps
+    pub period : f64,
+    /// time of the first step
+    pub phase : f64,
+}
+
+impl SampleGrid {
+    /// True if the model steps at the time
+    pub fn contains(&self, time : f64) -> bool {
+        let n = (time - self.phase) / self.period;
+        n > -TIME_EPS && (n - n.round()).abs() * self.period < TIME_EPS
+    }
+
+    /// True if every step of the other grid is also a step of this grid
+    pub fn divides(&self, other : &SampleGrid) -> bool {
+        let integral = |x : f64| (x - x.round()).abs() < 1e-6;
+        integral(other.period / self.period) && integral((other.phase - self.phase) / self.period)
+    }
+
+    /// First step strictly after the time
+    pub fn next_after(&self, time : f64) -> f64 {
+        if time < self.phase - TIME_EPS {
+            return self.phase;
+        }
+        let n = ((time - self.phase) / self.period + TIME_EPS).floor() + 1.0;
+        self.phase + n * self.period
+    }
+}
+
+/// Producer side of a connection, owned by the ScheduledModel of the source.
+/// Decides what is published to the buffer based on the rate transition
+pub struct OutputRoute {
+    pub name : String,
+    pub source : Selection,
+    pub buffer : Arc<ConnectionBuffer>,
+    pub mode : RateTransition,
+    /// steps of the producer
+    pub producer : SampleGrid,
+    /// steps of the consumer
+    pub consumer : SampleGrid,
+    /// consumed in the frame it is produced
+    pub direct : bool,
+    /// applied to the selected value before the rate transition
+    pub transform : Transform,
+    // values produced since the consumer window opened, average and queue
+    samples : Vec<rmpv::Value>,
+}
+
+impl OutputRoute {
+    pub fn new(name : String, source : Selection, buffer : Arc<ConnectionBuffer>, mode : RateTransition,
+               producer : SampleGrid, consumer : SampleGrid, direct : bool) -> OutputRoute {
+        OutputRoute {
+            name,
+            source,
+            buffer,
+            mode,
+            producer,
+            consumer,
+            direct,
+            transform : Transform::identity(),
+            samples : Vec::new(),
+        }
+    }
+
+    /// Number of producer steps per consumer step
+    pub fn ratio(&self) -> f64 {
+        self.consumer.period / self.producer.period
+    }
+
+    // true if the producer step ends a consumer window: the step at the consumer step
+    // for direct connections, the last step before the next consumer step otherwise
+    fn closes_window(&self, time : f64) -> bool {
+        if self.producer.period > self.consumer.period - TIME_EPS {
+            return true;
+        }
+        match self.direct {
+            true => self.consumer.contains(time),
+            false => time + self.producer.period > self.consumer.next_after(time) - TIME_EPS,
+        }
+    }
+
+    /// Called after each producer step with the value of the source field
+    pub fn publish(&mut self, time : f64, mp : Vec<u8>) -> Result<(), String> {
+        let mut mp = self.source.extract(mp)?;
+        if !self.transform.is_identity() {
+            mp = encode(&self.transform.apply(&decode(&mp)?)?)?;
+        }
+        match self.mode {
+            RateTransition::LATEST => {
+                self.buffer.publish(time, mp);
+            },
+            RateTransition::HOLD => {
+                // a slower producer is held by the buffer until it publishes again
+                if self.producer.period > self.consumer.period - TIME_EPS || self.consumer.contains(time) {
+                    self.buffer.publish(time, mp);
+                }
+            },
+            RateTransition::AVERAGE => {
+                self.samples.push(decode(&mp)?);
+                if self.closes_window(time) {
+                    let avg = average(&self.samples)?;
+                    self.samples.clear();
+                    self.buffer.publish(time, encode(&avg)?);
+                }
+            },
+            RateTransition::QUEUE => {
+                self.samples.push(decode(&mp)?);
+                if self.closes_window(time) {
+                    // partial windows, before the producer has started, are dropped
+                    let full = self.samples.len() as f64 > self.ratio() - 0.5;
+                    let queue = rmpv::Value::Array(self.samples.drain(..).collect());
+                    if full {
+                        self.buffer.publish(time, encode(&queue)?);
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Clear the route and its buffer, used when the engine is reset
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.buffer.clear();
+    }
+}
+
+pub(crate) fn decode(mp : &[u8]) -> Result<rmpv::Value, String> {
+    let mut rd = mp;
+    match rmpv::decode::read_value(&mut rd) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(format!("Failed to decode connection value: {}", e)),
+    }
+}
+
+pub(crate) fn encode(val : &rmpv::Value) -> Result<Vec<u8>, String> {
+    let mut mp = Vec::new();
+    match rmpv::encode::write_value(&mut mp, val) {
+        Ok(_) => Ok(mp),
+        Err(e) => Err(format!("Failed to encode connection value: {}", e)),
+    }
+}
+
+/// Element-wise mean of numeric values or arrays of numeric values.
+/// The result has the type of the first value, integers are rounded
+pub fn average(values : &[rmpv::Value]) -> Result<rmpv::Value, String> {
+    let n = values.len() as f64;
+    let mean = |vals : &[rmpv::Value]| -> Result<f64, String> {
+        let mut sum = 0.0;
+        for v in vals {
+            match v.as_f64().or_else(|| v.as_i64().map(|i| i as f64)).or_else(|| v.as_u64().map(|u| u as f64)) {
+                Some(x) => sum += x,
+                None => return Err(format!("Cannot average {} with a number", v)),
+            }
+        }
+        Ok(sum / n)
+    };
+    match values.first() {
+        Some(rmpv::Value::F64(_)) => Ok(rmpv::Value::F64(mean(values)?)),
+        Some(rmpv::Value::F32(_)) => Ok(rmpv::Value::F32(mean(values)? as f32)),
+        Some(rmpv::Value::Integer(i)) => {
+            let m = mean(values)?.round();
+            match i.is_u64() {
+                true => Ok(rmpv::Value::from(m as u64)),
+                false => Ok(rmpv::Value::from(m as i64)),
+            }
+        },
+        Some(rmpv::Value::Array(first)) => {
+            let mut out = Vec::new();
+            for i in 0..first.len() {
+                let mut column = Vec::new();
+                for v in values {
+                    match v.as_array() {
+                        Some(arr) if arr.len() == first.len() => column.push(arr[i].clone()),
+                        _ => return Err("Cannot average arrays of different shapes".to_string()),
+                    }
+                }
+                out.push(average(&column)?);
+            }
+            Ok(rmpv::Value::Array(out))
+        },
+        Some(v) => Err(format!("Cannot average non-numeric value {}", v)),
+        None => Err("Cannot average zero values".to_string()),
+    }
+}
+
+/// Consumer side of a connection, owned by the ScheduledModel of the destination
+pub struct InputRoute {
+    pub name : String,
+    pub target : Selection,
+    pub buffer : Arc<ConnectionBuffer>,
+    /// consumer steps between production and consumption
+    pub delay : u32,
+    /// seconds between consumer steps
+    pub period : f64,
+}
+
+impl InputRoute {
+    /// Value to apply before the consumer step at the time, if any has been produced
+    pub fn receive(&self, time : f64) -> Option<Vec<u8>> {
+        let limit = match self.delay {
+            0 => time + TIME_EPS,
+            d => time - (d - 1) as f64 * self.period - TIME_EPS,
+        };
+        self.buffer.latest_before(limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(path : &str, dims : Vec<i64>) -> FieldInfo {
+        FieldInfo {
+            path : path.to_string(),
+            index : vec![0],
+            typename : "f64".to_string(),
+            dims,
+            tag : "out".to_string(),
+            unit : String::new(),
+        }
+    }
+
+    // a 10 Hz producer feeding a consumer stepping every 0.3 s, one consumer step behind
+    fn routes(mode : RateTransition) -> (OutputRoute, InputRoute) {
+        let buffer = Arc::new(ConnectionBuffer::new(0.3));
+        let selection = Selection::new(&field("output", Vec::new()), &[]).unwrap();
+        let output = OutputRoute::new("gen1.output".to_string(), selection.clone(), Arc::clone(&buffer), mode,
+            SampleGrid { period : 0.1, phase : 0.0 }, SampleGrid { period : 0.3, phase : 0.0 }, false);
+        let input = InputRoute { name : "gen2.input".to_string(), target : selection, buffer, delay : 1, period : 0.3 };
+        (output, input)
+    }
+
+    // publishes the frame number as the value of each producer step
+    fn produce(output : &mut OutputRoute, frames : std::ops::Range<u32>) {
+        for i in frames {
+            output.publish(i as f64 * 0.1, encode(&rmpv::Value::F64(i as f64)).unwrap()).unwrap();
+        }
+    }
+
+    fn received(input : &InputRoute, time : f64) -> rmpv::Value {
+        decode(&input.receive(time).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hold_samples_at_consumer_steps() {
+        let (mut output, input) = routes(RateTransition::HOLD);
+        produce(&mut output, 0..3);
+        assert_eq!(received(&input, 0.3), rmpv::Value::F64(0.0));
+        produce(&mut output, 3..6);
+        assert_eq!(received(&input, 0.6), rmpv::Value::F64(3.0));
+    }
+
+    #[test]
+    fn average_of_a_consumer_window() {
+        let (mut output, input) = routes(RateTransition::AVERAGE);
+        assert!(input.receive(0.0).is_none());
+        produce(&mut output, 0..3);
+        assert_eq!(received(&input, 0.3), rmpv::Value::F64(1.0));
+        produce(&mut output, 3..6);
+        assert_eq!(received(&input, 0.6), rmpv::Value::F64(4.0));
+    }
+
+    #[test]
+    fn queue_of_a_consumer_window() {
+        let (mut output, input) = routes(RateTransition::QUEUE);
+        produce(&mut output, 0..6);
+        let queue = [3.0, 4.0, 5.0].iter().map(|v| rmpv::Value::F64(*v)).collect();
+        assert_eq!(received(&input, 0.6), rmpv::Value::Array(queue));
+    }
+
+    fn array(values : &[f64]) -> Vec<u8> {
+        encode(&rmpv::Value::Array(values.iter().map(|v| rmpv::Value::F64(*v)).collect())).unwrap()
+    }
+
+    #[test]
+    fn slice_of_a_vector() {
+        let (path, slices) = parse_slices("q[0:3]").unwrap();
+        assert_eq!(path, "q");
+        assert_eq!(slices, vec![Slice::RANGE(Some(0), Some(3))]);
+        let sel = Selection::new(&field("q", vec![4, 1]), &slices).unwrap();
+        assert_eq!(sel.elements, Some(vec![0, 1, 2]));
+        assert_eq!(sel.dims, vec![3]);
+        assert_eq!(sel.extract(array(&[1.0, 2.0, 3.0, 4.0])).unwrap(), array(&[1.0, 2.0, 3.0]));
+        assert_eq!(sel.insert(array(&[0.0; 4]), &array(&[1.0, 2.0, 3.0])).unwrap(), array(&[1.0, 2.0, 3.0, 0.0]));
+    }
+
+    #[test]
+    fn slices_of_a_matrix_are_column_major() {
+        let m = field("m", vec![3, 2]);
+        let (_, row) = parse_slices("m[1, :]").unwrap();
+        let sel = Selection::new(&m, &row).unwrap();
+        assert_eq!(sel.elements, Some(vec![1, 4]));
+        assert_eq!(sel.dims, vec![2]);
+        let (_, column) = parse_slices("m[:,1]").unwrap();
+        assert_eq!(Selection::new(&m, &column).unwrap().elements, Some(vec![3, 4, 5]));
+        let (_, element) = parse_slices("m[2,1]").unwrap();
+        let sel = Selection::new(&m, &element).unwrap();
+        assert_eq!(sel.elements, Some(vec![5]));
+        assert!(sel.shape().is_empty());
+    }
+
+    #[test]
+    fn trailing_unit_dimensions_may_be_omitted() {
+        let (_, slices) = parse_slices("pos[2]").unwrap();
+        let sel = Selection::new(&field("pos", vec![3, 1]), &slices).unwrap();
+        assert_eq!(sel.elements, Some(vec![2]));
+        assert_eq!(sel.extract(array(&[1.0, 2.0, 3.0])).unwrap(), encode(&rmpv::Value::F64(3.0)).unwrap());
+    }
+
+    #[test]
+    fn invalid_slices() {
+        assert!(parse_slices("q[0:3").is_err());
+        assert!(parse_slices("q[]").is_err());
+        let (_, slices) = parse_slices("q[0:5]").unwrap();
+        assert!(Selection::new(&field("q", vec![4, 1]), &slices).is_err());
+        assert!(Selection::new(&field("x", Vec::new()), &slices).is_err());
+    }
+
+    #[test]
+    fn average_rounds_integers() {
+        let values = [1, 2].iter().map(|v| rmpv::Value::from(*v as i64)).collect::<Vec<_>>();
+        assert_eq!(average(&values).unwrap(), rmpv::Value::from(2i64));
+        assert!(average(&[rmpv::Value::from("x")]).is_err());
+    }
+
+    #[test]
+    fn transformed_integers_are_range_checked() {
+        let t = Transform { scale : -1.0, offset : 0.0 };
+        assert_eq!(t.apply(&rmpv::Value::from(3u64)).unwrap(), rmpv::Value::from(-3i64));
+        let t = Transform { scale : 2.0, offset : 0.5 };
+        assert_eq!(t.apply(&rmpv::Value::from(-3i64)).unwrap(), rmpv::Value::from(-6i64));
+        let t = Transform { scale : 1e10, offset : 0.0 };
+        assert!(t.apply(&rmpv::Value::from(u32::MAX as u64)).is_err());
+        assert!(t.apply(&rmpv::Value::from(-(u32::MAX as i64))).is_err());
+        let t = Transform { scale : f64::NAN, offset : 0.0 };
+        assert!(t.apply(&rmpv::Value::from(1u64)).is_err());
+    }
+}
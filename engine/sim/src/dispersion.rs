@@ -0,0 +1,110 @@
+extern crate toml;
+
+use crate::metadata::split_signal;
+use crate::random::Random;
+
+/// Distribution of a dispersed parameter
+#[derive(Clone, Debug)]
+pub enum Distribution {
+    /// min, max
+    UNIFORM(f64, f64),
+    /// mean, standard deviation
+    NORMAL(f64, f64),
+    /// mean, standard deviation, min, max
+    TRUNCATED(f64, f64, f64, f64),
+    /// equally likely values
+    CHOICE(Vec<toml::Value>),
+}
+
+/// Dispersion
+/// Random value of a model signal, drawn for each case of a batch
+#[derive(Clone, Debug)]
+pub struct Dispersion {
+    pub signal : String,
+    pub dist : Distribution,
+}
+
+// draws before giving up on a truncated normal that almost never falls within its bounds
+const MAX_REJECTIONS : usize = 10000;
+
+fn number(tbl : &toml::Table, key : &str, ctxt : &str) -> Result<f64, String> {
+    match tbl.get(key) {
+        Some(toml::Value::Float(f)) => Ok(*f),
+        Some(toml::Value::Integer(i)) => Ok(*i as f64),
+        Some(_) => Err(format!("{}.{} is not a number", ctxt, key)),
+        None => Err(format!("Missing {}.{}", ctxt, key)),
+    }
+}
+
+impl Dispersion {
+    /// Parse an entry of the [dispersions] table, e.g.
+    /// `"gen1.params.amplitude" = {dist = "normal", mean = 1.0, std = 0.1}`
+    pub fn parse(signal : &str, val : &toml::Value) -> Result<Dispersion, String> {
+        split_signal(signal)?;
+        let ctxt = format!("[dispersions].\"{}\"", signal);
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        let allowed : &[&str] = match tbl.get("dist").and_then(|d| d.as_str()) {
+            Some("uniform") => &["dist", "min", "max"],
+            Some("normal") => &["dist", "mean", "std"],
+            Some("truncated_normal") => &["dist", "mean", "std", "min", "max"],
+            Some("choice") => &["dist", "values"],
+            _ => return Err(format!("{}.dist must be uniform, normal, truncated_normal or choice", ctxt)),
+        };
+        for key in tbl.keys() {
+            if !allowed.contains(&key.as_str()) {
+                return Err(format!("{}.{} does not apply to {} dispersions", ctxt, key, tbl["dist"]));
+            }
+        }
+        let dist = match tbl["dist"].as_str() {
+            Some("uniform") => Distribution::UNIFORM(number(tbl, "min", &ctxt)?, number(tbl, "max", &ctxt)?),
+            Some("normal") => Distribution::NORMAL(number(tbl, "mean", &ctxt)?, number(tbl, "std", &ctxt)?),
+            Some("truncated_normal") => Distribution::TRUNCATED(number(tbl, "mean", &ctxt)?, number(tbl, "std", &ctxt)?,
+                number(tbl, "min", &ctxt)?, number(tbl, "max", &ctxt)?),
+            _ => match tbl.get("values").and_then(|v| v.as_array()) {
+                Some(arr) if !arr.is_empty() => Distribution::CHOICE(arr.clone()),
+                _ => return Err(format!("{}.values must be a non-empty array", ctxt)),
+            },
+        };
+        match dist {
+            Distribution::UNIFORM(min, max) | Distribution::TRUNCATED(_, _, min, max) if min > max => {
+                Err(format!("{}.min is greater than its max", ctxt))
+            },
+            Distribution::NORMAL(_, std) | Distribution::TRUNCATED(_, std, _, _) if std < 0.0 => {
+                Err(format!("{}.std is negative", ctxt))
+            },
+            _ => Ok(Dispersion { signal : signal.to_string(), dist }),
+        }
+    }
+
+    /// Draw a value
+    pub fn sample(&self, rng : &mut Random) -> Result<toml::Value, String> {
+        match &self.dist {
+            Distribution::UNIFORM(min, max) => Ok(toml::Value::Float(min + (max - min) * rng.uniform())),
+            Distribution::NORMAL(mean, std) => Ok(toml::Value::Float(mean + std * rng.gaussian())),
+            Distribution::TRUNCATED(mean, std, min, max) => {
+                // rejection sampling keeps the shape of the normal within the bounds
+                for _ in 0..MAX_REJECTIONS {
+                    let x = mean + std * rng.gaussian();
+                    if x >= *min && x <= *max {
+                        return Ok(toml::Value::Float(x));
+                    }
+                }
+                Err(format!("Dispersion of [{}] rarely falls within [{}, {}]", self.signal, min, max))
+            },
+            Distribution::CHOICE(values) => {
+                let i = ((rng.uniform() * values.len() as f64) as usize).min(values.len() - 1);
+                Ok(values[i].clone())
+            },
+        }
+    }
+}
+
+/// Seed of a case, which only depends on the batch seed and the case number
+/// so that any case can be run again on its own
+pub fn case_seed(seed : u64, case : usize) -> u64 {
+    let base = Random::new(seed).next_u64();
+    Random::new(base.wrapping_add(case as u64)).next_u64()
+}
@@ -1,12 +1,72 @@
 
 use crate::state::EngineState;
+use crate::fault::{Fault, FaultRecord};
+use crate::hash::FrameHash;
+use crate::monitor::Verdict;
+use crate::timeline::Timeline;
+use crate::trace::ThreadTrace;
+
+use std::path::PathBuf;
 
 /// Engines present an API 
 pub trait Engine {
     fn get_state(&self) -> EngineState;
 
+    /// Simulation time of the current frame, in seconds
+    fn get_time(&self) -> f64;
+
     fn init(&mut self) -> i32;
     fn step(&mut self, steps: u64) -> i32;
+
+    /// Execute frames until the simulation time is reached
+    fn run_until(&mut self, time : f64) -> i32;
     fn pause(&mut self) -> i32;
     fn end(&mut self) -> i32;
+
+    /// Return to the configuration state from the initialized, paused,
+    /// or ended states so that the scenario can be initialized again
+    fn reset(&mut self) -> i32;
+
+    /// Encode a model signal as MessagePack, e.g. `gen2.params.amplitude`.
+    /// Executed at a frame boundary while running
+    fn get(&mut self, path : &str) -> Result<Vec<u8>, String>;
+
+    /// Decode MessagePack into a model signal.
+    /// Executed at a frame boundary while running
+    fn set(&mut self, path : &str, mp : &[u8]) -> Result<(), String>;
+
+    /// Arm a fault overriding a model signal, e.g. a bias on `gen2.input.amplitude`.
+    /// Executed at a frame boundary while running
+    fn inject(&mut self, fault : Fault) -> Result<(), String>;
+
+    /// Clear the faults on a signal, including those armed by the scene
+    fn clear_faults(&mut self, signal : &str) -> Result<(), String>;
+
+    /// Faults that became active or were cleared, in time order
+    fn fault_log(&mut self) -> Result<Vec<FaultRecord>, String>;
+
+    /// Replace the timeline evaluated at frame boundaries, before init
+    fn set_timeline(&mut self, timeline : Timeline) -> i32;
+
+    /// Pass or fail of the scenario, from the monitors of the timeline and the engine state
+    fn verdict(&mut self) -> Verdict;
+
+    /// Events of the timeline since the last call, in time order
+    fn events(&mut self) -> Vec<(f64, String)>;
+
+    /// Sample the signals at every frame boundary, so that a snapshot can read them while running
+    fn watch(&mut self, signals : &[String]) -> Result<(), String>;
+
+    /// Values of signals at a single frame boundary, with its time. An idle engine reads them
+    /// directly, a running engine returns the watched signals sampled at the last boundary
+    fn snapshot(&mut self, signals : &[String]) -> Result<(f64, Vec<(String, rmpv::Value)>), String>;
+
+    /// Hash of the model states at the last hashed frame, None unless the timeline hashes them
+    fn state_hash(&mut self) -> Option<FrameHash>;
+
+    /// Write the flight recorder of the scene to files, returning the signal file
+    fn dump(&mut self, reason : &str) -> Result<PathBuf, String>;
+
+    /// Spans of every thread, empty unless the scene is traced. Executed at a frame boundary while running
+    fn trace(&mut self) -> Result<Vec<ThreadTrace>, String>;
 }
@@ -0,0 +1,433 @@
+extern crate rmpv;
+
+use std::collections::HashMap;
+
+/// Binary operator of an expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    LT,
+    LE,
+    GT,
+    GE,
+    EQ,
+    NE,
+    AND,
+    OR,
+}
+
+/// Function of an expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Func {
+    ABS,
+    MIN,
+    MAX,
+    SQRT,
+}
+
+/// Expr
+/// Arithmetic and logic on scalar signals, e.g. `abs(gen1.output - gen2.input.bias) < 1e-3`.
+/// Values are numbers, comparisons and logic give 1 for true and 0 for false
+#[derive(Clone, Debug)]
+pub enum Expr {
+    NUM(f64),
+    /// signal, element of an array signal
+    SIGNAL(String, Option<usize>),
+    NEG(Box<Expr>),
+    NOT(Box<Expr>),
+    BINARY(Op, Box<Expr>, Box<Expr>),
+    CALL(Func, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(txt : &str) -> Result<Expr, String> {
+        let mut parser = Parser { txt, pos : 0 };
+        let expr = match parser.or() {
+            Ok(e) => e,
+            Err(e) => return Err(format!("Invalid expression [{}]: {}", txt, e)),
+        };
+        parser.skip_space();
+        if parser.pos != txt.len() {
+            return Err(format!("Invalid expression [{}]: unexpected [{}]", txt, &txt[parser.pos..]));
+        }
+        Ok(expr)
+    }
+
+    /// Signals read by the expression, without element selections
+    pub fn signals(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        self.collect(&mut out);
+        out
+    }
+
+    fn collect(&self, out : &mut Vec<String>) {
+        match self {
+            Expr::NUM(_) => {},
+            Expr::SIGNAL(s, _) => {
+                if !out.contains(s) {
+                    out.push(s.clone());
+                }
+            },
+            Expr::NEG(e) | Expr::NOT(e) => e.collect(out),
+            Expr::BINARY(_, a, b) => {
+                a.collect(out);
+                b.collect(out);
+            },
+            Expr::CALL(_, args) => {
+                for a in args {
+                    a.collect(out);
+                }
+            },
+        }
+    }
+
+    /// Evaluate with the sampled signal values
+    pub fn eval(&self, values : &HashMap<String, rmpv::Value>) -> Result<f64, String> {
+        match self {
+            Expr::NUM(x) => Ok(*x),
+            Expr::SIGNAL(s, index) => {
+                let val = match values.get(s) {
+                    Some(v) => v,
+                    None => return Err(format!("Signal [{}] was not sampled", s)),
+                };
+                let val = match (index, val) {
+                    (None, v) => v,
+                    (Some(i), rmpv::Value::Array(arr)) => match arr.get(*i) {
+                        Some(v) => v,
+                        None => return Err(format!("Element {} is out of bounds of [{}] with {} elements", i, s, arr.len())),
+                    },
+                    (Some(_), v) => return Err(format!("[{}] is not an array: {}", s, v)),
+                };
+                match scalar(val) {
+                    Some(x) => Ok(x),
+                    None => Err(format!("[{}] is not a number or boolean: {}", s, val)),
+                }
+            },
+            Expr::NEG(e) => Ok(-e.eval(values)?),
+            Expr::NOT(e) => Ok(truth(e.eval(values)? == 0.0)),
+            Expr::BINARY(op, a, b) => {
+                let x = a.eval(values)?;
+                let y = b.eval(values)?;
+                Ok(match op {
+                    Op::ADD => x + y,
+                    Op::SUB => x - y,
+                    Op::MUL => x * y,
+                    Op::DIV => x / y,
+                    Op::LT => truth(x < y),
+                    Op::LE => truth(x <= y),
+                    Op::GT => truth(x > y),
+                    Op::GE => truth(x >= y),
+                    Op::EQ => truth(x == y),
+                    Op::NE => truth(x != y),
+                    Op::AND => truth(x != 0.0 && y != 0.0),
+                    Op::OR => truth(x != 0.0 || y != 0.0),
+                })
+            },
+            Expr::CALL(func, args) => {
+                let mut xs = Vec::new();
+                for a in args {
+                    xs.push(a.eval(values)?);
+                }
+                Ok(match func {
+                    Func::ABS => xs[0].abs(),
+                    Func::SQRT => xs[0].sqrt(),
+                    Func::MIN => xs.iter().cloned().fold(f64::INFINITY, f64::min),
+                    Func::MAX => xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                })
+            },
+        }
+    }
+}
+
+/// Numeric value of a scalar signal, booleans are 0 and 1
+pub fn scalar(val : &rmpv::Value) -> Option<f64> {
+    match val {
+        rmpv::Value::F64(f) => Some(*f),
+        rmpv::Value::F32(f) => Some(*f as f64),
+        rmpv::Value::Boolean(b) => Some(truth(*b)),
+        rmpv::Value::Integer(i) => i.as_f64(),
+        _ => None,
+    }
+}
+
+fn truth(b : bool) -> f64 {
+    if b { 1.0 } else { 0.0 }
+}
+
+/// Condition
+/// Expression that holds when it evaluates to a non-zero value
+#[derive(Clone, Debug)]
+pub struct Condition {
+    /// expression as written in the scene
+    pub text : String,
+    pub expr : Expr,
+}
+
+impl Condition {
+    pub fn parse(txt : &str) -> Result<Condition, String> {
+        Ok(Condition { text : txt.trim().to_string(), expr : Expr::parse(txt)? })
+    }
+
+    pub fn holds(&self, values : &HashMap<String, rmpv::Value>) -> Result<bool, String> {
+        Ok(self.expr.eval(values)? != 0.0)
+    }
+
+    pub fn signals(&self) -> Vec<String> {
+        self.expr.signals()
+    }
+}
+
+// recursive descent over the expression grammar, lowest precedence first:
+//   or      := and ('||' and)*
+//   and     := compare ('&&' compare)*
+//   compare := sum (('<' | '<=' | '>' | '>=' | '==' | '!=') sum)?
+//   sum     := product (('+' | '-') product)*
+//   product := unary (('*' | '/') unary)*
+//   unary   := ('-' | '!') unary | primary
+//   primary := number | 'true' | 'false' | func '(' or (',' or)* ')' | signal ('[' integer ']')? | '(' or ')'
+struct Parser<'a> {
+    txt : &'a str,
+    pos : usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        while self.txt[self.pos..].starts_with(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn rest(&mut self) -> &'a str {
+        self.skip_space();
+        &self.txt[self.pos..]
+    }
+
+    // consumes the first of the tokens the text continues with
+    fn eat(&mut self, tokens : &[&'static str]) -> Option<&'static str> {
+        let rest = self.rest();
+        let tok = tokens.iter().find(|t| rest.starts_with(**t))?;
+        self.pos += tok.len();
+        Some(tok)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut e = self.and()?;
+        while self.eat(&["||"]).is_some() {
+            e = Expr::BINARY(Op::OR, Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut e = self.compare()?;
+        while self.eat(&["&&"]).is_some() {
+            e = Expr::BINARY(Op::AND, Box::new(e), Box::new(self.compare()?));
+        }
+        Ok(e)
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let e = self.sum()?;
+        // two character operators first, so that `<=` is not read as `<`
+        let op = match self.eat(&["<=", ">=", "==", "!=", "<", ">"]) {
+            Some("<=") => Op::LE,
+            Some(">=") => Op::GE,
+            Some("==") => Op::EQ,
+            Some("!=") => Op::NE,
+            Some("<") => Op::LT,
+            Some(">") => Op::GT,
+            _ => return Ok(e),
+        };
+        Ok(Expr::BINARY(op, Box::new(e), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut e = self.product()?;
+        loop {
+            let op = match self.eat(&["+", "-"]) {
+                Some("+") => Op::ADD,
+                Some("-") => Op::SUB,
+                _ => return Ok(e),
+            };
+            e = Expr::BINARY(op, Box::new(e), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut e = self.unary()?;
+        loop {
+            let op = match self.eat(&["*", "/"]) {
+                Some("*") => Op::MUL,
+                Some("/") => Op::DIV,
+                _ => return Ok(e),
+            };
+            e = Expr::BINARY(op, Box::new(e), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        // `!=` is a comparison, not a negation
+        if self.rest().starts_with('!') && !self.rest().starts_with("!=") {
+            self.pos += 1;
+            return Ok(Expr::NOT(Box::new(self.unary()?)));
+        }
+        if self.eat(&["-"]).is_some() {
+            return Ok(Expr::NEG(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let rest = self.rest();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Err("expected a value".to_string()),
+        };
+        if c == '(' {
+            self.pos += 1;
+            let e = self.or()?;
+            if self.eat(&[")"]).is_none() {
+                return Err("missing )".to_string());
+            }
+            return Ok(e);
+        }
+        if c.is_ascii_digit() || c == '.' {
+            // digits, a fraction and an exponent with an optional sign
+            let bytes = rest.as_bytes();
+            let mut len = 0;
+            while len < bytes.len() {
+                let b = bytes[len];
+                let exp_sign = (b == b'+' || b == b'-') && len > 0 && (bytes[len - 1] == b'e' || bytes[len - 1] == b'E');
+                if b.is_ascii_digit() || b == b'.' || b == b'e' || b == b'E' || exp_sign {
+                    len += 1;
+                } else {
+                    break;
+                }
+            }
+            return match rest[..len].parse::<f64>() {
+                Ok(x) => {
+                    self.pos += len;
+                    Ok(Expr::NUM(x))
+                },
+                Err(_) => Err(format!("invalid number [{}]", &rest[..len])),
+            };
+        }
+        if !(c.is_alphabetic() || c == '_') {
+            return Err(format!("unexpected [{}]", c));
+        }
+        let len = rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '.').map(|c| c.len_utf8()).sum();
+        let name = &rest[..len];
+        self.pos += len;
+        match name {
+            "true" => return Ok(Expr::NUM(1.0)),
+            "false" => return Ok(Expr::NUM(0.0)),
+            _ => {},
+        }
+
+        if self.eat(&["("]).is_some() {
+            let (func, arity) = match name {
+                "abs" => (Func::ABS, Some(1)),
+                "sqrt" => (Func::SQRT, Some(1)),
+                "min" => (Func::MIN, None),
+                "max" => (Func::MAX, None),
+                _ => return Err(format!("unknown function [{}], expected abs, sqrt, min or max", name)),
+            };
+            let mut args = vec![self.or()?];
+            while self.eat(&[","]).is_some() {
+                args.push(self.or()?);
+            }
+            if self.eat(&[")"]).is_none() {
+                return Err(format!("missing ) after the arguments of {}", name));
+            }
+            if arity.is_some_and(|n| n != args.len()) {
+                return Err(format!("{} takes one argument", name));
+            }
+            return Ok(Expr::CALL(func, args));
+        }
+
+        if !name.contains('.') {
+            return Err(format!("[{}] is not a signal of the form model.field", name));
+        }
+        let mut index = None;
+        if self.eat(&["["]).is_some() {
+            let digits : &str = self.rest();
+            let len = digits.chars().take_while(|c| c.is_ascii_digit()).count();
+            index = match digits[..len].parse::<usize>() {
+                Ok(i) => Some(i),
+                Err(_) => return Err(format!("expected an element index after {}[", name)),
+            };
+            self.pos += len;
+            if self.eat(&["]"]).is_none() {
+                return Err(format!("missing ] after the element of {}", name));
+            }
+        }
+        Ok(Expr::SIGNAL(name.to_string(), index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(txt : &str) -> f64 {
+        Expr::parse(txt).unwrap().eval(&HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("10 - 2 - 3"), 5.0);
+        assert_eq!(eval("8 / 2 / 2"), 2.0);
+        assert_eq!(eval("-2 * 3 + 1"), -5.0);
+        assert_eq!(eval("2e-3 * 1000"), 2.0);
+        assert_eq!(eval("1 + 1 == 2"), 1.0);
+        assert_eq!(eval("1 < 2 && 3 > 4 || 1"), 1.0);
+        assert_eq!(eval("1 || 0 && 0"), 1.0);
+        assert_eq!(eval("!0 && !(1 != 1)"), 1.0);
+        assert_eq!(eval("1 <= 1 && 2 >= 3"), 0.0);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("abs(1 - 3)"), 2.0);
+        assert_eq!(eval("sqrt(4) * 2"), 4.0);
+        assert_eq!(eval("min(3, 1, 2)"), 1.0);
+        assert_eq!(eval("max(1)"), 1.0);
+        assert_eq!(eval("max(1, min(5, 4)) - abs(-1)"), 3.0);
+    }
+
+    #[test]
+    fn function_arity() {
+        assert_eq!(Expr::parse("abs(1, 2)").unwrap_err(), "Invalid expression [abs(1, 2)]: abs takes one argument");
+        assert_eq!(Expr::parse("sqrt(1, 2)").unwrap_err(), "Invalid expression [sqrt(1, 2)]: sqrt takes one argument");
+        assert!(Expr::parse("abs()").is_err());
+        assert!(Expr::parse("abs(1").is_err());
+        assert!(Expr::parse("exp(1)").is_err());
+    }
+
+    #[test]
+    fn signals() {
+        let expr = Expr::parse("gen1.output[1] + gen2.input.on - gen1.output[0]").unwrap();
+        assert_eq!(expr.signals(), vec!["gen1.output".to_string(), "gen2.input.on".to_string()]);
+        let mut values = HashMap::new();
+        values.insert("gen1.output".to_string(), rmpv::Value::Array(vec![rmpv::Value::F64(1.0), rmpv::Value::F64(2.5)]));
+        values.insert("gen2.input.on".to_string(), rmpv::Value::Boolean(true));
+        assert_eq!(expr.eval(&values), Ok(2.5));
+        assert!(Expr::parse("gen1.output[2]").unwrap().eval(&values).is_err());
+        assert!(Expr::parse("gen3.output").unwrap().eval(&values).is_err());
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("output < 1").is_err());
+        assert!(Expr::parse("(1 + 2").is_err());
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("gen1.output[x]").is_err());
+    }
+}
@@ -0,0 +1,483 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::connection::{decode, encode, is_numeric, parse_slices, Selection, Transform};
+use crate::metadata::{split_signal, ModelMetadata};
+use crate::plugin::ModelInstance;
+use crate::random::Random;
+use crate::scene::{parse_duration, to_msgpack_value};
+use crate::TIME_EPS;
+
+/// FaultKind
+/// How a fault overrides the value of a signal
+#[derive(Clone, Debug, PartialEq)]
+pub enum FaultKind {
+    /// replace the value
+    FORCE(rmpv::Value),
+    /// add a constant
+    BIAS(f64),
+    /// add gaussian noise with the standard deviation
+    NOISE(f64),
+    /// hold the value the signal had when the fault became active
+    STUCK,
+    /// add a ramp, in units per second since the fault became active
+    RAMP(f64),
+}
+
+impl FaultKind {
+    /// Parse the kind name and its value, e.g. `bias` and `0.5`
+    pub fn parse(kind : &str, value : Option<rmpv::Value>) -> Result<FaultKind, String> {
+        let number = |v : Option<rmpv::Value>| -> Result<f64, String> {
+            match v {
+                Some(rmpv::Value::F64(f)) => Ok(f),
+                Some(rmpv::Value::F32(f)) => Ok(f as f64),
+                Some(rmpv::Value::Integer(i)) if i.as_f64().is_some() => Ok(i.as_f64().unwrap()),
+                Some(v) => Err(format!("{} fault value {} is not a number", kind, v)),
+                None => Err(format!("{} fault requires a value", kind)),
+            }
+        };
+        match kind {
+            "force" => match value {
+                Some(v) => Ok(FaultKind::FORCE(v)),
+                None => Err("force fault requires a value".to_string()),
+            },
+            "bias" => Ok(FaultKind::BIAS(number(value)?)),
+            "noise" => {
+                let std = number(value)?;
+                if std < 0.0 {
+                    return Err(format!("noise fault standard deviation {} is negative", std));
+                }
+                Ok(FaultKind::NOISE(std))
+            },
+            "stuck" => Ok(FaultKind::STUCK),
+            "ramp" => Ok(FaultKind::RAMP(number(value)?)),
+            _ => Err(format!("Unknown fault [{}], expected force, bias, noise, stuck or ramp", kind)),
+        }
+    }
+
+    /// Short description for the fault log, e.g. `bias 0.5`
+    pub fn describe(&self) -> String {
+        match self {
+            FaultKind::FORCE(v) => format!("force {}", v),
+            FaultKind::BIAS(b) => format!("bias {}", b),
+            FaultKind::NOISE(s) => format!("noise {}", s),
+            FaultKind::STUCK => "stuck".to_string(),
+            FaultKind::RAMP(r) => format!("ramp {}/s", r),
+        }
+    }
+}
+
+/// Fault
+/// Override of a model signal, active from `start` until `stop` seconds of simulation time.
+/// Declared in the scene [[faults]] array or injected while the engine runs
+#[derive(Clone, Debug)]
+pub struct Fault {
+    /// model signal with an optional element selection, e.g. `gen2.input.amplitude`
+    pub signal : String,
+    pub kind : FaultKind,
+    pub start : f64,
+    /// active until cleared if not set
+    pub stop : Option<f64>,
+    /// seed of the noise generator
+    pub seed : u64,
+}
+
+impl Fault {
+    /// Fault that is active from the next step of the model until cleared
+    pub fn new(signal : &str, kind : FaultKind) -> Fault {
+        Fault {
+            signal : signal.to_string(),
+            kind,
+            start : 0.0,
+            stop : None,
+            seed : 0,
+        }
+    }
+
+    /// Parse an entry of the [[faults]] array
+    pub fn parse(entry : &toml::Value, ctxt : &str) -> Result<Fault, String> {
+        let tbl = match entry.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        let signal = match tbl.get("signal").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Err(format!("{}.signal is missing or not a string", ctxt)),
+        };
+        let kind = match tbl.get("kind").and_then(|v| v.as_str()) {
+            Some(k) => k,
+            None => return Err(format!("{}.kind is missing or not a string", ctxt)),
+        };
+        let value = match tbl.get("value") {
+            Some(v) => Some(to_msgpack_value(v)?),
+            None => None,
+        };
+        let mut fault = match FaultKind::parse(kind, value) {
+            Ok(k) => Fault::new(signal, k),
+            Err(e) => return Err(format!("{}: {}", ctxt, e)),
+        };
+        if let Some(start) = tbl.get("start") {
+            fault.start = parse_duration(start)?;
+        }
+        if let Some(stop) = tbl.get("stop") {
+            let stop = parse_duration(stop)?;
+            if stop <= fault.start {
+                return Err(format!("{}.stop {} is not after start {}", ctxt, stop, fault.start));
+            }
+            fault.stop = Some(stop);
+        }
+        if let Some(seed) = tbl.get("seed") {
+            match seed.as_integer() {
+                Some(s) if s >= 0 => fault.seed = s as u64,
+                _ => return Err(format!("{}.seed is not a positive integer", ctxt)),
+            }
+        }
+        Ok(fault)
+    }
+
+    /// Name of the model instance the fault overrides
+    pub fn model(&self) -> Result<&str, String> {
+        Ok(split_signal(&self.signal)?.0)
+    }
+}
+
+/// FaultRecord
+/// Entry of the fault log, written when a fault becomes active and when it is cleared
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "server", derive(serde::Serialize))]
+pub struct FaultRecord {
+    /// simulation time, seconds
+    pub time : f64,
+    pub signal : String,
+    /// description of the override, e.g. `bias 0.5`
+    pub fault : String,
+    /// true when the fault became active, false when it was cleared
+    pub active : bool,
+}
+
+impl FaultRecord {
+    fn new(time : f64, fault : &Fault, active : bool) -> FaultRecord {
+        FaultRecord {
+            time,
+            signal : fault.signal.clone(),
+            fault : fault.kind.describe(),
+            active,
+        }
+    }
+}
+
+/// Progress of a fault through its active window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultState {
+    PENDING,
+    ACTIVE,
+    DONE,
+}
+
+/// FaultOverride
+/// A fault resolved against the interface of the model it overrides.
+/// Inputs, parameters and data are overridden before the model steps, after
+/// connections are received. Outputs are overridden after the model steps,
+/// before they are published to connections
+pub struct FaultOverride {
+    pub fault : Fault,
+    pub target : Selection,
+    /// armed by the scene, faults injected at run time are dropped on reset
+    pub scene : bool,
+    state : FaultState,
+    /// simulation time the fault became active
+    since : f64,
+    /// value held by a stuck fault
+    held : Option<Vec<u8>>,
+    /// value of the signal without the override, restored when the fault ends
+    base : Option<Vec<u8>>,
+    /// value last written by the override
+    written : Option<Vec<u8>>,
+    /// noise only depends on the seed and the steps of the model
+    rng : Random,
+}
+
+impl FaultOverride {
+    pub fn new(fault : Fault, meta : &ModelMetadata, scene : bool) -> Result<FaultOverride, String> {
+        let (_, path) = split_signal(&fault.signal)?;
+        let (path, slices) = parse_slices(path)?;
+        let field = match meta.find(path) {
+            Ok(f) => f,
+            Err(_) if !meta.children(path).is_empty() => {
+                return Err(format!("Fault on [{}]: faults apply to leaf fields, not structs", fault.signal));
+            },
+            Err(e) => return Err(format!("Fault on [{}]: {}", fault.signal, e)),
+        };
+        let target = Selection::new(field, &slices)?;
+        let mut fault = fault;
+        match &fault.kind {
+            FaultKind::FORCE(v) => {
+                fault.kind = FaultKind::FORCE(coerce(v, &field.typename));
+            },
+            FaultKind::STUCK => {},
+            _ if !is_numeric(&field.typename) => {
+                return Err(format!("Fault on [{}]: {} is {}, only numeric values can be {}",
+                    fault.signal, field.path, field.typename, fault.kind.describe()));
+            },
+            _ => {},
+        }
+        let mut o = FaultOverride {
+            fault,
+            target,
+            scene,
+            state : FaultState::PENDING,
+            since : 0.0,
+            held : None,
+            base : None,
+            written : None,
+            rng : Random::new(0),
+        };
+        o.reset();
+        Ok(o)
+    }
+
+    /// True if the fault overrides the value produced by the model step
+    pub fn after_step(&self) -> bool {
+        self.target.field.tag == "out"
+    }
+
+    /// True if the fault is currently overriding the signal
+    pub fn active(&self) -> bool {
+        self.state == FaultState::ACTIVE
+    }
+
+    /// Return to the armed state
+    pub fn reset(&mut self) {
+        self.state = FaultState::PENDING;
+        self.since = 0.0;
+        self.held = None;
+        self.base = None;
+        self.written = None;
+        self.rng = Random::new(self.fault.seed);
+    }
+
+    /// Stop overriding the signal, logging the fault if it was active
+    pub fn clear(&mut self, time : f64, log : &mut Vec<FaultRecord>) {
+        if self.state == FaultState::ACTIVE {
+            log.push(FaultRecord::new(time, &self.fault, false));
+        }
+        self.state = FaultState::DONE;
+    }
+
+    /// Activate or clear the fault at the simulation time of a model step, logging the change.
+    /// Returns true if the fault is active
+    pub fn update(&mut self, time : f64, log : &mut Vec<FaultRecord>) -> bool {
+        let stopped = match self.fault.stop {
+            Some(stop) => time >= stop - TIME_EPS,
+            None => false,
+        };
+        match self.state {
+            FaultState::PENDING if stopped => {
+                self.state = FaultState::DONE;
+            },
+            FaultState::PENDING if time >= self.fault.start - TIME_EPS => {
+                self.state = FaultState::ACTIVE;
+                self.since = time;
+                log.push(FaultRecord::new(time, &self.fault, true));
+            },
+            FaultState::ACTIVE if stopped => {
+                self.clear(time, log);
+            },
+            _ => {},
+        }
+        self.active()
+    }
+
+    /// Override the signal of the model instance
+    pub fn apply(&mut self, time : f64, instance : &mut ModelInstance) -> Result<(), String> {
+        let index = self.target.field.index.clone();
+        let field = instance.get(&index)?;
+        let value = self.value(time, self.target.extract(field.clone())?)?;
+        let mp = self.target.insert(field, &value)?;
+        match instance.set(&index, &mp) {
+            0 => Ok(()),
+            _ => Err(format!("Failed to override [{}] with {}", self.fault.signal, self.fault.kind.describe())),
+        }
+    }
+
+    /// Write back the value the signal had before the last override, unless a connection
+    /// or the model wrote the signal since then. Called before the overrides of each step,
+    /// so that overrides of a signal that is not driven do not accumulate
+    pub fn restore(&mut self, instance : &mut ModelInstance) -> Result<(), String> {
+        if self.base.is_none() {
+            return Ok(());
+        }
+        let index = self.target.field.index.clone();
+        let field = instance.get(&index)?;
+        if let Some(base) = self.restored(&self.target.extract(field.clone())?) {
+            let mp = self.target.insert(field, &base)?;
+            if instance.set(&index, &mp) != 0 {
+                return Err(format!("Failed to restore [{}] after {}", self.fault.signal, self.fault.kind.describe()));
+            }
+        }
+        Ok(())
+    }
+
+    // overridden value of the selection, given its value without the override
+    fn value(&mut self, time : f64, base : Vec<u8>) -> Result<Vec<u8>, String> {
+        let value = match &self.fault.kind {
+            FaultKind::FORCE(v) => encode(v)?,
+            FaultKind::STUCK => self.held.get_or_insert_with(|| base.clone()).clone(),
+            FaultKind::BIAS(b) => {
+                let t = Transform { scale : 1.0, offset : *b };
+                encode(&t.apply(&decode(&base)?)?)?
+            },
+            FaultKind::RAMP(r) => {
+                let t = Transform { scale : 1.0, offset : r * (time - self.since) };
+                encode(&t.apply(&decode(&base)?)?)?
+            },
+            FaultKind::NOISE(std) => {
+                let std = *std;
+                encode(&self.add_noise(&decode(&base)?, std)?)?
+            },
+        };
+        self.base = Some(base);
+        self.written = Some(value.clone());
+        Ok(value)
+    }
+
+    // value to write back, None if the signal was written since the last override
+    fn restored(&mut self, current : &[u8]) -> Option<Vec<u8>> {
+        let base = self.base.take();
+        match self.written.take() {
+            Some(w) if w == current => base,
+            _ => None,
+        }
+    }
+
+    fn add_noise(&mut self, val : &rmpv::Value, std : f64) -> Result<rmpv::Value, String> {
+        match val {
+            rmpv::Value::Array(arr) => {
+                let mut out = Vec::new();
+                for v in arr {
+                    out.push(self.add_noise(v, std)?);
+                }
+                Ok(rmpv::Value::Array(out))
+            },
+            _ => {
+                let t = Transform { scale : 1.0, offset : std * self.rng.gaussian() };
+                t.apply(val)
+            }
+        }
+    }
+}
+
+// integers given for floating point fields are converted, so that forcing `1` works for an f64,
+// and floats given for integer fields are rounded, as connection transforms do
+pub(crate) fn coerce(val : &rmpv::Value, typename : &str) -> rmpv::Value {
+    match val {
+        rmpv::Value::Integer(i) if typename == "f32" || typename == "f64" => match i.as_f64() {
+            Some(f) => rmpv::Value::F64(f),
+            None => val.clone(),
+        },
+        rmpv::Value::F64(f) if is_numeric(typename) && !typename.starts_with('f') => match typename.starts_with('u') {
+            true if *f >= 0.0 => rmpv::Value::from(f.round() as u64),
+            true => val.clone(),
+            false => rmpv::Value::from(f.round() as i64),
+        },
+        rmpv::Value::Array(arr) => rmpv::Value::Array(arr.iter().map(|v| coerce(v, typename)).collect()),
+        _ => val.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> ModelMetadata {
+        ModelMetadata::parse(r#"
+name = "m"
+generics = []
+defaults = []
+[[fields]]
+path = "input.x"
+index = [0, 0]
+type = "f64"
+dims = []
+tag = "in"
+"#).unwrap()
+    }
+
+    fn arm(kind : FaultKind, stop : Option<f64>) -> FaultOverride {
+        let mut fault = Fault::new("m.input.x", kind);
+        fault.stop = stop;
+        FaultOverride::new(fault, &input(), true).unwrap()
+    }
+
+    // the steps of a model whose input is not connected, as ScheduledModel::apply_faults does them
+    fn run(o : &mut FaultOverride, field : &mut Vec<u8>, times : &[f64], log : &mut Vec<FaultRecord>) -> Vec<f64> {
+        let mut seen = Vec::new();
+        for t in times {
+            if let Some(base) = o.restored(field) {
+                *field = base;
+            }
+            if o.update(*t, log) {
+                *field = o.value(*t, field.clone()).unwrap();
+            }
+            seen.push(decode(field).unwrap().as_f64().unwrap());
+        }
+        seen
+    }
+
+    fn value(x : f64) -> Vec<u8> {
+        encode(&rmpv::Value::F64(x)).unwrap()
+    }
+
+    #[test]
+    fn bias_does_not_accumulate_on_an_unconnected_field() {
+        let mut o = arm(FaultKind::BIAS(0.5), None);
+        let mut field = value(2.0);
+        let mut log = Vec::new();
+        assert_eq!(run(&mut o, &mut field, &[0.0, 0.1, 0.2], &mut log), vec![2.5, 2.5, 2.5]);
+        assert_eq!(log.len(), 1);
+    }
+
+    #[test]
+    fn ramp_grows_from_the_value_before_the_fault() {
+        let mut o = arm(FaultKind::RAMP(10.0), None);
+        let mut field = value(1.0);
+        let mut log = Vec::new();
+        let seen = run(&mut o, &mut field, &[0.0, 0.1, 0.2], &mut log);
+        for (s, expected) in seen.iter().zip([1.0, 2.0, 3.0]) {
+            assert!((s - expected).abs() < 1e-12, "{:?}", seen);
+        }
+    }
+
+    #[test]
+    fn force_is_restored_when_the_fault_expires() {
+        let mut o = arm(FaultKind::FORCE(rmpv::Value::F64(7.0)), Some(0.2));
+        let mut field = value(1.0);
+        let mut log = Vec::new();
+        assert_eq!(run(&mut o, &mut field, &[0.0, 0.1, 0.2, 0.3], &mut log), vec![7.0, 7.0, 1.0, 1.0]);
+        assert_eq!(log.iter().map(|r| r.active).collect::<Vec<_>>(), vec![true, false]);
+    }
+
+    #[test]
+    fn clear_restores_the_value_before_the_fault() {
+        let mut o = arm(FaultKind::BIAS(-3.0), None);
+        let mut field = value(4.0);
+        let mut log = Vec::new();
+        run(&mut o, &mut field, &[0.0, 0.1], &mut log);
+        o.clear(0.1, &mut log);
+        if let Some(base) = o.restored(&field) {
+            field = base;
+        }
+        assert_eq!(run(&mut o, &mut field, &[0.2, 0.3], &mut log), vec![4.0, 4.0]);
+    }
+
+    #[test]
+    fn a_driven_field_is_not_restored() {
+        let mut o = arm(FaultKind::BIAS(1.0), None);
+        let mut field = value(0.0);
+        let mut log = Vec::new();
+        run(&mut o, &mut field, &[0.0], &mut log);
+        // a connection wrote the field after the override
+        field = value(5.0);
+        assert_eq!(run(&mut o, &mut field, &[0.1], &mut log), vec![6.0]);
+    }
+}
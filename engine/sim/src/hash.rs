@@ -0,0 +1,179 @@
+extern crate toml;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+const FNV_OFFSET : u64 = 0xcbf29ce484222325;
+const FNV_PRIME : u64 = 0x100000001b3;
+
+/// FNV-1a hash of bytes, continuing from a previous hash
+pub fn fnv1a(hash : u64, bytes : &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, b| (h ^ *b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Hash of the MessagePack encoding of a field
+pub fn hash_bytes(bytes : &[u8]) -> u64 {
+    fnv1a(FNV_OFFSET, bytes)
+}
+
+/// FrameHash
+/// Hash of every model interface at the end of a frame
+#[derive(Clone, Debug, Default)]
+pub struct FrameHash {
+    pub frame : u64,
+    /// time of the frame, seconds
+    pub time : f64,
+    pub hash : u64,
+    /// hash of each field, sorted by signal so that it does not depend on the thread layout
+    pub fields : Vec<(String, u64)>,
+}
+
+impl FrameHash {
+    pub fn new(frame : u64, time : f64, fields : Vec<(String, u64)>) -> FrameHash {
+        let mut fields = fields;
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut hash = FNV_OFFSET;
+        for (signal, h) in fields.iter() {
+            hash = fnv1a(hash, signal.as_bytes());
+            hash = fnv1a(hash, &h.to_le_bytes());
+        }
+        FrameHash { frame, time, hash, fields }
+    }
+
+    /// First signal that differs from another hash of the same models,
+    /// including signals only one of them has
+    pub fn diverging(&self, other : &FrameHash) -> Option<String> {
+        let (mut i, mut j) = (0, 0);
+        while i < self.fields.len() || j < other.fields.len() {
+            match (self.fields.get(i), other.fields.get(j)) {
+                (Some(a), Some(b)) if a.0 == b.0 => {
+                    if a.1 != b.1 {
+                        return Some(a.0.clone());
+                    }
+                    i += 1;
+                    j += 1;
+                },
+                (Some(a), Some(b)) if a.0 < b.0 => return Some(a.0.clone()),
+                (Some(_), Some(b)) => return Some(b.0.clone()),
+                (Some(a), None) => return Some(a.0.clone()),
+                (None, Some(b)) => return Some(b.0.clone()),
+                (None, None) => break,
+            }
+        }
+        None
+    }
+}
+
+/// StateHasher
+/// Scene [hashing] table. Hashes the fields of every model at the end of every N frames,
+/// optionally writing the frame hashes to a CSV file that can be compared between machines
+#[derive(Debug)]
+pub struct StateHasher {
+    /// frames between hashes
+    pub every : u64,
+    pub file : Option<PathBuf>,
+    /// hash of the last hashed frame
+    pub last : Option<FrameHash>,
+    /// field hashes of the contexts sampled at the current frame boundary
+    pending : Vec<(String, u64)>,
+    writer : Option<BufWriter<File>>,
+}
+
+impl Clone for StateHasher {
+    fn clone(&self) -> StateHasher {
+        StateHasher::new(self.every, self.file.clone())
+    }
+}
+
+impl StateHasher {
+    pub fn new(every : u64, file : Option<PathBuf>) -> StateHasher {
+        StateHasher {
+            every : every.max(1),
+            file,
+            last : None,
+            pending : Vec::new(),
+            writer : None,
+        }
+    }
+
+    /// Parse the [hashing] table
+    pub fn parse(val : &toml::Value) -> Result<StateHasher, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err("[hashing] is not a table".to_string()),
+        };
+        for key in tbl.keys() {
+            if !["every", "file"].contains(&key.as_str()) {
+                return Err(format!("[hashing].{} is not a hashing setting", key));
+            }
+        }
+        let every = match tbl.get("every") {
+            Some(toml::Value::Integer(n)) if *n > 0 => *n as u64,
+            Some(_) => return Err("[hashing].every must be a positive number of frames".to_string()),
+            None => 1,
+        };
+        let file = match tbl.get("file") {
+            Some(toml::Value::String(f)) => Some(PathBuf::from(f)),
+            Some(_) => return Err("[hashing].file is not a string".to_string()),
+            None => None,
+        };
+        Ok(StateHasher::new(every, file))
+    }
+
+    /// True if the state is hashed at a frame boundary
+    /// @param[in] boundary - number of frames executed
+    pub fn due(&self, boundary : u64) -> bool {
+        boundary > 0 && boundary.is_multiple_of(self.every)
+    }
+
+    /// Add the field hashes of a context
+    pub fn add(&mut self, fields : Vec<(String, u64)>) {
+        self.pending.extend(fields);
+    }
+
+    /// Drop the field hashes of a boundary that is not hashed
+    pub fn discard(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Combine the field hashes of every context into the hash of the frame before the boundary
+    pub fn record(&mut self, boundary : u64, delta : f64) -> Result<(), String> {
+        let frame = boundary - 1;
+        let hash = FrameHash::new(frame, frame as f64 * delta, self.pending.drain(..).collect());
+        if let Some(file) = &self.file {
+            if self.writer.is_none() {
+                let mut w = match File::create(file) {
+                    Ok(f) => BufWriter::new(f),
+                    Err(e) => return Err(format!("Unable to create hash file {}: {}", file.display(), e)),
+                };
+                if writeln!(w, "time,frame,hash").is_err() {
+                    return Err(format!("Failed to write hash file {}", file.display()));
+                }
+                self.writer = Some(w);
+            }
+            // the hash is written as text so that logdiff compares it exactly
+            let row = format!("{},{},0x{:016x}", (hash.time * 1e9).round() / 1e9, frame, hash.hash);
+            match self.writer.as_mut().map(|w| writeln!(w, "{}", row)) {
+                Some(Ok(_)) => {},
+                _ => return Err(format!("Failed to write hash file {}", file.display())),
+            }
+        }
+        self.last = Some(hash);
+        Ok(())
+    }
+
+    /// Return to the state before the scenario started, the file is rewritten by the next run
+    pub fn reset(&mut self) {
+        self.flush();
+        self.writer = None;
+        self.last = None;
+        self.pending.clear();
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(w) = self.writer.as_mut() {
+            let _ = w.flush();
+        }
+    }
+}
@@ -1,24 +1,65 @@
 
 extern crate libc;
 
-mod state;
-mod engine;
-mod threadcontext;
+pub mod state;
+pub mod engine;
+pub mod threadcontext;
+pub mod serial;
+pub mod plugin;
+pub mod modelthread;
+pub mod scene;
+pub mod metadata;
+pub mod connection;
+pub mod fault;
+pub mod timeline;
+pub mod expr;
+pub mod monitor;
+pub mod hash;
+pub mod verify;
+pub mod runner;
+pub mod validate;
+pub mod compose;
+pub mod params;
+pub mod trace;
+pub mod recorder;
+pub mod snapshot;
+pub mod compare;
+pub mod random;
+pub mod dispersion;
+pub mod sweep;
+pub mod batch;
+#[cfg(feature = "server")]
+pub mod server;
+
+/// Tolerance when comparing simulation times, seconds
+pub const TIME_EPS : f64 = 1e-9;
 
 use crate::state::EngineState;
 use crate::engine::Engine;
-use crate::threadcontext::{ThreadContext};
-
-use std::{thread, time};
+use crate::connection::decode;
+use crate::threadcontext::{ThreadContext, ThreadTime};
+use crate::metadata::split_signal;
+use crate::fault::{Fault, FaultRecord};
+use crate::hash::FrameHash;
+use crate::monitor::Verdict;
+use crate::timeline::{Halt, Timeline};
+use crate::trace::{ThreadTrace, TraceKind};
+use crate::snapshot::ModelState;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use std::thread;
 use std::time::{Instant, Duration};
-use std::sync::{Arc, Barrier, mpsc, mpsc::TryRecvError, mpsc::Receiver, mpsc::Sender, Mutex};
+use std::sync::{Arc, Barrier, BarrierWaitResult, mpsc, mpsc::Receiver, mpsc::Sender, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use rmodel::{ConfigStatus, RunStatus};
 
 #[derive(Debug)]
 pub struct ThreadComms {
     pub handle : thread::JoinHandle<()>,
-    pub tx : Sender<ThreadCommand>,
+    pub tx : Sender<ThreadMessage>,
     pub rx : Receiver<ThreadResult>,
 }
 
@@ -33,6 +74,18 @@ pub struct SimEngine<const N: usize> {
     pub runner : thread::JoinHandle<()>,
     pub runner_tx : Sender<ThreadCommand>,
     pub runner_rx : Receiver<ThreadResult>,
+
+    // number of frames executed since init, and the duration of a frame
+    pub frame : Arc<AtomicU64>,
+    pub delta : f64,
+
+    // direct access to each thread for signal requests
+    pub signal_tx : Vec<Sender<ThreadMessage>>,
+    // model instance name to thread id
+    pub models : HashMap<String, usize>,
+
+    // scripted actions and logs, evaluated at frame boundaries
+    pub timeline : Arc<Mutex<Timeline>>,
 }
 
 #[derive(PartialEq)]
@@ -40,15 +93,60 @@ pub enum ThreadCommand {
     INIT,
     EXECUTE(u64),
     PAUSE,
+    RESET,
     SHUTDOWN,
 }
 
+/// Signal access executed by the thread that owns the model
+pub enum SignalRequest {
+    /// model, field path, reply
+    GET(String, String, Sender<Result<Vec<u8>, String>>),
+    /// model, field path, msgpack, reply
+    SET(String, String, Vec<u8>, Sender<Result<(), String>>),
+    /// fault, reply
+    INJECT(Fault, Sender<Result<(), String>>),
+    /// signal, reply
+    CLEAR(String, Sender<Result<(), String>>),
+    /// reply
+    LOG(Sender<Vec<FaultRecord>>),
+    /// reply
+    TRACE(Sender<Option<ThreadTrace>>),
+    /// reply
+    STATE(Sender<Result<Vec<ModelState>, String>>),
+}
+
+pub enum ThreadMessage {
+    COMMAND(ThreadCommand),
+    SIGNAL(SignalRequest),
+}
+
+#[derive(PartialEq)]
 pub enum ThreadResult {
     OK,
     ERR,
     END,
 }
 
+impl<const N: usize> SimEngine<N> {
+    fn thread_of(&self, name : &str) -> Result<usize, String> {
+        match self.models.get(name) {
+            Some(tid) => Ok(*tid),
+            None => Err(format!("Model [{}] not found", name)),
+        }
+    }
+
+    // sends a signal request to a thread and waits for the reply
+    fn request<T>(&self, tid : usize, req : SignalRequest, rx : Receiver<T>) -> Result<T, String> {
+        if self.signal_tx[tid].send(ThreadMessage::SIGNAL(req)).is_err() {
+            return Err(format!("Thread {} is not running", tid));
+        }
+        match rx.recv() {
+            Ok(res) => Ok(res),
+            Err(_) => Err(format!("Thread {} dropped the request", tid)),
+        }
+    }
+}
+
 impl<const N: usize> Engine for SimEngine<N> {
 
     fn get_state(&self) -> EngineState {
@@ -64,7 +162,11 @@ impl<const N: usize> Engine for SimEngine<N> {
 
     fn init(&mut self) -> i32 {
         match self.state.lock() {
-            Ok(_) => {
+            Ok(mut state) => {
+                // the state changes with the command, so that callers can wait on it
+                if *state == EngineState::CONFIG {
+                    *state = EngineState::INITIALIZING;
+                }
                 match self.runner_tx.send(ThreadCommand::INIT) {
                     Ok(_) => {
                         return 0;
@@ -82,7 +184,10 @@ impl<const N: usize> Engine for SimEngine<N> {
 
     fn step(&mut self, steps: u64) -> i32 {
         match self.state.lock() {
-            Ok(_) => {
+            Ok(mut state) => {
+                if matches!(*state, EngineState::INITIALIZED | EngineState::PAUSED) {
+                    *state = EngineState::RUNNING;
+                }
                 match self.runner_tx.send(ThreadCommand::EXECUTE(steps)) {
                     Ok(_) => {
                         return 0;
@@ -118,7 +223,10 @@ impl<const N: usize> Engine for SimEngine<N> {
 
     fn end(&mut self) -> i32 {
         match self.state.lock() {
-            Ok(_) => {
+            Ok(mut state) => {
+                if matches!(*state, EngineState::INITIALIZED | EngineState::PAUSED) {
+                    *state = EngineState::ENDING;
+                }
                 match self.runner_tx.send(ThreadCommand::SHUTDOWN) {
                     Ok(_) => {
                         return 0;
@@ -133,23 +241,279 @@ impl<const N: usize> Engine for SimEngine<N> {
             }
         }
     }
+
+    fn reset(&mut self) -> i32 {
+        match self.state.lock() {
+            Ok(mut state) => {
+                // the threads are idle in the states a reset is accepted from
+                if matches!(*state, EngineState::INITIALIZED | EngineState::PAUSED | EngineState::ENDED) {
+                    self.timeline.lock().unwrap().reset();
+                    *state = EngineState::RESETTING;
+                }
+                match self.runner_tx.send(ThreadCommand::RESET) {
+                    Ok(_) => {
+                        0
+                    },
+                    _ => {
+                        2 // failed to send command
+                    }
+                }
+            },
+            _ => {
+                -1 // failed to lock
+            }
+        }
+    }
+
+    fn get_time(&self) -> f64 {
+        self.frame.load(Ordering::SeqCst) as f64 * self.delta
+    }
+
+    fn run_until(&mut self, time : f64) -> i32 {
+        let target = (time / self.delta).round() as u64;
+        let current = self.frame.load(Ordering::SeqCst);
+        if target <= current {
+            return 1; // already past the requested time
+        }
+        self.step(target - current)
+    }
+
+    fn get(&mut self, path : &str) -> Result<Vec<u8>, String> {
+        let (name, field) = split_signal(path)?;
+        let tid = self.thread_of(name)?;
+        let (tx, rx) = mpsc::channel();
+        self.request(tid, SignalRequest::GET(name.to_string(), field.to_string(), tx), rx)?
+    }
+
+    fn set(&mut self, path : &str, mp : &[u8]) -> Result<(), String> {
+        let (name, field) = split_signal(path)?;
+        let tid = self.thread_of(name)?;
+        let (tx, rx) = mpsc::channel();
+        self.request(tid, SignalRequest::SET(name.to_string(), field.to_string(), mp.to_vec(), tx), rx)?
+    }
+
+    fn inject(&mut self, fault : Fault) -> Result<(), String> {
+        let tid = self.thread_of(fault.model()?)?;
+        let (tx, rx) = mpsc::channel();
+        self.request(tid, SignalRequest::INJECT(fault, tx), rx)?
+    }
+
+    fn clear_faults(&mut self, signal : &str) -> Result<(), String> {
+        let (name, _) = split_signal(signal)?;
+        let tid = self.thread_of(name)?;
+        let (tx, rx) = mpsc::channel();
+        self.request(tid, SignalRequest::CLEAR(signal.to_string(), tx), rx)?
+    }
+
+    fn fault_log(&mut self) -> Result<Vec<FaultRecord>, String> {
+        let mut log = Vec::new();
+        for tid in 0..N {
+            let (tx, rx) = mpsc::channel();
+            log.extend(self.request(tid, SignalRequest::LOG(tx), rx)?);
+        }
+        log.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(log)
+    }
+
+    fn set_timeline(&mut self, timeline : Timeline) -> i32 {
+        match self.state.lock() {
+            Ok(state) => {
+                if *state != EngineState::CONFIG {
+                    return 1; // invalid state
+                }
+                *self.timeline.lock().unwrap() = timeline;
+                0
+            },
+            _ => {
+                -1 // failed to lock
+            }
+        }
+    }
+
+    fn verdict(&mut self) -> Verdict {
+        let mut verdict = self.timeline.lock().unwrap().verdict();
+        if verdict.error.is_none() && self.get_state() == EngineState::ERRORED {
+            verdict.error = Some("Engine errored".to_string());
+        }
+        verdict
+    }
+
+    fn events(&mut self) -> Vec<(f64, String)> {
+        std::mem::take(&mut self.timeline.lock().unwrap().events)
+    }
+
+    fn watch(&mut self, signals : &[String]) -> Result<(), String> {
+        // a signal that does not read would fail the timeline
+        for s in signals {
+            self.get(s)?;
+        }
+        self.timeline.lock().unwrap().watch(signals);
+        Ok(())
+    }
+
+    fn snapshot(&mut self, signals : &[String]) -> Result<(f64, Vec<(String, rmpv::Value)>), String> {
+        if matches!(self.get_state(), EngineState::RUNNING | EngineState::ENDING) {
+            return match self.timeline.lock().unwrap().snapshot(signals) {
+                Some(s) => Ok(s),
+                None => Err("Signals are not sampled while running, watch them before the run".to_string()),
+            };
+        }
+        // the threads are idle at the same frame boundary
+        let mut values = Vec::new();
+        for s in signals {
+            values.push((s.clone(), decode(&self.get(s)?)?));
+        }
+        Ok((self.get_time(), values))
+    }
+
+    fn state_hash(&mut self) -> Option<FrameHash> {
+        self.timeline.lock().unwrap().hasher.as_ref().and_then(|h| h.last.clone())
+    }
+
+    fn dump(&mut self, reason : &str) -> Result<PathBuf, String> {
+        let file = self.timeline.lock().unwrap().dump(reason)?;
+        for tid in 0..N {
+            let (tx, rx) = mpsc::channel();
+            let models = self.request(tid, SignalRequest::STATE(tx), rx)??;
+            self.timeline.lock().unwrap().add_state(models)?;
+        }
+        Ok(file)
+    }
+
+    fn trace(&mut self) -> Result<Vec<ThreadTrace>, String> {
+        let mut traces = Vec::new();
+        for tid in 0..N {
+            let (tx, rx) = mpsc::channel();
+            traces.extend(self.request(tid, SignalRequest::TRACE(tx), rx)?);
+        }
+        Ok(traces)
+    }
+}
+
+// executes a signal request against the models of a thread
+fn handle_signal(obj : &mut Box<dyn ThreadContext + Send>, req : SignalRequest) {
+    match req {
+        SignalRequest::GET(name, path, reply) => {
+            let _ = reply.send(obj.get_signal(&name, &path));
+        },
+        SignalRequest::SET(name, path, mp, reply) => {
+            let _ = reply.send(obj.set_signal(&name, &path, &mp));
+        },
+        SignalRequest::INJECT(fault, reply) => {
+            let _ = reply.send(obj.inject(fault));
+        },
+        SignalRequest::CLEAR(signal, reply) => {
+            let _ = reply.send(obj.clear_faults(&signal));
+        },
+        SignalRequest::LOG(reply) => {
+            let _ = reply.send(obj.fault_log());
+        },
+        SignalRequest::TRACE(reply) => {
+            let _ = reply.send(obj.trace());
+        },
+        SignalRequest::STATE(reply) => {
+            let _ = reply.send(snapshot::capture(obj.as_mut()));
+        }
+    }
+}
+
+// waits for every thread at the barrier, recording the wait when the thread is traced
+fn barrier_wait(obj : &mut Box<dyn ThreadContext + Send>, barrier : &Barrier) -> BarrierWaitResult {
+    let span = trace::begin(obj.tracer());
+    let res = barrier.wait();
+    trace::end(obj.tracer(), span, TraceKind::BARRIER, 0);
+    res
+}
+
+// samples the watched signals of the models of a thread
+fn timeline_sample(obj : &mut Box<dyn ThreadContext + Send>, timeline : &Mutex<Timeline>, names : &[String]) {
+    let mut tl = timeline.lock().unwrap();
+    if let Err(e) = tl.sample(obj.as_mut(), names) {
+        tl.fail(format!("Timeline failed to sample: {}", e));
+    }
+}
+
+// evaluates the timeline once every thread has sampled it, halting at this boundary if requested
+fn report_errors(obj : &mut Box<dyn ThreadContext + Send>, timeline : &Mutex<Timeline>) {
+    let errors = obj.errors();
+    if !errors.is_empty() {
+        timeline.lock().unwrap().model_failures(errors);
+    }
+}
+
+fn timeline_boundary(timeline : &Mutex<Timeline>, frame : u64, delta : f64, halt : &AtomicBool) {
+    let mut tl = timeline.lock().unwrap();
+    tl.boundary(frame, delta);
+    if tl.halt.is_some() {
+        halt.store(true, Ordering::SeqCst);
+    }
+}
+
+// executes the timeline actions for the models of a thread
+fn timeline_apply(obj : &mut Box<dyn ThreadContext + Send>, timeline : &Mutex<Timeline>, names : &[String], halt : &AtomicBool) {
+    let mut tl = timeline.lock().unwrap();
+    if let Err(e) = tl.apply(obj.as_mut(), names) {
+        tl.fail(format!("Timeline action failed: {}", e));
+        halt.store(true, Ordering::SeqCst);
+    }
+}
+
+// instantiates start_engine for the supported numbers of contexts
+macro_rules! start_sized {
+    ($tcs:ident, $srt:ident, $($n:literal)*) => {
+        match $tcs.len() {
+            $($n => match <[Box<dyn ThreadContext + Send>; $n]>::try_from($tcs) {
+                Ok(arr) => Ok(Box::new(start_engine(arr, $srt))),
+                Err(_) => Err("Failed to arrange the thread contexts".to_string()),
+            },)*
+            n => Err(format!("SimEngine executes 1 to {} threads, not {}", MAX_THREADS, n)),
+        }
+    };
+}
+
+/// Most threads of an engine started by start_engine_boxed
+pub const MAX_THREADS : usize = 16;
+
+/// Start a SimEngine for a number of contexts only known at run time, e.g. the threads of a scene
+pub fn start_engine_boxed(tcs : Vec<Box<dyn ThreadContext + Send>>, soft_real_time : bool) -> Result<Box<dyn Engine + Send>, String> {
+    start_sized!(tcs, soft_real_time, 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16)
 }
 
 // creates the SimEngine struct, starts threads that are ready to initialize
 // @param[in] tcs - array of ThreadContext objects containing models to execute
-fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_real_time : bool) -> SimEngine<N> {
+pub fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_real_time : bool) -> SimEngine<N> {
     // create barrier for thread sync
     let barr = Arc::new(Barrier::new(N));
 
+    // set by the runner (pause/end) or by a thread (model stop/error) to halt execution.
+    // threads only act on it at a frame boundary, after agreeing on its value
+    let halt  = Arc::new(AtomicBool::new(false));
+    let latch = Arc::new(AtomicBool::new(false));
+    let frame = Arc::new(AtomicU64::new(0));
+    let timeline = Arc::new(Mutex::new(Timeline::default()));
+
+    // the engine frame is the fastest thread rate
+    let delta = tcs.iter().map(|tc| tc.get_time().delta).fold(f64::INFINITY, f64::min);
+
     let mut tc_all = Vec::new(); // temporary for insertion into contructor
+    let mut signal_tx = Vec::new();
+    let mut models = HashMap::new();
 
     // spawn context threads
     for (ind, tc) in tcs.into_iter().enumerate() {
         let (txx, rxx) = mpsc::channel(); // trigger channel
         let (tx, rx)   = mpsc::channel(); // response channel
         let cbarrier = Arc::clone(&barr);
+        let chalt  = Arc::clone(&halt);
+        let clatch = Arc::clone(&latch);
+        let cframe = Arc::clone(&frame);
+        let ctimeline = Arc::clone(&timeline);
         let srt = soft_real_time;
-        let timedelta  = Duration::from_secs_f64(tc.get_time().delta);
+        let timedelta  = Duration::from_secs_f64(delta);
+        let ratio = tc.get_time().frames(delta);
+        for name in tc.model_names() {
+            models.insert(name, ind);
+        }
         let handle = thread::spawn(move||{
             let mut obj = tc;
 
@@ -159,10 +523,21 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
 
             let mut frame_start = Instant::now();
 
+            // every command is answered with exactly one result once it has completed
             loop {
-                match rxx.recv() {
+                let cmd = match rxx.recv() {
+                    Ok(ThreadMessage::COMMAND(c)) => Ok(c),
+                    Ok(ThreadMessage::SIGNAL(req)) => {
+                        handle_signal(&mut obj, req);
+                        continue;
+                    },
+                    Err(e) => Err(e),
+                };
+                match cmd {
                     Ok(ThreadCommand::INIT) => {
-                        match obj.init() {
+                        let status = obj.init();
+                        report_errors(&mut obj, &ctimeline);
+                        match status {
                             ConfigStatus::OK => {
                                 tx.send(ThreadResult::OK).unwrap();
                             },
@@ -176,33 +551,68 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
                         }
                     }
                     Ok(ThreadCommand::EXECUTE(steps)) => {
+                        let mut result = ThreadResult::OK;
+                        let timed = !ctimeline.lock().unwrap().is_empty();
+                        let names = obj.model_names();
+                        let mut completed = true;
                         for _ in 0..steps {
                             if srt {
                                 frame_start = Instant::now();
                             }
-                            // wait for runner thread to trigger
-                            cbarrier.wait();
-
-                            match obj.step() {
-                                RunStatus::OK => {
-                                    tx.send(ThreadResult::OK).unwrap();
+                            // every thread has completed the previous frame, the timeline
+                            // samples the signals of each thread before the leader evaluates it
+                            if timed {
+                                barrier_wait(&mut obj, &cbarrier);
+                                timeline_sample(&mut obj, &ctimeline, &names);
+                            }
+                            // wait for all threads to reach the frame boundary, then
+                            // agree on the halt flag so that every thread stops on the same frame
+                            if barrier_wait(&mut obj, &cbarrier).is_leader() {
+                                if timed {
+                                    timeline_boundary(&ctimeline, cframe.load(Ordering::SeqCst), delta, &chalt);
                                 }
+                                let halted = chalt.load(Ordering::SeqCst);
+                                clatch.store(halted, Ordering::SeqCst);
+                                if !halted {
+                                    cframe.fetch_add(1, Ordering::SeqCst);
+                                }
+                            }
+                            barrier_wait(&mut obj, &cbarrier);
+
+                            // signal requests and timeline actions are executed at the frame boundary.
+                            // the runner does not send commands while the threads are executing
+                            while let Ok(ThreadMessage::SIGNAL(req)) = rxx.try_recv() {
+                                handle_signal(&mut obj, req);
+                            }
+                            if timed {
+                                timeline_apply(&mut obj, &ctimeline, &names, &chalt);
+                            }
+                            if clatch.load(Ordering::SeqCst) {
+                                completed = false;
+                                break;
+                            }
+
+                            // slower threads only step on the engine frames that start one of their frames
+                            let due = (cframe.load(Ordering::SeqCst) - 1).is_multiple_of(ratio);
+                            let status = match due {
+                                true => obj.step(),
+                                false => RunStatus::OK,
+                            };
+                            report_errors(&mut obj, &ctimeline);
+                            match status {
+                                RunStatus::OK => {},
                                 RunStatus::STOP => {
-                                    tx.send(ThreadResult::END).unwrap();
+                                    if result != ThreadResult::ERR {
+                                        result = ThreadResult::END;
+                                    }
+                                    chalt.store(true, Ordering::SeqCst);
                                 }
                                 RunStatus::ERR => {
-                                    tx.send(ThreadResult::ERR).unwrap();
-                                }
-                            }
-                            // check for pause command
-                            match rxx.try_recv() {
-                                Ok(ThreadCommand::PAUSE) => {
-                                    //
-                                    tx.send(ThreadResult::OK).unwrap();
-                                    break;
-                                },
-                                _ => {
-                                    // do nothing
+                                    result = ThreadResult::ERR;
+                                    chalt.store(true, Ordering::SeqCst);
+                                    if timed {
+                                        ctimeline.lock().unwrap().model_error(format!("model error on thread {}", obj.get_tid()));
+                                    }
                                 }
                             }
                             if srt {
@@ -213,19 +623,70 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
                                 }
                             }
                         }
+                        if timed {
+                            // the boundary after the last frame, so that it is logged before pausing
+                            if completed {
+                                barrier_wait(&mut obj, &cbarrier);
+                                timeline_sample(&mut obj, &ctimeline, &names);
+                                if barrier_wait(&mut obj, &cbarrier).is_leader() {
+                                    timeline_boundary(&ctimeline, cframe.load(Ordering::SeqCst), delta, &chalt);
+                                }
+                                barrier_wait(&mut obj, &cbarrier);
+                                timeline_apply(&mut obj, &ctimeline, &names, &chalt);
+                            }
+                            let mut tl = ctimeline.lock().unwrap();
+                            let span = trace::begin(obj.tracer());
+                            tl.flush();
+                            trace::end(obj.tracer(), span, TraceKind::FLUSH, 0);
+                            match tl.halt {
+                                Some(Halt::END) if result == ThreadResult::OK => result = ThreadResult::END,
+                                Some(Halt::ERR) => result = ThreadResult::ERR,
+                                _ => {},
+                            }
+                        }
+                        tx.send(result).unwrap();
                     }
                     Ok(ThreadCommand::PAUSE) => {
-                        // the loop is already in a paused-like state, do nothing
+                        // pausing is handled through the halt flag, do nothing
                         continue;
                     }
+                    Ok(ThreadCommand::RESET) => {
+                        // return to the post-load state with the clock zeroed
+                        let delta = obj.get_time().delta;
+                        obj.set_time(ThreadTime { delta, tick : 0 });
+                        let status = obj.reset();
+                        report_errors(&mut obj, &ctimeline);
+                        match status {
+                            ConfigStatus::OK => {
+                                tx.send(ThreadResult::OK).unwrap();
+                            },
+                            _ => {
+                                tx.send(ThreadResult::ERR).unwrap();
+                            }
+                        }
+                    }
                     Ok(ThreadCommand::SHUTDOWN) => {
+                        // halt the models, but keep the thread alive so that it can be reset
+                        let status = obj.end();
+                        report_errors(&mut obj, &ctimeline);
+                        match status {
+                            RunStatus::ERR => {
+                                tx.send(ThreadResult::ERR).unwrap();
+                            },
+                            _ => {
+                                tx.send(ThreadResult::END).unwrap();
+                            }
+                        }
+                    }
+                    _ => {
+                        // engine has been dropped
                         break;
                     }
-                    _ => ()
                 }
             }
         });
 
+        signal_tx.push(txx.clone());
         let thread_comm = ThreadComms {
             handle : handle,
             tx : txx,
@@ -246,22 +707,24 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
     let mutex_state = Arc::clone(&rstate);
 
     let mut thread_state : [EngineState; N] = [EngineState::CONFIG; N];
+    let rhalt = Arc::clone(&halt);
+    let rframe = Arc::clone(&frame);
+    let rtimeline = Arc::clone(&timeline);
+    let mut end_requested = false;
 
     let run = thread::spawn(move|| {
         loop {
             let stat = mtor_rx.try_recv();
             match state {
-                EngineState::CONFIG => {
-                    if stat == Ok(ThreadCommand::INIT) {
-                        let mut s = mutex_state.lock().unwrap();
-                        state = EngineState::INITIALIZING;
-                        *s = state;
+                EngineState::CONFIG if stat == Ok(ThreadCommand::INIT) => {
+                    let mut s = mutex_state.lock().unwrap();
+                    state = EngineState::INITIALIZING;
+                    *s = state;
 
-                        // send commands to initialize threads
-                        for i in 0..N {
-                            tc_all[i].tx.send(ThreadCommand::INIT).unwrap();
-                            thread_state[i] = EngineState::INITIALIZING;
-                        }
+                    // send commands to initialize threads
+                    for i in 0..N {
+                        tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::INIT)).unwrap();
+                        thread_state[i] = EngineState::INITIALIZING;
                     }
                 },
                 EngineState::INITIALIZING => {
@@ -318,43 +781,155 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
                             *s = state;
 
                             // send execute command
+                            rhalt.store(false, Ordering::SeqCst);
+                            rtimeline.lock().unwrap().halt = None;
                             for i in 0..N {
-                                tc_all[i].tx.send(ThreadCommand::EXECUTE(steps)).unwrap();
+                                tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::EXECUTE(steps))).unwrap();
+                                thread_state[i] = EngineState::RUNNING;
                             }
                         },
                         Ok(ThreadCommand::SHUTDOWN) => {
-                            // go straight to the ended state instead of ending
                             let mut s = mutex_state.lock().unwrap();
-                            state = EngineState::ENDED;
+                            state = EngineState::ENDING;
                             *s = state;
-                        }
+
+                            // send command to shutdown
+                            for i in 0..N {
+                                tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::SHUTDOWN)).unwrap();
+                                thread_state[i] = EngineState::ENDING;
+                            }
+                        },
+                        Ok(ThreadCommand::RESET) => {
+                            let mut s = mutex_state.lock().unwrap();
+                            state = EngineState::RESETTING;
+                            *s = state;
+
+                            // send command to reset
+                            for i in 0..N {
+                                tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::RESET)).unwrap();
+                                thread_state[i] = EngineState::RESETTING;
+                            }
+                        },
                         _ => ()
                     }
                 },
                 EngineState::RUNNING => {
-                    // poll state
                     match stat {
                         Ok(ThreadCommand::SHUTDOWN) => {
-                            // set flag to halt threads
-                            state = EngineState::ENDING;
+                            // halt the threads, then shutdown once they have stopped
+                            end_requested = true;
+                            rhalt.store(true, Ordering::SeqCst);
+                        },
+                        Ok(ThreadCommand::PAUSE) => {
+                            // halt the threads at the next frame boundary
+                            rhalt.store(true, Ordering::SeqCst);
+                        }
+                        _ => ()
+                    }
 
-                            // send command to shutdown
+                    // poll state
+                    let mut count = 0;
+                    for i in 0..N {
+                        if thread_state[i] == EngineState::RUNNING {
+                            match tc_all[i].rx.try_recv() {
+                                Ok(ThreadResult::OK) => {
+                                    thread_state[i] = EngineState::PAUSED;
+                                },
+                                Ok(ThreadResult::ERR) => {
+                                    thread_state[i] = EngineState::ERRORED;
+                                },
+                                Ok(ThreadResult::END) => {
+                                    thread_state[i] = EngineState::ENDING;
+                                },
+                                _ => {
+                                    // still executing
+                                    continue;
+                                }
+                            }
+                        }
+                        count += 1;
+                    }
+                    if count == N {
+                        // all threads have finished executing
+                        let mut s = mutex_state.lock().unwrap();
+                        if thread_state.contains(&EngineState::ERRORED) {
+                            state = EngineState::ERRORED;
+                        } else if end_requested || thread_state.contains(&EngineState::ENDING) {
+                            state = EngineState::ENDING;
                             for i in 0..N {
-                                tc_all[i].tx.send(ThreadCommand::SHUTDOWN).unwrap();
+                                tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::SHUTDOWN)).unwrap();
+                                thread_state[i] = EngineState::ENDING;
                             }
-                        },
-                        Ok(ThreadCommand::PAUSE) => {
-                            // set flag to halt threads
+                        } else {
                             state = EngineState::PAUSED;
                         }
-                        _ => ()
+                        *s = state;
+                        end_requested = false;
                     }
                 },
                 EngineState::ENDING => {
-                    // poll state
+                    // poll state, all threads have been sent the shutdown command
+                    let mut count = 0;
+                    for i in 0..N {
+                        if thread_state[i] == EngineState::ENDING {
+                            match tc_all[i].rx.try_recv() {
+                                Ok(_) => {
+                                    thread_state[i] = EngineState::ENDED;
+                                },
+                                _ => {
+                                    continue;
+                                }
+                            }
+                        }
+                        count += 1;
+                    }
+                    if count == N {
+                        let mut s = mutex_state.lock().unwrap();
+                        state = EngineState::ENDED;
+                        *s = state;
+                    }
+                },
+                EngineState::ENDED if stat == Ok(ThreadCommand::RESET) => {
+                    let mut s = mutex_state.lock().unwrap();
+                    state = EngineState::RESETTING;
+                    *s = state;
 
-                    // if all threads have paused, call final actions and shutdown
-                }
+                    for i in 0..N {
+                        tc_all[i].tx.send(ThreadMessage::COMMAND(ThreadCommand::RESET)).unwrap();
+                        thread_state[i] = EngineState::RESETTING;
+                    }
+                },
+                EngineState::RESETTING => {
+                    // poll state
+                    let mut count = 0;
+                    for i in 0..N {
+                        if thread_state[i] == EngineState::RESETTING {
+                            match tc_all[i].rx.try_recv() {
+                                Ok(ThreadResult::ERR) => {
+                                    thread_state[i] = EngineState::ERRORED;
+                                },
+                                Ok(_) => {
+                                    thread_state[i] = EngineState::CONFIG;
+                                },
+                                _ => {
+                                    continue;
+                                }
+                            }
+                        }
+                        count += 1;
+                    }
+                    if count == N {
+                        // all threads are back in their post-load state
+                        let mut s = mutex_state.lock().unwrap();
+                        if thread_state.contains(&EngineState::ERRORED) {
+                            state = EngineState::ERRORED;
+                        } else {
+                            state = EngineState::CONFIG;
+                            rframe.store(0, Ordering::SeqCst);
+                        }
+                        *s = state;
+                    }
+                },
                 _ => ()
             }
             thread::sleep(Duration::from_millis(20)); // sleep to prevent hogging the cpu
@@ -362,11 +937,16 @@ fn start_engine<const N: usize>(tcs : [Box<dyn ThreadContext + Send>; N], soft_r
     });
 
     SimEngine {
-        soft_real_time : false,
+        soft_real_time,
         state : rstate,
         barrier : barr,
         runner : run,
         runner_tx : mtor_tx,
         runner_rx : rtom_rx,
+        frame,
+        delta,
+        signal_tx,
+        models,
+        timeline,
     }
 }
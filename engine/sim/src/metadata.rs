@@ -0,0 +1,238 @@
+extern crate toml;
+
+use std::collections::HashMap;
+
+/// FieldInfo
+/// A leaf of the model interface
+#[derive(Clone, Debug)]
+pub struct FieldInfo {
+    /// dotted path from the model struct, e.g. `params.amplitude`
+    pub path : String,
+    /// index array of the generated msgpack functions
+    pub index : Vec<i32>,
+    pub typename : String,
+    /// empty for scalars, -1 for variable length
+    pub dims : Vec<i64>,
+    /// tag of the top level field: in, out, data, params
+    pub tag : String,
+    /// unit attribute of the IDL, empty if the field has none
+    pub unit : String,
+}
+
+/// ModelMetadata
+/// Flattened model interface exported by a model plugin
+#[derive(Clone, Debug)]
+pub struct ModelMetadata {
+    pub name : String,
+    pub generics : Vec<String>,
+    pub defaults : Vec<String>,
+    pub fields : Vec<FieldInfo>,
+    lookup : HashMap<String, usize>,
+}
+
+fn string_array(tbl : &toml::Table, key : &str) -> Result<Vec<String>, String> {
+    let mut out = vec![];
+    match tbl.get(key).and_then(|v| v.as_array()) {
+        Some(arr) => {
+            for v in arr {
+                match v.as_str() {
+                    Some(s) => out.push(s.to_string()),
+                    None => return Err(format!("Metadata [{}] contains a value that is not a string", key)),
+                }
+            }
+        },
+        None => return Err(format!("Metadata [{}] is not an array", key)),
+    }
+    Ok(out)
+}
+
+fn int_array(tbl : &toml::Table, key : &str) -> Result<Vec<i64>, String> {
+    let mut out = vec![];
+    match tbl.get(key).and_then(|v| v.as_array()) {
+        Some(arr) => {
+            for v in arr {
+                match v.as_integer() {
+                    Some(i) => out.push(i),
+                    None => return Err(format!("Metadata [{}] contains a value that is not an integer", key)),
+                }
+            }
+        },
+        None => return Err(format!("Metadata [{}] is not an array", key)),
+    }
+    Ok(out)
+}
+
+impl ModelMetadata {
+    /// Parse the metadata string exported by `rsis_metadata`
+    pub fn parse(txt : &str) -> Result<ModelMetadata, String> {
+        let data = match txt.parse::<toml::Table>() {
+            Ok(d) => d,
+            Err(e) => return Err(format!("Failed to parse model metadata: {}", e)),
+        };
+        let name = match data.get("name").and_then(|v| v.as_str()) {
+            Some(n) => n.to_string(),
+            None => return Err("Metadata does not define [name]".to_string()),
+        };
+        let mut meta = ModelMetadata {
+            name,
+            generics : string_array(&data, "generics")?,
+            defaults : string_array(&data, "defaults")?,
+            fields : Vec::new(),
+            lookup : HashMap::new(),
+        };
+        if let Some(fields) = data.get("fields") {
+            let arr = match fields.as_array() {
+                Some(a) => a,
+                None => return Err("Metadata [fields] is not an array".to_string()),
+            };
+            for f in arr {
+                let tbl = match f.as_table() {
+                    Some(t) => t,
+                    None => return Err("Metadata [fields] contains a value that is not a table".to_string()),
+                };
+                let get = |key : &str| -> Result<String, String> {
+                    match tbl.get(key).and_then(|v| v.as_str()) {
+                        Some(s) => Ok(s.to_string()),
+                        None => Err(format!("Metadata field is missing [{}]", key)),
+                    }
+                };
+                meta.fields.push(FieldInfo {
+                    path : get("path")?,
+                    index : int_array(tbl, "index")?.into_iter().map(|i| i as i32).collect(),
+                    typename : get("type")?,
+                    dims : int_array(tbl, "dims")?,
+                    tag : get("tag")?,
+                    unit : get("unit").unwrap_or_default(),
+                });
+            }
+        }
+        meta.reindex();
+        Ok(meta)
+    }
+
+    fn reindex(&mut self) {
+        self.lookup = self.fields.iter().enumerate().map(|(i, f)| (f.path.clone(), i)).collect();
+    }
+
+    /// Resolve the generic types of the fields
+    /// @param[in] generic - comma separated specialization, empty for the default
+    pub fn specialize(&self, generic : &str) -> Result<ModelMetadata, String> {
+        let spec : Vec<String> = if generic.is_empty() {
+            self.defaults.clone()
+        } else {
+            generic.split(',').map(|s| s.trim().to_string()).collect()
+        };
+        if spec.len() != self.generics.len() {
+            return Err(format!("Model {} expects {} generic(s), got [{}]", self.name, self.generics.len(), generic));
+        }
+        let map : HashMap<&String, &String> = self.generics.iter().zip(spec.iter()).collect();
+        let mut meta = self.clone();
+        for f in meta.fields.iter_mut() {
+            if let Some(t) = map.get(&f.typename) {
+                f.typename = t.to_string();
+            }
+        }
+        Ok(meta)
+    }
+
+    /// Find the leaf field at the dotted path
+    pub fn find(&self, path : &str) -> Result<&FieldInfo, String> {
+        match self.lookup.get(path) {
+            Some(i) => Ok(&self.fields[*i]),
+            None => Err(format!("Model {} has no field [{}]", self.name, path)),
+        }
+    }
+
+    /// Leaf fields below the dotted path of a struct, in interface order
+    pub fn children(&self, path : &str) -> Vec<&FieldInfo> {
+        let prefix = format!("{}.", path);
+        self.fields.iter().filter(|f| f.path.starts_with(&prefix)).collect()
+    }
+}
+
+/// Split a signal path into the model instance name and the field path,
+/// e.g. `gen2.params.amplitude` into `gen2` and `params.amplitude`
+pub fn split_signal(path : &str) -> Result<(&str, &str), String> {
+    match path.split_once('.') {
+        Some((model, field)) if !model.is_empty() && !field.is_empty() => Ok((model, field)),
+        _ => Err(format!("Signal [{}] is not of the form model.field", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generic() -> ModelMetadata {
+        ModelMetadata::parse(r#"
+name = "filter"
+generics = ["T"]
+defaults = ["f64"]
+[[fields]]
+path = "input.x"
+index = [0, 0]
+type = "T"
+dims = []
+tag = "in"
+[[fields]]
+path = "params.gains.kp"
+index = [2, 0, 0]
+type = "f32"
+dims = []
+tag = "params"
+unit = "1/s"
+[[fields]]
+path = "params.gains.ki"
+index = [2, 0, 1]
+type = "f32"
+dims = [3]
+tag = "params"
+"#).unwrap()
+    }
+
+    #[test]
+    fn fields_are_found_by_path() {
+        let meta = generic();
+        let kp = meta.find("params.gains.kp").unwrap();
+        assert_eq!(kp.index, vec![2, 0, 0]);
+        assert_eq!(kp.unit, "1/s");
+        assert_eq!(meta.find("params.gains.ki").unwrap().dims, vec![3]);
+        assert_eq!(meta.find("params.gains.ki").unwrap().unit, "");
+        assert!(meta.find("params.gains").is_err());
+        assert!(meta.find("input.y").is_err());
+    }
+
+    #[test]
+    fn children_are_in_interface_order() {
+        let meta = generic();
+        let paths : Vec<&str> = meta.children("params.gains").iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["params.gains.kp", "params.gains.ki"]);
+        assert_eq!(meta.children("params").len(), 2);
+        assert!(meta.children("params.gain").is_empty());
+    }
+
+    #[test]
+    fn generics_are_specialized() {
+        let meta = generic();
+        assert_eq!(meta.specialize("").unwrap().find("input.x").unwrap().typename, "f64");
+        let spec = meta.specialize("i32").unwrap();
+        assert_eq!(spec.find("input.x").unwrap().typename, "i32");
+        assert_eq!(spec.find("params.gains.kp").unwrap().typename, "f32");
+        assert!(meta.specialize("i32, u8").is_err());
+    }
+
+    #[test]
+    fn malformed_metadata_is_rejected() {
+        assert!(ModelMetadata::parse("generics = []\ndefaults = []").is_err());
+        assert!(ModelMetadata::parse("name = \"m\"\ndefaults = []").is_err());
+        assert!(ModelMetadata::parse("name = \"m\"\ngenerics = []\ndefaults = []\n[[fields]]\npath = \"x\"").is_err());
+    }
+
+    #[test]
+    fn signals_split_into_model_and_field() {
+        assert_eq!(split_signal("gen2.params.amplitude").unwrap(), ("gen2", "params.amplitude"));
+        assert!(split_signal("gen2").is_err());
+        assert!(split_signal(".output").is_err());
+        assert!(split_signal("gen2.").is_err());
+    }
+}
@@ -0,0 +1,432 @@
+
+use crate::plugin::ModelInstance;
+use crate::connection::{InputRoute, OutputRoute};
+use crate::fault::{Fault, FaultOverride, FaultRecord};
+use crate::hash::hash_bytes;
+use crate::trace::{self, ThreadTrace, TraceBuffer, TraceKind};
+use crate::threadcontext::{ThreadContext, ThreadTime};
+use crate::metadata::{split_signal, FieldInfo};
+
+use rmodel::{ConfigStatus, RunStatus, RFrameTime};
+
+/// Field value written through the generated setters: (index, msgpack)
+pub type FieldValue = (Vec<i32>, Vec<u8>);
+
+/// ScheduledModel
+/// A model instance executed by a ModelThread
+pub struct ScheduledModel {
+    pub name : String,
+    pub instance : ModelInstance,
+    /// number of thread frames between model steps
+    pub divisor : i64,
+    /// thread frame of the first model step
+    pub offset : i64,
+    /// scene parameters, applied on creation and reset: (index, msgpack)
+    pub params : Vec<FieldValue>,
+    /// connections read before each step
+    pub inputs : Vec<InputRoute>,
+    /// connections published after each step
+    pub outputs : Vec<OutputRoute>,
+    /// signal overrides, evaluated when the model steps
+    pub faults : Vec<FaultOverride>,
+}
+
+impl ScheduledModel {
+    /// Write a scene parameter, kept to be written again when the model is recreated on reset
+    pub fn set_param(&mut self, index : Vec<i32>, mp : Vec<u8>) -> Result<(), String> {
+        if self.instance.set(&index, &mp) != 0 {
+            return Err(format!("Failed to set parameter {:?} of {}", index, self.name));
+        }
+        // a later value of the same field replaces the earlier one
+        self.params.retain(|(i, _)| *i != index);
+        self.params.push((index, mp));
+        Ok(())
+    }
+
+    pub fn apply_params(&mut self) -> Result<(), String> {
+        for (ind, mp) in self.params.iter() {
+            if self.instance.set(ind, mp) != 0 {
+                return Err(format!("Failed to set parameter {:?} of {}", ind, self.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Update and apply the faults overriding the signals of the model
+    /// @param[in] after - true after the model step, for faults on outputs
+    pub fn apply_faults(&mut self, time : f64, after : bool, log : &mut Vec<FaultRecord>) -> Result<(), String> {
+        // undone in reverse order, so that faults stacked on a signal apply to its own value
+        for f in self.faults.iter_mut().rev().filter(|f| f.after_step() == after) {
+            f.restore(&mut self.instance)?;
+        }
+        for f in self.faults.iter_mut().filter(|f| f.after_step() == after) {
+            if f.update(time, log) {
+                f.apply(time, &mut self.instance)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ModelThread
+/// ThreadContext executing dynamically loaded models in schedule order
+pub struct ModelThread {
+    pub time : ThreadTime,
+    pub tid : usize,
+    pub models : Vec<ScheduledModel>,
+    /// faults that became active or were cleared, in time order
+    pub log : Vec<FaultRecord>,
+    /// spans of the model steps and connections, when the scene is traced
+    pub trace : Option<TraceBuffer>,
+    /// model failures with their simulation time, until taken by the engine
+    pub errors : Vec<(f64, String)>,
+}
+
+impl ModelThread {
+    pub fn new(delta : f64) -> ModelThread {
+        ModelThread {
+            time : ThreadTime { delta, tick : 0 },
+            tid : 0,
+            models : Vec::new(),
+            log : Vec::new(),
+            trace : None,
+            errors : Vec::new(),
+        }
+    }
+
+    /// Order the models so that each model steps after the models it depends on.
+    /// Models without dependencies between them keep their schedule order
+    /// @param[in] deps - (producer, consumer) pairs of direct connections within this thread
+    pub fn order(&mut self, deps : &[(String, String)]) -> Result<(), String> {
+        let names : Vec<&str> = self.models.iter().map(|m| m.name.as_str()).collect();
+        let order = match execution_order(&names, deps) {
+            Ok(o) => o,
+            Err(cycle) => return Err(format!("Algebraic loop on thread {}: {}. Add a delay to one of the connections",
+                self.tid, cycle)),
+        };
+        let mut models : Vec<Option<ScheduledModel>> = self.models.drain(..).map(Some).collect();
+        self.models = order.iter().map(|i| models[*i].take().unwrap()).collect();
+        Ok(())
+    }
+
+    /// Arm a fault on a model of this thread
+    /// @param[in] scene - true if declared by the scene, run time faults are dropped on reset
+    pub fn arm(&mut self, fault : Fault, scene : bool) -> Result<(), String> {
+        let m = self.model_mut(fault.model()?)?;
+        let o = FaultOverride::new(fault, &m.instance.meta, scene)?;
+        m.faults.push(o);
+        Ok(())
+    }
+
+    fn model_mut(&mut self, name : &str) -> Result<&mut ScheduledModel, String> {
+        match self.models.iter_mut().find(|m| m.name == name) {
+            Some(m) => Ok(m),
+            None => Err(format!("Model [{}] is not executed by thread {}", name, self.tid)),
+        }
+    }
+
+    fn frame_time(&self) -> RFrameTime {
+        RFrameTime {
+            time : self.time.tick as f64 * self.time.delta,
+            tick : self.time.tick,
+            delta : self.time.delta,
+        }
+    }
+}
+
+// Kahn's algorithm, always taking the earliest scheduled model that is ready.
+// Returns the model indices in execution order, or the models along one algebraic loop
+fn execution_order(names : &[&str], deps : &[(String, String)]) -> Result<Vec<usize>, String> {
+    let n = names.len();
+    let position = |name : &str| names.iter().position(|m| *m == name);
+    let mut edges : Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut indegree = vec![0; n];
+    for (src, dst) in deps {
+        if let (Some(s), Some(d)) = (position(src), position(dst)) {
+            if !edges[s].contains(&d) {
+                edges[s].push(d);
+                indegree[d] += 1;
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut done = vec![false; n];
+    while let Some(next) = (0..n).find(|i| !done[*i] && indegree[*i] == 0) {
+        done[next] = true;
+        order.push(next);
+        for d in edges[next].iter() {
+            indegree[*d] -= 1;
+        }
+    }
+
+    if order.len() < n {
+        // walk back along unresolved edges until a model repeats to report one loop
+        let mut path = vec![(0..n).find(|i| !done[*i]).unwrap()];
+        loop {
+            let last = *path.last().unwrap();
+            let prev = (0..n).find(|i| !done[*i] && edges[*i].contains(&last)).unwrap();
+            if let Some(start) = path.iter().position(|p| *p == prev) {
+                let mut cycle : Vec<&str> = path[start..].iter().rev().map(|i| names[*i]).collect();
+                cycle.push(cycle[0]);
+                return Err(cycle.join(" -> "));
+            }
+            path.push(prev);
+        }
+    }
+    Ok(order)
+}
+
+impl ThreadContext for ModelThread {
+    fn set_time(&mut self, new_time : ThreadTime) -> ConfigStatus {
+        self.time = new_time;
+        ConfigStatus::OK
+    }
+
+    fn get_time(&self) -> ThreadTime {
+        self.time
+    }
+
+    fn set_tid(&mut self, id : usize) -> ConfigStatus {
+        self.tid = id;
+        ConfigStatus::OK
+    }
+
+    fn get_tid(&self) -> usize {
+        self.tid
+    }
+
+    fn init(&mut self) -> ConfigStatus {
+        let time = self.frame_time();
+        for m in self.models.iter_mut() {
+            match m.instance.config(&time) {
+                ConfigStatus::OK => {},
+                status => {
+                    self.errors.push((time.time, format!("{} config failed", m.name)));
+                    return status;
+                }
+            }
+            match m.instance.init(&time) {
+                ConfigStatus::OK => {},
+                status => {
+                    self.errors.push((time.time, format!("{} init failed", m.name)));
+                    return status;
+                }
+            }
+        }
+        ConfigStatus::OK
+    }
+
+    fn step(&mut self) -> RunStatus {
+        let time = self.frame_time();
+        let mut result = RunStatus::OK;
+        for (i, m) in self.models.iter_mut().enumerate() {
+            if time.tick < m.offset || (time.tick - m.offset) % m.divisor != 0 {
+                continue;
+            }
+            let span = trace::begin(&self.trace);
+            for input in m.inputs.iter() {
+                if let Some(mp) = input.receive(time.time) {
+                    let index = &input.target.field.index;
+                    // elements are written into the current value of the field
+                    let mp = match input.target.elements {
+                        Some(_) => match m.instance.get(index).and_then(|field| input.target.insert(field, &mp)) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                self.errors.push((time.time, format!("{} failed to receive {}: {}", m.name, input.name, e)));
+                                return RunStatus::ERR;
+                            }
+                        },
+                        None => mp,
+                    };
+                    if m.instance.set(index, &mp) != 0 {
+                        self.errors.push((time.time, format!("{} failed to receive {}", m.name, input.name)));
+                        return RunStatus::ERR;
+                    }
+                }
+            }
+            if let Err(e) = m.apply_faults(time.time, false, &mut self.log) {
+                self.errors.push((time.time, format!("{} fault failed: {}", m.name, e)));
+                return RunStatus::ERR;
+            }
+            trace::end(&mut self.trace, span, TraceKind::INPUTS, i);
+            let span = trace::begin(&self.trace);
+            match m.instance.step(&time) {
+                RunStatus::OK => {},
+                RunStatus::STOP => {
+                    result = RunStatus::STOP;
+                },
+                RunStatus::ERR => {
+                    self.errors.push((time.time, format!("{} step failed", m.name)));
+                    return RunStatus::ERR;
+                }
+            }
+            trace::end(&mut self.trace, span, TraceKind::STEP, i);
+            let span = trace::begin(&self.trace);
+            if let Err(e) = m.apply_faults(time.time, true, &mut self.log) {
+                self.errors.push((time.time, format!("{} fault failed: {}", m.name, e)));
+                return RunStatus::ERR;
+            }
+            for output in m.outputs.iter_mut() {
+                match m.instance.get(&output.source.field.index).and_then(|mp| output.publish(time.time, mp)) {
+                    Ok(_) => {},
+                    Err(e) => {
+                        self.errors.push((time.time, format!("{} failed to publish {}: {}", m.name, output.name, e)));
+                        return RunStatus::ERR;
+                    }
+                }
+            }
+            trace::end(&mut self.trace, span, TraceKind::OUTPUTS, i);
+        }
+        self.time.tick += 1;
+        result
+    }
+
+    fn end(&mut self) -> RunStatus {
+        let time = self.frame_time();
+        let mut result = RunStatus::OK;
+        for m in self.models.iter_mut() {
+            if let RunStatus::ERR = m.instance.halt(&time) {
+                self.errors.push((time.time, format!("{} halt failed", m.name)));
+                result = RunStatus::ERR;
+            }
+        }
+        result
+    }
+
+    fn model_names(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.name.clone()).collect()
+    }
+
+    fn fields(&self, name : &str) -> Result<Vec<FieldInfo>, String> {
+        match self.models.iter().find(|m| m.name == name) {
+            Some(m) => Ok(m.instance.meta.fields.clone()),
+            None => Err(format!("Model [{}] is not executed by thread {}", name, self.tid)),
+        }
+    }
+
+    fn get_signal(&mut self, name : &str, path : &str) -> Result<Vec<u8>, String> {
+        self.model_mut(name)?.instance.get_path(path)
+    }
+
+    fn set_signal(&mut self, name : &str, path : &str, mp : &[u8]) -> Result<(), String> {
+        self.model_mut(name)?.instance.set_path(path, mp)
+    }
+
+    fn hash_state(&mut self) -> Result<Vec<(String, u64)>, String> {
+        let mut out = Vec::new();
+        for m in self.models.iter_mut() {
+            let fields : Vec<(String, Vec<i32>)> = m.instance.meta.fields.iter().map(|f| (f.path.clone(), f.index.clone())).collect();
+            for (path, ind) in fields {
+                let mp = m.instance.get(&ind)?;
+                out.push((format!("{}.{}", m.name, path), hash_bytes(&mp)));
+            }
+        }
+        Ok(out)
+    }
+
+    fn inject(&mut self, fault : Fault) -> Result<(), String> {
+        self.arm(fault, false)
+    }
+
+    fn clear_faults(&mut self, signal : &str) -> Result<(), String> {
+        let time = self.frame_time().time;
+        let (name, _) = split_signal(signal)?;
+        let log = &mut self.log;
+        let m = match self.models.iter_mut().find(|m| m.name == name) {
+            Some(m) => m,
+            None => return Err(format!("Model [{}] is not executed by thread {}", name, self.tid)),
+        };
+        let count = m.faults.iter().filter(|f| f.fault.signal == signal).count();
+        if count == 0 {
+            return Err(format!("No fault on [{}]", signal));
+        }
+        for f in m.faults.iter_mut().rev().filter(|f| f.fault.signal == signal) {
+            f.clear(time, log);
+            f.restore(&mut m.instance)?;
+        }
+        // faults of the scene are kept so that a reset arms them again
+        m.faults.retain(|f| f.fault.signal != signal || f.scene);
+        Ok(())
+    }
+
+    fn fault_log(&self) -> Vec<FaultRecord> {
+        self.log.clone()
+    }
+
+    fn tracer(&mut self) -> &mut Option<TraceBuffer> {
+        &mut self.trace
+    }
+
+    fn trace(&self) -> Option<ThreadTrace> {
+        self.trace.as_ref().map(|t| ThreadTrace {
+            tid : self.tid,
+            models : self.model_names(),
+            events : t.events(),
+            dropped : t.dropped(),
+        })
+    }
+
+    fn errors(&mut self) -> Vec<(f64, String)> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn reset(&mut self) -> ConfigStatus {
+        self.log.clear();
+        self.errors.clear();
+        if let Some(t) = self.trace.as_mut() {
+            t.clear();
+        }
+        for m in self.models.iter_mut() {
+            for output in m.outputs.iter_mut() {
+                output.clear();
+            }
+            m.faults.retain(|f| f.scene);
+            for f in m.faults.iter_mut() {
+                f.reset();
+            }
+            let status = m.instance.recreate().and_then(|_| m.apply_params());
+            match status {
+                Ok(_) => {},
+                Err(e) => {
+                    self.errors.push((0.0, format!("{} reset failed: {}", m.name, e)));
+                    return ConfigStatus::ERR;
+                }
+            }
+        }
+        ConfigStatus::OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deps(pairs : &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(s, d)| (s.to_string(), d.to_string())).collect()
+    }
+
+    #[test]
+    fn independent_models_keep_their_schedule_order() {
+        assert_eq!(execution_order(&["a", "b", "c"], &[]), Ok(vec![0, 1, 2]));
+        // models of other threads are not ordered here
+        assert_eq!(execution_order(&["a", "b", "c"], &deps(&[("x", "a"), ("b", "y")])), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn consumers_step_after_their_producers() {
+        assert_eq!(execution_order(&["a", "b", "c"], &deps(&[("c", "a")])), Ok(vec![1, 2, 0]));
+        assert_eq!(execution_order(&["a", "b", "c"], &deps(&[("c", "a"), ("c", "a")])), Ok(vec![1, 2, 0]));
+        assert_eq!(execution_order(&["a", "b", "c"], &deps(&[("b", "a"), ("c", "b")])), Ok(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn algebraic_loops_are_reported() {
+        let names = ["a", "b", "c"];
+        assert_eq!(execution_order(&names, &deps(&[("a", "b"), ("b", "c"), ("c", "a")])), Err("b -> c -> a -> b".to_string()));
+        assert_eq!(execution_order(&names, &deps(&[("a", "a")])), Err("a -> a".to_string()));
+        // the models before and after the loop are not part of it
+        let names = ["a", "b", "c", "d"];
+        assert_eq!(execution_order(&names, &deps(&[("a", "b"), ("b", "c"), ("c", "b"), ("c", "d")])), Err("c -> b -> c".to_string()));
+    }
+}
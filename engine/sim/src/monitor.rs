@@ -0,0 +1,229 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::expr::Condition;
+use crate::scene::parse_duration;
+use crate::TIME_EPS;
+
+use std::collections::HashMap;
+
+/// Violation
+/// First sample at which a monitor failed
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "server", derive(serde::Serialize))]
+pub struct Violation {
+    /// simulation time of the frame that produced the values, seconds
+    pub time : f64,
+    pub message : String,
+    /// signal, value
+    pub values : Vec<(String, String)>,
+}
+
+/// Monitor
+/// Entry of the scene [[monitors]] array, a condition checked at every frame.
+/// Without `when`, `check` must hold from `after` until `until`. With `when`,
+/// `check` must hold within `within` seconds of every rising edge of `when`
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    pub name : String,
+    pub check : Condition,
+    pub when : Option<Condition>,
+    /// seconds
+    pub within : f64,
+    /// start of the checked interval, seconds
+    pub after : f64,
+    /// end of the checked interval, seconds
+    pub until : Option<f64>,
+    pub violation : Option<Violation>,
+    /// value of `when` at the previous sample
+    was_true : bool,
+    /// time of the rising edge of `when` awaiting `check`
+    since : Option<f64>,
+}
+
+impl Monitor {
+    /// Parse an entry of the [[monitors]] array
+    pub fn parse(entry : &toml::Value, ctxt : &str) -> Result<Monitor, String> {
+        let tbl = match entry.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        for key in tbl.keys() {
+            if !["name", "check", "when", "within", "after", "until"].contains(&key.as_str()) {
+                return Err(format!("{}.{} is not a monitor key", ctxt, key));
+            }
+        }
+        let condition = |key : &str| -> Result<Option<Condition>, String> {
+            match tbl.get(key) {
+                Some(toml::Value::String(s)) => match Condition::parse(s) {
+                    Ok(c) => Ok(Some(c)),
+                    Err(e) => Err(format!("{}.{}: {}", ctxt, key, e)),
+                },
+                Some(_) => Err(format!("{}.{} is not a string", ctxt, key)),
+                None => Ok(None),
+            }
+        };
+        let check = match condition("check")? {
+            Some(c) => c,
+            None => return Err(format!("Missing {}.check", ctxt)),
+        };
+        let when = condition("when")?;
+        let within = match (&when, tbl.get("within")) {
+            (Some(_), Some(w)) => parse_duration(w)?,
+            (Some(_), None) => return Err(format!("{}.when needs a within duration", ctxt)),
+            (None, Some(_)) => return Err(format!("{}.within applies to when conditions only", ctxt)),
+            (None, None) => 0.0,
+        };
+        let after = match tbl.get("after") {
+            Some(a) => parse_duration(a)?,
+            None => 0.0,
+        };
+        let until = match tbl.get("until") {
+            Some(u) => Some(parse_duration(u)?),
+            None => None,
+        };
+        if until.is_some_and(|u| u <= after) {
+            return Err(format!("{}.until must be after {}.after", ctxt, ctxt));
+        }
+        let name = match tbl.get("name") {
+            Some(toml::Value::String(s)) => s.clone(),
+            Some(_) => return Err(format!("{}.name is not a string", ctxt)),
+            None => check.text.clone(),
+        };
+        Ok(Monitor {
+            name,
+            check,
+            when,
+            within,
+            after,
+            until,
+            violation : None,
+            was_true : false,
+            since : None,
+        })
+    }
+
+    /// Signals read by the monitor
+    pub fn signals(&self) -> Vec<String> {
+        let mut out = self.check.signals();
+        if let Some(w) = &self.when {
+            for s in w.signals() {
+                if !out.contains(&s) {
+                    out.push(s);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn reset(&mut self) {
+        self.violation = None;
+        self.was_true = false;
+        self.since = None;
+    }
+
+    /// Check the values produced by the frame at a time
+    pub fn evaluate(&mut self, time : f64, values : &HashMap<String, rmpv::Value>) -> Result<(), String> {
+        if self.violation.is_some() {
+            return Ok(());
+        }
+        let checked = time >= self.after - TIME_EPS && self.until.is_none_or(|u| time < u - TIME_EPS);
+        let failed = match &self.when {
+            None => checked && !self.check.holds(values)?,
+            Some(when) => {
+                let now = when.holds(values)?;
+                if checked && now && !self.was_true && self.since.is_none() {
+                    self.since = Some(time);
+                }
+                self.was_true = now;
+                match self.since {
+                    Some(since) => {
+                        if self.check.holds(values)? {
+                            self.since = None;
+                            false
+                        } else {
+                            time >= since + self.within - TIME_EPS
+                        }
+                    },
+                    None => false,
+                }
+            },
+        };
+        if failed {
+            let message = match (&self.when, self.since) {
+                (Some(when), Some(since)) => format!("{} did not hold within {} s of {} at t={:.6}",
+                    self.check.text, self.within, when.text, since),
+                _ => format!("{} does not hold", self.check.text),
+            };
+            let values = self.signals().into_iter()
+                .map(|s| {
+                    let v = values.get(&s).map(|v| v.to_string()).unwrap_or_default();
+                    (s, v)
+                })
+                .collect::<Vec<(String, String)>>();
+            self.violation = Some(Violation {
+                time,
+                message,
+                values,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Result of a monitor at the end of a scenario
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "server", derive(serde::Serialize))]
+pub struct MonitorResult {
+    pub name : String,
+    pub check : String,
+    pub violation : Option<Violation>,
+}
+
+/// Verdict
+/// Pass or fail of a scenario, from its monitors and the engine state
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "server", derive(serde::Serialize))]
+pub struct Verdict {
+    pub monitors : Vec<MonitorResult>,
+    /// set if the engine or the timeline failed
+    pub error : Option<String>,
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.monitors.iter().all(|m| m.violation.is_none())
+    }
+
+    /// Process exit code: 0 if passed, 1 if a monitor failed, 2 if the scenario failed to execute
+    pub fn exit_code(&self) -> i32 {
+        match (&self.error, self.passed()) {
+            (Some(_), _) => 2,
+            (None, true) => 0,
+            (None, false) => 1,
+        }
+    }
+
+    /// One line per monitor and a final PASS or FAIL line
+    pub fn report(&self) -> String {
+        let mut lines = Vec::new();
+        for m in self.monitors.iter() {
+            match &m.violation {
+                Some(v) => {
+                    let shown = v.values.iter().map(|(s, x)| format!("{} = {}", s, x)).collect::<Vec<String>>();
+                    lines.push(format!("FAIL {}: t={:.6} {} ({})", m.name, v.time, v.message, shown.join(", ")));
+                },
+                None => lines.push(format!("pass {}", m.name)),
+            }
+        }
+        if let Some(e) = &self.error {
+            lines.push(format!("ERROR {}", e));
+        }
+        let failed = self.monitors.iter().filter(|m| m.violation.is_some()).count();
+        lines.push(match self.passed() {
+            true => format!("PASS {} monitors", self.monitors.len()),
+            false => format!("FAIL {} of {} monitors", failed, self.monitors.len()),
+        });
+        lines.join("\n")
+    }
+}
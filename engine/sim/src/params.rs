@@ -0,0 +1,298 @@
+extern crate rmpv;
+extern crate serde_json;
+extern crate toml;
+
+use crate::connection::{encode, is_numeric};
+use crate::fault::coerce;
+use crate::metadata::{FieldInfo, ModelMetadata};
+use crate::modelthread::FieldValue;
+use crate::scene::to_msgpack_value;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Parameters
+/// Parameter file applied to model instances before init
+#[derive(Clone, Debug)]
+pub struct Parameters {
+    pub file : PathBuf,
+    /// instance names the file is applied to
+    pub models : Vec<String>,
+    /// scene key the file is listed at, e.g. `[schedule][1].params`
+    pub key : String,
+}
+
+impl Parameters {
+    /// Parse a [[parameters]] entry applying a file to a group of instances
+    /// @param[in] dir - directory that the file is resolved against
+    pub fn parse(val : &toml::Value, ctxt : &str, dir : &Path) -> Result<Parameters, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        for key in tbl.keys() {
+            if !["file", "models"].contains(&key.as_str()) {
+                return Err(format!("{} has unknown key {}", ctxt, key));
+            }
+        }
+        let file = match tbl.get("file").map(|v| v.as_str()) {
+            Some(Some(f)) => dir.join(f),
+            Some(None) => return Err(format!("{}.file is not a string", ctxt)),
+            None => return Err(format!("Missing {}.file", ctxt)),
+        };
+        let mut models = Vec::new();
+        match tbl.get("models").and_then(|v| v.as_array()) {
+            Some(arr) => {
+                for m in arr {
+                    match m.as_str() {
+                        Some(s) => models.push(s.to_string()),
+                        None => return Err(format!("{}.models contains a value that is not a string", ctxt)),
+                    }
+                }
+            },
+            None => return Err(format!("{}.models is not an array of instance names", ctxt)),
+        }
+        Ok(Parameters { file, models, key : format!("{}.file", ctxt) })
+    }
+
+    /// Parse the `params` key of a [[schedule]] entry, a file or an array of files
+    pub fn parse_instance(val : &toml::Value, ctxt : &str, dir : &Path, name : &str) -> Result<Vec<Parameters>, String> {
+        let key = format!("{}.params", ctxt);
+        let files = match val {
+            toml::Value::String(s) => vec![s.as_str()],
+            toml::Value::Array(arr) => {
+                let mut out = Vec::new();
+                for f in arr {
+                    match f.as_str() {
+                        Some(s) => out.push(s),
+                        None => return Err(format!("{} contains a value that is not a string", key)),
+                    }
+                }
+                out
+            },
+            _ => return Err(format!("{} is not a file or an array of files", key)),
+        };
+        Ok(files.into_iter().map(|f| Parameters { file : dir.join(f), models : vec![name.to_string()], key : key.clone() }).collect())
+    }
+
+    /// Read the file, by its extension: .toml, .json, or .msgpack and .mp
+    pub fn read(&self) -> Result<rmpv::Value, String> {
+        let ext = self.file.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let val = match ext {
+            "toml" => fs::read_to_string(&self.file).map_err(|e| e.to_string()).and_then(|txt| {
+                match txt.parse::<toml::Table>() {
+                    Ok(tbl) => from_toml(&toml::Value::Table(tbl)),
+                    Err(e) => Err(e.to_string()),
+                }
+            }),
+            "json" => fs::read_to_string(&self.file).map_err(|e| e.to_string())
+                .and_then(|txt| serde_json::from_str::<rmpv::Value>(&txt).map_err(|e| e.to_string())),
+            "msgpack" | "mp" => fs::read(&self.file).map_err(|e| e.to_string())
+                .and_then(|data| rmpv::decode::read_value(&mut &data[..]).map_err(|e| e.to_string())),
+            _ => Err("not a .toml, .json or .msgpack file".to_string()),
+        };
+        match val {
+            Ok(v) if v.is_map() => Ok(v),
+            Ok(_) => Err(format!("Parameter file {} is not a table of fields", self.file.display())),
+            Err(e) => Err(format!("Unable to read parameter file {}: {}", self.file.display(), e)),
+        }
+    }
+
+    /// Values of the file by field path, checked against the types and dims of a model interface
+    pub fn values(&self, meta : &ModelMetadata) -> Result<Vec<(String, rmpv::Value)>, String> {
+        let mut out = Vec::new();
+        match flatten(&self.read()?, "", meta, &mut out) {
+            Ok(_) => Ok(out),
+            Err(e) => Err(format!("{}: {}", self.file.display(), e)),
+        }
+    }
+
+    /// Values of the file encoded for the generated setters, as the (index, msgpack) parameters of a model
+    pub fn encoded(&self, meta : &ModelMetadata) -> Result<Vec<FieldValue>, String> {
+        let mut out = Vec::new();
+        for (path, val) in self.values(meta)? {
+            let field = meta.find(&path)?;
+            out.push((field.index.clone(), encode(&coerce(&val, &field.typename))?));
+        }
+        Ok(out)
+    }
+}
+
+// converts a parameter file table, which unlike scene values nests tables
+fn from_toml(val : &toml::Value) -> Result<rmpv::Value, String> {
+    match val {
+        toml::Value::Table(tbl) => {
+            let mut out = Vec::new();
+            for (k, v) in tbl.iter() {
+                out.push((rmpv::Value::from(k.as_str()), from_toml(v)?));
+            }
+            Ok(rmpv::Value::Map(out))
+        },
+        toml::Value::Array(arr) => Ok(rmpv::Value::Array(arr.iter().map(from_toml).collect::<Result<Vec<_>, _>>()?)),
+        v => to_msgpack_value(v),
+    }
+}
+
+// collects the leaf fields of the nested tables of a file
+fn flatten(val : &rmpv::Value, path : &str, meta : &ModelMetadata, out : &mut Vec<(String, rmpv::Value)>) -> Result<(), String> {
+    if let Ok(field) = meta.find(path) {
+        check_value(val, &field.dims, field)?;
+        out.push((path.to_string(), val.clone()));
+        return Ok(());
+    }
+    match val.as_map() {
+        Some(entries) => {
+            for (k, v) in entries.iter() {
+                let key = match k.as_str() {
+                    Some(s) => s,
+                    None => return Err(format!("Key {} of [{}] is not a field name", k, path)),
+                };
+                let child = match path.is_empty() {
+                    true => key.to_string(),
+                    false => format!("{}.{}", path, key),
+                };
+                flatten(v, &child, meta, out)?;
+            }
+            Ok(())
+        },
+        None if !meta.children(path).is_empty() => Err(format!("[{}] of model {} is a struct, not {}", path, meta.name, val)),
+        None => meta.find(path).map(|_| ()),
+    }
+}
+
+// checks a value against the element type and the remaining dims of a field
+fn check_value(val : &rmpv::Value, dims : &[i64], field : &FieldInfo) -> Result<(), String> {
+    if let Some((len, rest)) = dims.split_first() {
+        return match val.as_array() {
+            Some(arr) if *len >= 0 && arr.len() as i64 != *len => {
+                Err(format!("[{}] has dims {:?}, {} has {} elements", field.path, field.dims, val, arr.len()))
+            },
+            Some(arr) => arr.iter().try_for_each(|v| check_value(v, rest, field)),
+            None => Err(format!("[{}] has dims {:?}, {} is not an array", field.path, field.dims, val)),
+        };
+    }
+    let t = field.typename.as_str();
+    let valid = match val {
+        rmpv::Value::Boolean(_) => t == "bool",
+        rmpv::Value::Integer(i) if t.starts_with('u') => is_numeric(t) && i.as_u64().is_some(),
+        rmpv::Value::Integer(_) => is_numeric(t),
+        rmpv::Value::F32(_) | rmpv::Value::F64(_) => t == "f32" || t == "f64",
+        rmpv::Value::String(_) => t == "String",
+        rmpv::Value::Array(_) => false,
+        _ => !is_numeric(t) && t != "bool",
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(format!("[{}] is {}, not {}", field.path, t, val)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::decode;
+
+    fn meta() -> ModelMetadata {
+        ModelMetadata::parse(r#"
+name = "imu"
+generics = []
+defaults = []
+[[fields]]
+path = "params.gain"
+index = [3, 0]
+type = "f64"
+dims = []
+tag = "params"
+[[fields]]
+path = "params.bias"
+index = [3, 1]
+type = "f32"
+dims = [3]
+tag = "params"
+[[fields]]
+path = "params.filter.taps"
+index = [3, 2, 0]
+type = "u8"
+dims = [-1]
+tag = "params"
+[[fields]]
+path = "params.label"
+index = [3, 3]
+type = "String"
+dims = []
+tag = "params"
+"#).unwrap()
+    }
+
+    // writes a parameter file to the temporary directory
+    fn file(name : &str, data : &[u8]) -> Parameters {
+        let file = std::env::temp_dir().join(format!("rsis_params_{}_{}", std::process::id(), name));
+        fs::write(&file, data).unwrap();
+        Parameters { file, models : vec!["imu".to_string()], key : "[schedule][0].params".to_string() }
+    }
+
+    fn values(name : &str, txt : &str) -> Result<Vec<(String, rmpv::Value)>, String> {
+        let p = file(name, txt.as_bytes());
+        let v = p.values(&meta());
+        let _ = fs::remove_file(&p.file);
+        v
+    }
+
+    #[test]
+    fn entries_resolve_files_against_the_scene() {
+        let dir = Path::new("/scenes");
+        let val = toml::Value::Table("file = \"imu.toml\"\nmodels = [\"imu1\", \"imu2\"]".parse().unwrap());
+        let p = Parameters::parse(&val, "[parameters][0]", dir).unwrap();
+        assert_eq!((p.file, p.models, p.key), (dir.join("imu.toml"), vec!["imu1".to_string(), "imu2".to_string()], "[parameters][0].file".to_string()));
+        assert!(Parameters::parse(&toml::Value::Table("file = \"imu.toml\"".parse().unwrap()), "[parameters][0]", dir).is_err());
+        assert!(Parameters::parse(&toml::Value::Table("file = 1\nmodels = []".parse().unwrap()), "[parameters][0]", dir).is_err());
+
+        let files = toml::Value::Array(vec![toml::Value::from("a.json"), toml::Value::from("b.mp")]);
+        let ps = Parameters::parse_instance(&files, "[schedule][1]", dir, "imu1").unwrap();
+        assert_eq!(ps.iter().map(|p| p.file.clone()).collect::<Vec<PathBuf>>(), vec![dir.join("a.json"), dir.join("b.mp")]);
+        assert_eq!(ps[1].key, "[schedule][1].params");
+        assert!(Parameters::parse_instance(&toml::Value::from(1), "[schedule][1]", dir, "imu1").is_err());
+    }
+
+    #[test]
+    fn files_of_every_format_give_the_same_values() {
+        let from_toml = values("same.toml", "[params]\ngain = 2.5\nbias = [0.1, 0.2, 0.3]\nlabel = \"x\"\n[params.filter]\ntaps = [1, 2]").unwrap();
+        let from_json = values("same.json", r#"{"params": {"bias": [0.1, 0.2, 0.3], "filter": {"taps": [1, 2]}, "gain": 2.5, "label": "x"}}"#).unwrap();
+        let mut mp = Vec::new();
+        rmpv::encode::write_value(&mut mp, &rmpv::Value::Map(vec![(rmpv::Value::from("params"), rmpv::Value::Map(vec![(rmpv::Value::from("gain"), rmpv::Value::F64(2.5))]))])).unwrap();
+        let p = file("same.mp", &mp);
+        let from_msgpack = p.values(&meta()).unwrap();
+        let _ = fs::remove_file(&p.file);
+
+        let paths = |v : &Vec<(String, rmpv::Value)>| v.iter().map(|(p, _)| p.clone()).collect::<Vec<String>>();
+        assert_eq!(paths(&from_toml), vec!["params.bias", "params.filter.taps", "params.gain", "params.label"]);
+        assert_eq!(from_toml, from_json);
+        assert_eq!(from_msgpack, vec![("params.gain".to_string(), rmpv::Value::F64(2.5))]);
+    }
+
+    #[test]
+    fn values_are_checked_against_the_interface() {
+        assert!(values("unknown.toml", "[params]\noffset = 1.0").is_err());
+        assert!(values("type.toml", "[params]\nlabel = 1").is_err());
+        assert!(values("dims.toml", "[params]\nbias = [0.1, 0.2]").is_err());
+        assert!(values("scalar.toml", "[params]\nbias = 0.1").is_err());
+        assert!(values("unsigned.toml", "[params.filter]\ntaps = [1, -2]").is_err());
+        assert!(values("struct.toml", "[params]\nfilter = 1").is_err());
+        assert!(values("array.toml", "params = [1]").is_err());
+        assert!(values("params.yaml", "params: {}").is_err());
+        let e = values("unknown.json", r#"{"params": {"offset": 1.0}}"#).unwrap_err();
+        assert!(e.contains("unknown.json"), "{}", e);
+    }
+
+    #[test]
+    fn values_are_encoded_as_their_field_type() {
+        let p = file("encoded.toml", b"[params]\ngain = 2\n[params.filter]\ntaps = [3]");
+        let encoded = p.encoded(&meta()).unwrap();
+        let _ = fs::remove_file(&p.file);
+        assert_eq!(encoded.len(), 2);
+        assert_eq!(encoded[0].0, vec![3, 2, 0]);
+        assert_eq!(decode(&encoded[0].1).unwrap(), rmpv::Value::Array(vec![rmpv::Value::from(3)]));
+        assert_eq!(encoded[1].0, vec![3, 0]);
+        assert_eq!(decode(&encoded[1].1).unwrap(), rmpv::Value::F64(2.0));
+    }
+}
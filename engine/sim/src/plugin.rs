@@ -0,0 +1,281 @@
+extern crate libloading;
+
+use libloading::Library;
+use rmodel::{ConfigStatus, RunStatus, RFrameTime, RSIS_ABI_VERSION};
+
+use crate::metadata::ModelMetadata;
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type MetadataFn   = unsafe extern "C" fn() -> *const c_char;
+type NewFn        = unsafe extern "C" fn(*const c_char) -> *mut c_void;
+type DeleteFn     = unsafe extern "C" fn(*mut c_void);
+type CallFn       = unsafe extern "C" fn(*mut c_void, *const RFrameTime) -> u32;
+type GetFn        = unsafe extern "C" fn(*mut c_void, *const i32, usize, *mut u8, usize) -> i64;
+type SetFn        = unsafe extern "C" fn(*mut c_void, *const i32, usize, *const u8, usize) -> i32;
+
+fn to_config_status(val : u32) -> ConfigStatus {
+    match val {
+        0 => ConfigStatus::OK,
+        2 => ConfigStatus::CONTINUE,
+        _ => ConfigStatus::ERR,
+    }
+}
+
+fn to_run_status(val : u32) -> RunStatus {
+    match val {
+        0 => RunStatus::OK,
+        2 => RunStatus::STOP,
+        _ => RunStatus::ERR,
+    }
+}
+
+/// ModelLibrary
+/// Model plugin loaded from a shared library generated with the
+/// `plugin.rs` target of rsiswrap
+pub struct ModelLibrary {
+    pub name : String,
+    pub path : PathBuf,
+    /// flattened interface description, TOML
+    pub metadata : String,
+    /// parsed interface description, generic types unresolved
+    pub interface : ModelMetadata,
+
+    new : NewFn,
+    delete : DeleteFn,
+    config : CallFn,
+    init : CallFn,
+    step : CallFn,
+    halt : CallFn,
+    get : GetFn,
+    set : SetFn,
+
+    // the function pointers above are only valid while the library is loaded
+    _lib : Library,
+}
+
+impl ModelLibrary {
+    /// Load the library at the path and check its ABI version
+    pub fn open(name : &str, path : &Path) -> Result<ModelLibrary, String> {
+        unsafe {
+            let lib = match Library::new(path) {
+                Ok(l) => l,
+                Err(e) => return Err(format!("Failed to load model library {}: {}", path.display(), e)),
+            };
+            let version = match lib.get::<AbiVersionFn>(b"rsis_abi_version\0") {
+                Ok(f) => f(),
+                Err(_) => return Err(format!("{} is not an RSIS model library", path.display())),
+            };
+            if version != RSIS_ABI_VERSION {
+                return Err(format!("Model library {} has ABI version {}, expected {}",
+                    path.display(), version, RSIS_ABI_VERSION));
+            }
+            let metadata = match lib.get::<MetadataFn>(b"rsis_metadata\0") {
+                Ok(f) => CStr::from_ptr(f()).to_string_lossy().into_owned(),
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            };
+            let interface = ModelMetadata::parse(&metadata)?;
+            Ok(ModelLibrary {
+                name : name.to_string(),
+                path : path.to_path_buf(),
+                metadata,
+                interface,
+                new : *symbol(&lib, path, b"rsis_new\0")?,
+                delete : *symbol(&lib, path, b"rsis_delete\0")?,
+                config : *symbol(&lib, path, b"rsis_config\0")?,
+                init : *symbol(&lib, path, b"rsis_init\0")?,
+                step : *symbol(&lib, path, b"rsis_step\0")?,
+                halt : *symbol(&lib, path, b"rsis_halt\0")?,
+                get : *symbol(&lib, path, b"rsis_get\0")?,
+                set : *symbol(&lib, path, b"rsis_set\0")?,
+                _lib : lib,
+            })
+        }
+    }
+
+    /// Create a model instance
+    /// @param[in] generic - comma separated generic specialization, empty for the default
+    pub fn create(self : &Arc<Self>, generic : &str) -> Result<ModelInstance, String> {
+        let meta = self.interface.specialize(generic)?;
+        let spec = match CString::new(generic) {
+            Ok(s) => s,
+            Err(_) => return Err(format!("Invalid specialization: {}", generic)),
+        };
+        let obj = unsafe { (self.new)(spec.as_ptr()) };
+        if obj.is_null() {
+            return Err(format!("Model library {} does not support specialization [{}]", self.name, generic));
+        }
+        Ok(ModelInstance {
+            lib : Arc::clone(self),
+            obj,
+            generic : generic.to_string(),
+            meta,
+        })
+    }
+}
+
+unsafe fn symbol<'a, T>(lib : &'a Library, path : &Path, name : &[u8]) -> Result<libloading::Symbol<'a, T>, String> {
+    match lib.get::<T>(name) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// ModelInstance
+/// A model object owned by a ModelLibrary
+pub struct ModelInstance {
+    lib : Arc<ModelLibrary>,
+    obj : *mut c_void,
+    pub generic : String,
+    /// interface description for this specialization
+    pub meta : ModelMetadata,
+}
+
+// the instance is only ever accessed by the thread that owns it
+unsafe impl Send for ModelInstance {}
+
+impl ModelInstance {
+    pub fn library(&self) -> &Arc<ModelLibrary> {
+        &self.lib
+    }
+
+    pub fn config(&mut self, time : &RFrameTime) -> ConfigStatus {
+        to_config_status(unsafe { (self.lib.config)(self.obj, time) })
+    }
+
+    pub fn init(&mut self, time : &RFrameTime) -> ConfigStatus {
+        to_config_status(unsafe { (self.lib.init)(self.obj, time) })
+    }
+
+    pub fn step(&mut self, time : &RFrameTime) -> RunStatus {
+        to_run_status(unsafe { (self.lib.step)(self.obj, time) })
+    }
+
+    pub fn halt(&mut self, time : &RFrameTime) -> RunStatus {
+        to_run_status(unsafe { (self.lib.halt)(self.obj, time) })
+    }
+
+    /// Encode the element at the index as MessagePack
+    pub fn get(&mut self, ind : &[i32]) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0u8; 64];
+        loop {
+            let len = unsafe { (self.lib.get)(self.obj, ind.as_ptr(), ind.len(), buf.as_mut_ptr(), buf.len()) };
+            if len < 0 {
+                return Err(format!("Failed to get {:?} from {}", ind, self.lib.name));
+            }
+            let len = len as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            // retry with a buffer that fits
+            buf.resize(len, 0);
+        }
+    }
+
+    /// Decode MessagePack into the element at the index, returns 0 on success
+    pub fn set(&mut self, ind : &[i32], mp : &[u8]) -> i32 {
+        unsafe { (self.lib.set)(self.obj, ind.as_ptr(), ind.len(), mp.as_ptr(), mp.len()) }
+    }
+
+    /// Encode the field at the dotted path as MessagePack
+    pub fn get_path(&mut self, path : &str) -> Result<Vec<u8>, String> {
+        let ind = self.meta.find(path)?.index.clone();
+        self.get(&ind)
+    }
+
+    /// Decode MessagePack into the field at the dotted path
+    pub fn set_path(&mut self, path : &str, mp : &[u8]) -> Result<(), String> {
+        let ind = self.meta.find(path)?.index.clone();
+        match self.set(&ind, mp) {
+            0 => Ok(()),
+            1 | 2 => Err(format!("Index {:?} of {}.{} is not a field of the model", ind, self.lib.name, path)),
+            3 => Err(format!("Failed to decode value for {}.{}", self.lib.name, path)),
+            _ => Err(format!("Failed to set {}.{}", self.lib.name, path)),
+        }
+    }
+
+    /// Replace the model object with a newly created one
+    pub fn recreate(&mut self) -> Result<(), String> {
+        let fresh = self.lib.create(&self.generic)?;
+        *self = fresh;
+        Ok(())
+    }
+}
+
+impl Drop for ModelInstance {
+    fn drop(&mut self) {
+        unsafe { (self.lib.delete)(self.obj) };
+    }
+}
+
+/// PluginLoader
+/// Locates model libraries by name in the configured search paths.
+/// Paths in the `RSIS_MODEL_PATH` environment variable are searched last.
+/// Each library is loaded once and shared between instances
+pub struct PluginLoader {
+    pub paths : Vec<PathBuf>,
+    libraries : HashMap<String, Arc<ModelLibrary>>,
+}
+
+impl Default for PluginLoader {
+    fn default() -> PluginLoader {
+        PluginLoader::new()
+    }
+}
+
+impl PluginLoader {
+    pub fn new() -> PluginLoader {
+        PluginLoader {
+            paths : Vec::new(),
+            libraries : HashMap::new(),
+        }
+    }
+
+    pub fn add_path<P : AsRef<Path>>(&mut self, path : P) {
+        let p = path.as_ref().to_path_buf();
+        if !self.paths.contains(&p) {
+            self.paths.push(p);
+        }
+    }
+
+    /// Platform specific file name of a model library
+    pub fn library_filename(name : &str) -> String {
+        format!("{}{}{}", env::consts::DLL_PREFIX, name, env::consts::DLL_SUFFIX)
+    }
+
+    /// Returns the first match for the library in the search paths
+    pub fn find(&self, name : &str) -> Option<PathBuf> {
+        let filename = PluginLoader::library_filename(name);
+        let mut paths = self.paths.clone();
+        if let Some(envpaths) = env::var_os("RSIS_MODEL_PATH") {
+            paths.extend(env::split_paths(&envpaths));
+        }
+        for p in paths {
+            let candidate = p.join(&filename);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    pub fn load(&mut self, name : &str) -> Result<Arc<ModelLibrary>, String> {
+        if let Some(lib) = self.libraries.get(name) {
+            return Ok(Arc::clone(lib));
+        }
+        let path = match self.find(name) {
+            Some(p) => p,
+            None => return Err(format!("Model library [{}] ({}) not found in search paths {:?}",
+                name, PluginLoader::library_filename(name), self.paths)),
+        };
+        let lib = Arc::new(ModelLibrary::open(name, &path)?);
+        self.libraries.insert(name.to_string(), Arc::clone(&lib));
+        Ok(lib)
+    }
+}
@@ -0,0 +1,368 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::metadata::split_signal;
+use crate::scene::parse_duration;
+use crate::snapshot::{ModelState, StateReport};
+use crate::threadcontext::ThreadContext;
+use crate::timeline::{flatten, SignalLog};
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Signals kept by the flight recorder
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recorded {
+    /// every signal of the scene logs
+    LOGGED,
+    /// every field tagged `out` of every model
+    OUT,
+    LIST(Vec<String>),
+}
+
+/// Recorder
+/// Flight recorder of the scene [recorder] table. Keeps the last seconds of signals
+/// and engine events in memory, and writes them to files when the run errors, a
+/// monitor fails, a frame overruns the watchdog, or on demand. Each dump is joined
+/// by a report of the state of every model, captured at the frame boundary
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    /// seconds of history kept
+    pub duration : f64,
+    pub recorded : Recorded,
+    /// base name of the dumps, numbered from 0 in each run
+    pub file : PathBuf,
+    /// wall time a frame may take before the recorder is dumped, seconds
+    pub watchdog : Option<f64>,
+    /// write the state of every model with each dump
+    pub state : bool,
+    /// recorded signals, in column order
+    pub signals : Vec<String>,
+    rows : VecDeque<(f64, Vec<rmpv::Value>)>,
+    events : VecDeque<(f64, String)>,
+    /// models whose outputs are recorded
+    resolved : Vec<String>,
+    /// time of the last recorded frame
+    now : f64,
+    dumps : usize,
+    last_dump : Option<(f64, String)>,
+    last_boundary : Option<Instant>,
+    /// state reports of the dumps at the current frame boundary, completed by each context
+    reports : Vec<StateReport>,
+}
+
+impl Recorder {
+    /// Parse the [recorder] table
+    /// @param[in] name - default file name, without the extension
+    pub fn parse(val : &toml::Value, name : &str) -> Result<Recorder, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err("[recorder] is not a table".to_string()),
+        };
+        for key in tbl.keys() {
+            if !["duration", "signals", "file", "watchdog", "state"].contains(&key.as_str()) {
+                return Err(format!("[recorder].{} is not a recorder setting", key));
+            }
+        }
+        let duration = match tbl.get("duration") {
+            Some(d) => parse_duration(d)?,
+            None => 10.0,
+        };
+        if duration <= 0.0 {
+            return Err("[recorder].duration must be positive".to_string());
+        }
+        let recorded = match tbl.get("signals") {
+            None => Recorded::LOGGED,
+            Some(toml::Value::String(s)) if s == "logged" => Recorded::LOGGED,
+            Some(toml::Value::String(s)) if s == "out" => Recorded::OUT,
+            Some(toml::Value::Array(arr)) => {
+                let mut signals = Vec::new();
+                for v in arr {
+                    match v.as_str() {
+                        Some(s) => {
+                            split_signal(s)?;
+                            signals.push(s.to_string());
+                        },
+                        None => return Err("[recorder].signals contains a value that is not a string".to_string()),
+                    }
+                }
+                Recorded::LIST(signals)
+            },
+            Some(_) => return Err("[recorder].signals must be \"logged\", \"out\" or an array of signals".to_string()),
+        };
+        let file = match tbl.get("file") {
+            Some(toml::Value::String(f)) => PathBuf::from(f),
+            Some(_) => return Err("[recorder].file is not a string".to_string()),
+            None => PathBuf::from(format!("{}_recorder.csv", name)),
+        };
+        let watchdog = match tbl.get("watchdog") {
+            Some(w) => Some(parse_duration(w)?),
+            None => None,
+        };
+        let state = match tbl.get("state") {
+            Some(toml::Value::Boolean(b)) => *b,
+            Some(_) => return Err("[recorder].state is not a boolean".to_string()),
+            None => true,
+        };
+        let signals = match &recorded {
+            Recorded::LIST(s) => s.clone(),
+            _ => Vec::new(),
+        };
+        Ok(Recorder {
+            duration,
+            recorded,
+            file,
+            watchdog,
+            state,
+            signals,
+            rows : VecDeque::new(),
+            events : VecDeque::new(),
+            resolved : Vec::new(),
+            now : 0.0,
+            dumps : 0,
+            last_dump : None,
+            last_boundary : None,
+            reports : Vec::new(),
+        })
+    }
+
+    /// Record the signals of the scene logs
+    pub fn watch_logs(&mut self, logs : &[SignalLog]) {
+        if self.recorded != Recorded::LOGGED {
+            return;
+        }
+        for s in logs.iter().flat_map(|l| l.signals.iter()) {
+            if !self.signals.contains(s) {
+                self.signals.push(s.clone());
+            }
+        }
+    }
+
+    /// Record the outputs of the models of a context, once per model
+    pub fn watch_outputs(&mut self, tc : &dyn ThreadContext, names : &[String]) -> Result<(), String> {
+        if self.recorded != Recorded::OUT {
+            return Ok(());
+        }
+        for name in names {
+            if self.resolved.contains(name) {
+                continue;
+            }
+            for f in tc.fields(name)?.iter().filter(|f| f.tag == "out") {
+                self.signals.push(format!("{}.{}", name, f.path));
+            }
+            self.resolved.push(name.clone());
+        }
+        Ok(())
+    }
+
+    /// Keep the values a frame produced, dropping those older than the duration
+    pub fn record(&mut self, time : f64, values : &HashMap<String, rmpv::Value>) {
+        let row = self.signals.iter().map(|s| values.get(s).cloned().unwrap_or(rmpv::Value::Nil)).collect();
+        self.rows.push_back((time, row));
+        self.now = time;
+        while self.rows.front().is_some_and(|(t, _)| *t < time - self.duration) {
+            self.rows.pop_front();
+        }
+        while self.events.front().is_some_and(|(t, _)| *t < time - self.duration) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Keep an engine event, at the time of the last recorded frame
+    pub fn event(&mut self, text : &str) {
+        self.events.push_back((self.now, text.to_string()));
+    }
+
+    /// Wall time of the frame that ended at this boundary, if it overran the watchdog
+    pub fn overrun(&mut self) -> Option<f64> {
+        let now = Instant::now();
+        let elapsed = self.last_boundary.map(|b| now.duration_since(b).as_secs_f64());
+        self.last_boundary = Some(now);
+        match (elapsed, self.watchdog) {
+            (Some(e), Some(w)) if e > w => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Stop timing frames until the next boundary, while the engine does not execute frames
+    pub fn pause(&mut self) {
+        self.last_boundary = None;
+    }
+
+    /// Write the history to `<file>_<n>.csv` and its events to `<file>_<n>_events.csv`,
+    /// returning the signal file, and start the state report `<file>_<n>_state.toml`.
+    /// The same reason at the same time is only written once
+    pub fn dump(&mut self, reason : &str) -> Result<PathBuf, String> {
+        let key = (self.now, reason.to_string());
+        if self.last_dump.as_ref() == Some(&key) {
+            return Ok(self.numbered(self.dumps - 1, "", "csv"));
+        }
+        self.event(&format!("recorder dumped: {}", reason));
+        let file = self.numbered(self.dumps, "", "csv");
+        let events = self.numbered(self.dumps, "_events", "csv");
+
+        let mut header = vec!["time".to_string()];
+        let mut lines = Vec::new();
+        for (i, (time, row)) in self.rows.iter().enumerate() {
+            let mut cells = vec![((time * 1e9).round() / 1e9).to_string()];
+            let mut names = Vec::new();
+            for (s, v) in self.signals.iter().zip(row.iter()) {
+                flatten(s, v, &mut names, &mut cells);
+            }
+            if i == 0 {
+                header.extend(names);
+            }
+            lines.push(cells.join(","));
+        }
+        lines.insert(0, header.join(","));
+        if let Err(e) = fs::write(&file, lines.join("\n") + "\n") {
+            return Err(format!("Unable to write recorder {}: {}", file.display(), e));
+        }
+        let mut txt = "time,event\n".to_string();
+        for (time, e) in self.events.iter() {
+            txt.push_str(&format!("{},\"{}\"\n", (time * 1e9).round() / 1e9, e.replace('"', "\"\"")));
+        }
+        if let Err(e) = fs::write(&events, txt) {
+            return Err(format!("Unable to write recorder {}: {}", events.display(), e));
+        }
+        if self.state {
+            self.reports.push(StateReport::new(self.numbered(self.dumps, "_state", "toml"), self.now, reason));
+        }
+        self.dumps += 1;
+        self.last_dump = Some(key);
+        Ok(file)
+    }
+
+    /// Number of dumps written in this run
+    pub fn dumps(&self) -> usize {
+        self.dumps
+    }
+
+    /// True if dumps at the current frame boundary wait for the state of the models
+    pub fn capturing(&self) -> bool {
+        !self.reports.is_empty()
+    }
+
+    /// Add models to the state reports of the dumps at the current frame boundary and rewrite them
+    pub fn add_state(&mut self, models : Vec<ModelState>) -> Result<(), String> {
+        for r in self.reports.iter_mut() {
+            r.add(models.clone());
+            r.write()?;
+        }
+        Ok(())
+    }
+
+    /// Stop capturing state at a new frame boundary
+    pub fn settle(&mut self) {
+        self.reports.clear();
+    }
+
+    fn numbered(&self, n : usize, suffix : &str, ext : &str) -> PathBuf {
+        let stem = self.file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        self.file.with_file_name(format!("{}_{}{}.{}", stem, n, suffix, ext))
+    }
+
+    /// Return to the state before the scenario started, dumps are rewritten by the next run
+    pub fn reset(&mut self) {
+        self.rows.clear();
+        self.events.clear();
+        self.now = 0.0;
+        self.dumps = 0;
+        self.last_dump = None;
+        self.last_boundary = None;
+        self.reports.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder(txt : &str) -> Result<Recorder, String> {
+        Recorder::parse(&toml::Value::Table(txt.parse::<toml::Table>().unwrap()), "st")
+    }
+
+    fn values(x : f64) -> HashMap<String, rmpv::Value> {
+        let v = rmpv::Value::Array(vec![rmpv::Value::F64(x), rmpv::Value::F64(-x)]);
+        HashMap::from([("m.x".to_string(), rmpv::Value::F64(x)), ("m.v".to_string(), v)])
+    }
+
+    #[test]
+    fn recorders_parse_with_defaults() {
+        let r = recorder("").unwrap();
+        assert_eq!((r.duration, r.recorded, r.watchdog, r.state), (10.0, Recorded::LOGGED, None, true));
+        assert_eq!(r.file, PathBuf::from("st_recorder.csv"));
+        let r = recorder("duration = \"500 ms\"\nsignals = [\"m.x\"]\nwatchdog = 0.01").unwrap();
+        assert_eq!((r.duration, r.watchdog), (0.5, Some(0.01)));
+        assert_eq!(r.signals, vec!["m.x"]);
+        assert_eq!(recorder("signals = \"out\"").unwrap().recorded, Recorded::OUT);
+        assert!(recorder("duration = 0").is_err());
+        assert!(recorder("signals = \"all\"").is_err());
+        assert!(recorder("signals = [\"x\"]").is_err());
+        assert!(recorder("state = 1").is_err());
+        assert!(recorder("rate = 1").is_err());
+    }
+
+    #[test]
+    fn logged_signals_are_recorded_once() {
+        let logs = [
+            SignalLog::parse(&toml::Value::Table("rate = 1\nsignals = [\"m.x\", \"m.v\"]".parse().unwrap()), "logging[0]", "a").unwrap(),
+            SignalLog::parse(&toml::Value::Table("rate = 1\nsignals = [\"m.x\"]".parse().unwrap()), "logging[1]", "b").unwrap(),
+        ];
+        let mut r = recorder("").unwrap();
+        r.watch_logs(&logs);
+        assert_eq!(r.signals, vec!["m.x", "m.v"]);
+        let mut r = recorder("signals = [\"m.y\"]").unwrap();
+        r.watch_logs(&logs);
+        assert_eq!(r.signals, vec!["m.y"]);
+    }
+
+    #[test]
+    fn dumps_keep_the_last_seconds() {
+        let dir = std::env::temp_dir().join(format!("rsis_recorder_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut r = recorder("duration = 0.25\nsignals = [\"m.x\", \"m.v\"]\nstate = false").unwrap();
+        r.file = dir.join("rec.csv");
+        for i in 0..5 {
+            r.record(i as f64 * 0.1, &values(i as f64));
+            if i == 1 {
+                r.event("early");
+            }
+        }
+        r.event("late");
+        let file = r.dump("on demand").unwrap();
+        assert_eq!(file, dir.join("rec_0.csv"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "time,m.x,m.v[0],m.v[1]\n0.2,2,2,-2\n0.3,3,3,-3\n0.4,4,4,-4\n");
+        let events = fs::read_to_string(dir.join("rec_0_events.csv")).unwrap();
+        assert_eq!(events, "time,event\n0.4,\"late\"\n0.4,\"recorder dumped: on demand\"\n");
+        assert!(!r.capturing());
+
+        // the same reason at the same time is not dumped again
+        assert_eq!(r.dump("on demand").unwrap(), file);
+        assert_eq!(r.dumps(), 1);
+        r.record(0.5, &values(5.0));
+        assert_eq!(r.dump("on demand").unwrap(), dir.join("rec_1.csv"));
+        assert_eq!(r.dumps(), 2);
+
+        r.reset();
+        r.record(0.0, &values(1.0));
+        assert_eq!(r.dump("again").unwrap(), dir.join("rec_0.csv"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "time,m.x,m.v[0],m.v[1]\n0,1,1,-1\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_watchdog_times_frames_between_boundaries() {
+        let mut r = recorder("watchdog = 1e-9").unwrap();
+        assert_eq!(r.overrun(), None);
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        assert!(r.overrun().is_some_and(|e| e >= 1e-3));
+        r.pause();
+        assert_eq!(r.overrun(), None);
+        let mut r = recorder("").unwrap();
+        r.overrun();
+        assert_eq!(r.overrun(), None);
+    }
+}
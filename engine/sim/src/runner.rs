@@ -0,0 +1,203 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::compose::Composition;
+use crate::engine::Engine;
+use crate::fault::FaultRecord;
+use crate::metadata::split_signal;
+use crate::monitor::Verdict;
+use crate::plugin::PluginLoader;
+use crate::scene::{parse_duration, to_msgpack_value};
+use crate::serial::start_serial_engine;
+use crate::{start_engine_boxed, TIME_EPS};
+use crate::state::EngineState;
+use crate::trace::write_chrome_trace;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// an asynchronous engine changes state when it accepts a command, so the
+// state it leaves once the command completes is waited on
+fn wait_while(engine : &mut Box<dyn Engine + Send>, states : &[EngineState]) {
+    while states.contains(&engine.get_state()) {
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Wait for init to complete on an asynchronous engine, true if it initialized
+pub fn wait_init(engine : &mut Box<dyn Engine + Send>) -> bool {
+    wait_while(engine, &[EngineState::INITIALIZING]);
+    engine.get_state() == EngineState::INITIALIZED
+}
+
+/// Execute frames until the target time, returning once an asynchronous engine is idle again
+pub fn advance(engine : &mut Box<dyn Engine + Send>, target : f64) {
+    if engine.run_until(target) == 0 {
+        wait_while(engine, &[EngineState::RUNNING, EngineState::ENDING]);
+    }
+}
+
+/// End the run, waiting for the threads of an asynchronous engine to end
+pub fn finish(engine : &mut Box<dyn Engine + Send>) {
+    if engine.end() == 0 {
+        wait_while(engine, &[EngineState::ENDING]);
+    }
+}
+
+/// Execute frames until the stop time, resuming timeline pauses as there is no one to resume them.
+/// The verdict errors if the run stopped before the stop time without being ended by a model or the timeline
+pub fn run_to_stop(engine : &mut Box<dyn Engine + Send>, stop : f64) -> Verdict {
+    let mut reached = -1.0;
+    while matches!(engine.get_state(), EngineState::INITIALIZED | EngineState::PAUSED)
+        && engine.get_time() < stop - TIME_EPS && engine.get_time() > reached {
+        reached = engine.get_time();
+        advance(engine, stop);
+    }
+    let mut verdict = engine.verdict();
+    let time = engine.get_time();
+    if time < stop - TIME_EPS && verdict.error.is_none() && engine.get_state() != EngineState::ENDED {
+        verdict.error = Some(format!("Run stopped at t={:.6} before the stop time t={:.6}", time, stop));
+    }
+    verdict
+}
+
+/// Why the engine failed to initialize, from the model failures in its events
+pub fn init_failure(engine : &mut dyn Engine) -> String {
+    let failures : Vec<String> = engine.events().into_iter().map(|(_, e)| e).collect();
+    match failures.is_empty() {
+        true => "Engine failed to initialize".to_string(),
+        false => format!("Engine failed to initialize: {}", failures.join(", ")),
+    }
+}
+
+/// Parse a time in seconds, e.g. "2.5", or with a unit, e.g. "2 min"
+pub fn parse_time(txt : &str) -> Result<f64, String> {
+    match txt.trim().parse::<f64>() {
+        Ok(t) => Ok(t),
+        Err(_) => parse_duration(&toml::Value::String(txt.to_string())),
+    }
+}
+
+/// Parse `signal=value`, where the value is written as in the scene file,
+/// e.g. `gen1.params.amplitude=2` or `imu.bias=[0.1, 0.0, 0.0]`. Other values are strings
+pub fn parse_assignment(txt : &str) -> Result<(String, rmpv::Value), String> {
+    let (signal, val) = match txt.split_once('=') {
+        Some((s, v)) => (s.trim(), v.trim()),
+        None => return Err(format!("{} is not signal=value", txt)),
+    };
+    split_signal(signal)?;
+    Ok((signal.to_string(), to_msgpack_value(&parse_value(val))?))
+}
+
+/// Parse a value written as in the scene file, e.g. `2`, `"2 s"` or `[0.1, 0.0]`.
+/// Other values are strings
+pub fn parse_value(txt : &str) -> toml::Value {
+    match format!("value = {}", txt.trim()).parse::<toml::Table>() {
+        Ok(mut tbl) => tbl.remove("value").unwrap_or(toml::Value::String(txt.to_string())),
+        Err(_) => toml::Value::String(txt.trim().to_string()),
+    }
+}
+
+/// Parse `key=value` overriding a value of the scene file, e.g. `scene.stop=10`
+pub fn parse_override(txt : &str) -> Result<(String, toml::Value), String> {
+    match txt.split_once('=') {
+        Some((k, v)) => Ok((k.trim().to_string(), parse_value(v))),
+        None => Err(format!("{} is not key=value", txt)),
+    }
+}
+
+/// RunOptions
+/// Changes to a scene for a headless run
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// simulation time to run, seconds, the scene stop time if None
+    pub stop : Option<f64>,
+    /// directory of the logs, hashes, trace and recorder dumps
+    pub output : Option<PathBuf>,
+    /// seed of the fault noise
+    pub seed : Option<u64>,
+    /// scene files applied on top of the scene, in order
+    pub overlays : Vec<PathBuf>,
+    /// values of the scene file replaced after the overlays, by dotted key
+    pub overrides : Vec<(String, toml::Value)>,
+    /// values written before init, after the scene
+    pub set : Vec<(String, rmpv::Value)>,
+    /// execute on a SerialEngine instead of a SimEngine
+    pub serial : bool,
+    /// pace the frames to wall time, SimEngine only
+    pub real_time : bool,
+}
+
+/// RunOutcome
+/// How a headless run ended
+#[derive(Clone, Debug)]
+pub struct RunOutcome {
+    /// simulation time reached, seconds
+    pub time : f64,
+    pub verdict : Verdict,
+    /// faults that became active or were cleared, in time order
+    pub faults : Vec<FaultRecord>,
+    /// events of the timeline, in time order
+    pub events : Vec<(f64, String)>,
+}
+
+/// Load a scene with its includes, then apply the overlays and overrides of the options
+pub fn compose_scene(scene_file : &Path, options : &RunOptions) -> Result<Composition, String> {
+    let mut c = Composition::load(scene_file)?;
+    for o in options.overlays.iter() {
+        c.overlay(o)?;
+    }
+    for (key, val) in options.overrides.iter() {
+        c.set(key, val.clone())?;
+    }
+    Ok(c)
+}
+
+/// Build a scene, run it until its stop time and end it
+pub fn run_scene(scene_file : &Path, options : &RunOptions) -> Result<RunOutcome, String> {
+    let mut scene = compose_scene(scene_file, options)?.scene()?;
+    let stop = match options.stop {
+        Some(s) => s,
+        None => scene.stop,
+    };
+    if stop <= 0.0 {
+        return Err(format!("Scene {} has no stop time, set one with --stop", scene_file.display()));
+    }
+    if let Some(dir) = &options.output {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(format!("Unable to create {}: {}", dir.display(), e));
+        }
+        scene.relocate(dir);
+    }
+    if let Some(seed) = options.seed {
+        scene.reseed(seed);
+    }
+    scene.initial.extend(options.set.iter().cloned());
+
+    let mut loader = PluginLoader::new();
+    let tcs = scene.build(&mut loader)?;
+    let mut engine : Box<dyn Engine + Send> = match options.serial {
+        true => Box::new(start_serial_engine(tcs)?),
+        false => start_engine_boxed(tcs, options.real_time)?,
+    };
+    engine.set_timeline(scene.timeline());
+    if engine.init() != 0 || !wait_init(&mut engine) {
+        let e = init_failure(engine.as_mut());
+        finish(&mut engine);
+        return Err(e);
+    }
+    let verdict = run_to_stop(&mut engine, stop);
+    let outcome = RunOutcome {
+        time : engine.get_time(),
+        verdict,
+        faults : engine.fault_log()?,
+        events : engine.events(),
+    };
+    if let Some(t) = &scene.tracing {
+        write_chrome_trace(&t.file, &engine.trace()?)?;
+    }
+    finish(&mut engine);
+    Ok(outcome)
+}
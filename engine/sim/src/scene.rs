@@ -0,0 +1,695 @@
+extern crate toml;
+
+use crate::modelthread::{ModelThread, ScheduledModel};
+use crate::fault::{coerce, Fault, FaultKind, FaultOverride};
+use crate::monitor::Monitor;
+use crate::hash::StateHasher;
+use crate::recorder::{Recorded, Recorder};
+use crate::trace::{TraceBuffer, Tracing};
+use crate::timeline::{Action, SignalLog, Timeline, TimelineEntry};
+use crate::connection::{encode, parse_slices, Connection, ConnectionBuffer, InputRoute, OutputRoute, RateTransition, SampleGrid, Selection, Transform};
+use crate::plugin::PluginLoader;
+use crate::threadcontext::ThreadContext;
+use crate::metadata::split_signal;
+use crate::validate::Problem;
+use crate::compose::Composition;
+use crate::params::Parameters;
+use crate::TIME_EPS;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Model instance entry of the [[schedule]] array
+pub struct ModelEntry {
+    pub lib : String,
+    pub name : String,
+    pub freq : f64,
+    pub thread : usize,
+    pub offset : i64,
+    /// comma separated generic specialization, empty for the default
+    pub generic : String,
+}
+
+/// Thread entry of the [[threads]] array
+pub struct ThreadEntry {
+    pub freq : f64,
+}
+
+/// Scene
+/// Scenario definition loaded from a scene file
+pub struct Scene {
+    pub name : String,
+    pub desc : String,
+    pub engine : String,
+    /// seconds, zero if not defined
+    pub stop : f64,
+    /// model library search paths
+    pub paths : Vec<PathBuf>,
+    pub threads : Vec<ThreadEntry>,
+    pub schedule : Vec<ModelEntry>,
+    pub connections : Vec<Connection>,
+    /// signal overrides armed when the scene is built
+    pub faults : Vec<Fault>,
+    /// scripted actions, in the order of the [[timeline]] array
+    pub timeline : Vec<TimelineEntry>,
+    pub logging : Vec<SignalLog>,
+    /// conditions checked at every frame, deciding the verdict of the scenario
+    pub monitors : Vec<Monitor>,
+    /// parameter files written through the model setters when the scene is built, groups
+    /// first, then the files of the [[schedule]] entries
+    pub parameters : Vec<Parameters>,
+    /// signal values written through the model setters when the scene is built, before init
+    pub initial : Vec<(String, rmpv::Value)>,
+    /// hashes of the model states, for determinism checks
+    pub hashing : Option<StateHasher>,
+    /// timing spans of every thread, written as a Chrome trace
+    pub tracing : Option<Tracing>,
+    /// flight recorder dumped when the run fails
+    pub recorder : Option<Recorder>,
+}
+
+fn get_str(tbl : &toml::Table, key : &str, ctxt : &str) -> Result<String, String> {
+    match tbl.get(key) {
+        Some(v) => match v.as_str() {
+            Some(s) => Ok(s.to_string()),
+            None => Err(format!("{}.{} is not a string", ctxt, key)),
+        },
+        None => Err(format!("Missing {}.{}", ctxt, key)),
+    }
+}
+
+fn get_float(tbl : &toml::Table, key : &str, ctxt : &str) -> Result<f64, String> {
+    match tbl.get(key) {
+        Some(toml::Value::Float(f)) => Ok(*f),
+        Some(toml::Value::Integer(i)) => Ok(*i as f64),
+        Some(_) => Err(format!("{}.{} is not a number", ctxt, key)),
+        None => Err(format!("Missing {}.{}", ctxt, key)),
+    }
+}
+
+/// Parse a duration into seconds, either a number or a string such as "2 minute" or "2min"
+pub fn parse_duration(val : &toml::Value) -> Result<f64, String> {
+    match val {
+        toml::Value::Float(f) => Ok(*f),
+        toml::Value::Integer(i) => Ok(*i as f64),
+        toml::Value::String(txt) => {
+            let mut toks : Vec<&str> = txt.split_whitespace().collect();
+            // the unit is the trailing letters of a single token
+            if toks.len() == 1 {
+                let split = toks[0].rfind(|c : char| !c.is_alphabetic()).map_or(0, |i| i + 1);
+                toks = vec![&toks[0][..split], &toks[0][split..]];
+            }
+            if toks.len() != 2 {
+                return Err(format!("Duration parsing failed: {}", txt));
+            }
+            let num : f64 = match toks[0].parse() {
+                Ok(n) => n,
+                Err(_) => return Err(format!("Duration parsing failed: {}", txt)),
+            };
+            let scale = match toks[1] {
+                "ms" | "millisecond" | "milliseconds" => 1e-3,
+                "s" | "sec" | "second" | "seconds" => 1.0,
+                "min" | "minute" | "minutes" => 60.0,
+                "hr" | "hour" | "hours" => 3600.0,
+                "d" | "day" | "days" => 86400.0,
+                _ => return Err(format!("Duration has unknown unit: {}", txt)),
+            };
+            Ok(num * scale)
+        },
+        _ => Err(format!("Duration is not a number or string: {}", val)),
+    }
+}
+
+/// Convert a scene value into the MessagePack value of a model field
+pub fn to_msgpack_value(val : &toml::Value) -> Result<rmpv::Value, String> {
+    match val {
+        toml::Value::Integer(i) => Ok(rmpv::Value::from(*i)),
+        toml::Value::Float(f) => Ok(rmpv::Value::F64(*f)),
+        toml::Value::Boolean(b) => Ok(rmpv::Value::Boolean(*b)),
+        toml::Value::String(s) => Ok(rmpv::Value::from(s.as_str())),
+        toml::Value::Array(arr) => {
+            let mut out = Vec::new();
+            for v in arr {
+                out.push(to_msgpack_value(v)?);
+            }
+            Ok(rmpv::Value::Array(out))
+        },
+        _ => Err(format!("Value {} has no model field equivalent", val)),
+    }
+}
+
+/// Parse scene file contents without checking the schedule against the threads
+/// @param[in] dir - directory that relative paths are resolved against
+pub fn parse_scene_unchecked(contents : &str, dir : &Path) -> Result<Scene, String> {
+    let data = match contents.parse::<toml::Table>() {
+        Ok(d) => d,
+        Err(e) => return Err(format!("Failed to parse scene: {}", e)),
+    };
+    scene_from_table(&data, dir)
+}
+
+/// Scene of parsed scene tables, without checking the schedule against the threads
+/// @param[in] dir - directory that relative paths are resolved against
+pub fn scene_from_table(data : &toml::Table, dir : &Path) -> Result<Scene, String> {
+    let st = match data.get("scene").and_then(|v| v.as_table()) {
+        Some(t) => t,
+        None => return Err("Missing [scene] table".to_string()),
+    };
+    let mut scene = Scene {
+        name : get_str(st, "name", "[scene]")?,
+        desc : "".to_string(),
+        engine : get_str(st, "engine", "[scene]")?,
+        stop : 0.0,
+        paths : Vec::new(),
+        threads : Vec::new(),
+        schedule : Vec::new(),
+        connections : Vec::new(),
+        faults : Vec::new(),
+        timeline : Vec::new(),
+        logging : Vec::new(),
+        monitors : Vec::new(),
+        parameters : Vec::new(),
+        initial : Vec::new(),
+        hashing : None,
+        tracing : None,
+        recorder : None,
+    };
+    if st.contains_key("desc") {
+        scene.desc = get_str(st, "desc", "[scene]")?;
+    }
+    if let Some(stop) = st.get("stop") {
+        scene.stop = parse_duration(stop)?;
+    }
+    if let Some(paths) = st.get("paths") {
+        match paths.as_array() {
+            Some(arr) => {
+                for p in arr {
+                    match p.as_str() {
+                        Some(txt) => scene.paths.push(dir.join(txt)),
+                        None => return Err("[scene].paths contains a value that is not a string".to_string()),
+                    }
+                }
+            },
+            None => return Err("[scene].paths is not an array".to_string()),
+        }
+    }
+
+    if let Some(threads) = data.get("threads") {
+        let arr = match threads.as_array() {
+            Some(a) => a,
+            None => return Err("[threads] is not an array of tables".to_string()),
+        };
+        for (i, t) in arr.iter().enumerate() {
+            let ctxt = format!("[threads][{}]", i);
+            let tbl = match t.as_table() {
+                Some(tbl) => tbl,
+                None => return Err(format!("{} is not a table", ctxt)),
+            };
+            let freq = get_float(tbl, "freq", &ctxt)?;
+            if freq <= 0.0 {
+                return Err(format!("{}.freq must be positive", ctxt));
+            }
+            scene.threads.push(ThreadEntry { freq });
+        }
+    }
+
+    let mut instance_params = Vec::new();
+    if let Some(schedule) = data.get("schedule") {
+        let arr = match schedule.as_array() {
+            Some(a) => a,
+            None => return Err("[schedule] is not an array of tables".to_string()),
+        };
+        for (i, m) in arr.iter().enumerate() {
+            let ctxt = format!("[schedule][{}]", i);
+            let tbl = match m.as_table() {
+                Some(tbl) => tbl,
+                None => return Err(format!("{} is not a table", ctxt)),
+            };
+            let mut entry = ModelEntry {
+                lib : get_str(tbl, "lib", &ctxt)?,
+                name : get_str(tbl, "name", &ctxt)?,
+                freq : get_float(tbl, "freq", &ctxt)?,
+                thread : 0,
+                offset : 0,
+                generic : "".to_string(),
+            };
+            if entry.freq <= 0.0 {
+                return Err(format!("{}.freq must be positive", ctxt));
+            }
+            if let Some(t) = tbl.get("thread") {
+                match t.as_integer() {
+                    Some(v) if v >= 0 => entry.thread = v as usize,
+                    _ => return Err(format!("{}.thread is not a valid thread index", ctxt)),
+                }
+            }
+            if let Some(o) = tbl.get("offset") {
+                match o.as_integer() {
+                    Some(v) if v >= 0 => entry.offset = v,
+                    _ => return Err(format!("{}.offset is not a valid frame offset", ctxt)),
+                }
+            }
+            if tbl.contains_key("generic") {
+                entry.generic = get_str(tbl, "generic", &ctxt)?;
+            }
+            if let Some(p) = tbl.get("params") {
+                instance_params.extend(Parameters::parse_instance(p, &ctxt, dir, &entry.name)?);
+            }
+            scene.schedule.push(entry);
+        }
+    }
+    if let Some(parameters) = data.get("parameters") {
+        let arr = match parameters.as_array() {
+            Some(a) => a,
+            None => return Err("[parameters] is not an array of tables".to_string()),
+        };
+        for (i, p) in arr.iter().enumerate() {
+            scene.parameters.push(Parameters::parse(p, &format!("[parameters][{}]", i), dir)?);
+        }
+    }
+    scene.parameters.extend(instance_params);
+
+    if let Some(connections) = data.get("connections") {
+        let tbl = match connections.as_table() {
+            Some(t) => t,
+            None => return Err("[connections] is not a table".to_string()),
+        };
+        for (models, fields) in tbl {
+            let arr = match fields.as_array() {
+                Some(a) => a,
+                None => return Err(format!("[connections].\"{}\" is not an array", models)),
+            };
+            for f in arr {
+                scene.connections.push(Connection::parse(models, f)?);
+            }
+        }
+    }
+
+    if let Some(faults) = data.get("faults") {
+        let arr = match faults.as_array() {
+            Some(a) => a,
+            None => return Err("[faults] is not an array of tables".to_string()),
+        };
+        for (i, f) in arr.iter().enumerate() {
+            scene.faults.push(Fault::parse(f, &format!("[faults][{}]", i))?);
+        }
+    }
+
+    if let Some(timeline) = data.get("timeline") {
+        let arr = match timeline.as_array() {
+            Some(a) => a,
+            None => return Err("[timeline] is not an array of tables".to_string()),
+        };
+        for (i, e) in arr.iter().enumerate() {
+            scene.timeline.push(TimelineEntry::parse(e, &format!("[timeline][{}]", i))?);
+        }
+    }
+
+    if let Some(logging) = data.get("logging") {
+        let arr = match logging.as_array() {
+            Some(a) => a,
+            None => return Err("[logging] is not an array of tables".to_string()),
+        };
+        for (i, l) in arr.iter().enumerate() {
+            let name = match arr.len() {
+                1 => scene.name.clone(),
+                _ => format!("{}_{}", scene.name, i),
+            };
+            scene.logging.push(SignalLog::parse(l, &format!("[logging][{}]", i), &name)?);
+        }
+    }
+
+    if let Some(monitors) = data.get("monitors") {
+        let arr = match monitors.as_array() {
+            Some(a) => a,
+            None => return Err("[monitors] is not an array of tables".to_string()),
+        };
+        for (i, m) in arr.iter().enumerate() {
+            scene.monitors.push(Monitor::parse(m, &format!("[monitors][{}]", i))?);
+        }
+    }
+
+    if let Some(hashing) = data.get("hashing") {
+        scene.hashing = Some(StateHasher::parse(hashing)?);
+    }
+    if let Some(tracing) = data.get("tracing") {
+        scene.tracing = Some(Tracing::parse(tracing, &scene.name)?);
+    }
+    if let Some(recorder) = data.get("recorder") {
+        scene.recorder = Some(Recorder::parse(recorder, &scene.name)?);
+    }
+
+    // without explicit threads, run everything on one thread at the fastest model rate
+    if scene.threads.is_empty() {
+        let freq = scene.schedule.iter().map(|m| m.freq).fold(0.0, f64::max);
+        if freq > 0.0 {
+            scene.threads.push(ThreadEntry { freq });
+        }
+    }
+    Ok(scene)
+}
+
+/// Parse scene file contents, returning the first problem of the scene if it has any
+/// @param[in] dir - directory that relative paths are resolved against
+pub fn parse_scene(contents : &str, dir : &Path) -> Result<Scene, String> {
+    parse_scene_unchecked(contents, dir)?.checked()
+}
+
+/// Load a scene file with the fragments it includes
+pub fn load_scene<P : AsRef<Path>>(path : P) -> Result<Scene, String> {
+    Composition::load(path.as_ref())?.scene()
+}
+
+// thread index, thread delta and model of a scheduled instance
+fn find_model<'a>(threads : &'a mut [ModelThread], name : &str) -> Result<(usize, f64, &'a mut ScheduledModel), String> {
+    for (i, t) in threads.iter_mut().enumerate() {
+        let delta = t.time.delta;
+        if let Some(m) = t.models.iter_mut().find(|m| m.name == name) {
+            return Ok((i, delta, m));
+        }
+    }
+    Err(format!("[connections] model [{}] is not scheduled", name))
+}
+
+// checks that the signal is a leaf field of a scheduled model
+fn check_signal(threads : &mut [ModelThread], signal : &str, ctxt : &str) -> Result<(), String> {
+    let (name, path) = split_signal(signal)?;
+    let (_, _, m) = match find_model(threads, name) {
+        Ok(m) => m,
+        Err(_) => return Err(format!("{} model [{}] is not scheduled", ctxt, name)),
+    };
+    match m.instance.meta.find(path) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{} {}", ctxt, e)),
+    }
+}
+
+// writes a value into a leaf field or its selected elements, kept as a parameter of the model for reset
+fn write_initial(m : &mut ScheduledModel, signal : &str, val : &rmpv::Value) -> Result<(), String> {
+    let (_, path) = split_signal(signal)?;
+    let (path, slices) = parse_slices(path)?;
+    let field = m.instance.meta.find(path)?;
+    let target = Selection::new(field, &slices)?;
+    let value = encode(&coerce(val, &field.typename))?;
+    let index = target.field.index.clone();
+    let mp = target.insert(m.instance.get(&index)?, &value)?;
+    match m.set_param(index, mp) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("{} does not match the field type {}", val, target.field.typename)),
+    }
+}
+
+fn sample_grid(delta : f64, m : &ScheduledModel) -> SampleGrid {
+    SampleGrid {
+        period : m.divisor as f64 * delta,
+        phase : m.offset as f64 * delta,
+    }
+}
+
+impl Scene {
+    /// Problems of the schedule: duplicate instance names, undefined threads and
+    /// rates that are not integer divisions of the thread rates
+    pub fn check(&self) -> Vec<Problem> {
+        let mut out = Vec::new();
+        // threads step on the frames of the fastest thread
+        let fastest = self.threads.iter().map(|t| t.freq).fold(0.0, f64::max);
+        for (i, t) in self.threads.iter().enumerate() {
+            let ratio = fastest / t.freq;
+            if (ratio - ratio.round()).abs() > 1e-9 {
+                out.push(Problem::new(&format!("[threads][{}].freq", i),
+                    &format!("{} is not an integer division of the fastest thread freq {}", t.freq, fastest)));
+            }
+        }
+        for (i, m) in self.schedule.iter().enumerate() {
+            if self.schedule[..i].iter().any(|e| e.name == m.name) {
+                out.push(Problem::new(&format!("[schedule][{}].name", i), &format!("[{}] is already scheduled", m.name)));
+            }
+            if m.thread >= self.threads.len() {
+                out.push(Problem::new(&format!("[schedule][{}].thread", i),
+                    &format!("{} is scheduled on thread {}, which is not defined", m.name, m.thread)));
+            } else if let Err(e) = self.divisor(m) {
+                out.push(Problem::new(&format!("[schedule][{}].freq", i), &e));
+            }
+        }
+        for (i, p) in self.parameters.iter().enumerate().filter(|(_, p)| p.key.starts_with("[parameters]")) {
+            for name in p.models.iter().filter(|n| !self.schedule.iter().any(|m| m.name == **n)) {
+                out.push(Problem::new(&format!("[parameters][{}].models", i), &format!("model [{}] is not scheduled", name)));
+            }
+        }
+        out
+    }
+
+    /// The scene if it has no problems, the first problem otherwise
+    pub fn checked(self) -> Result<Scene, String> {
+        match self.check().first() {
+            Some(p) => Err(p.to_string()),
+            None => Ok(self),
+        }
+    }
+
+    /// Number of thread frames between steps of a model
+    pub fn divisor(&self, m : &ModelEntry) -> Result<i64, String> {
+        let ratio = self.threads[m.thread].freq / m.freq;
+        let div = ratio.round();
+        if div < 1.0 || (ratio - div).abs() > 1e-9 {
+            return Err(format!("{} freq {} is not an integer division of thread {} freq {}",
+                m.name, m.freq, m.thread, self.threads[m.thread].freq));
+        }
+        Ok(div as i64)
+    }
+
+    /// Load the model libraries and create the scheduled model instances,
+    /// returning one ModelThread per scene thread
+    pub fn build_threads(&self, loader : &mut PluginLoader) -> Result<Vec<ModelThread>, String> {
+        for p in self.paths.iter() {
+            loader.add_path(p);
+        }
+        let mut threads : Vec<ModelThread> = self.threads.iter().map(|t| ModelThread::new(1.0 / t.freq)).collect();
+        // every thread measures its spans from the same instant
+        let epoch = Instant::now();
+        for (i, t) in threads.iter_mut().enumerate() {
+            t.tid = i;
+            t.trace = self.tracing.as_ref().map(|tr| TraceBuffer::new(tr.capacity, epoch));
+        }
+        for m in self.schedule.iter() {
+            let divisor = self.divisor(m)?;
+            let lib = loader.load(&m.lib)?;
+            let instance = lib.create(&m.generic)?;
+            threads[m.thread].models.push(ScheduledModel {
+                name : m.name.clone(),
+                instance,
+                divisor,
+                offset : m.offset,
+                params : Vec::new(),
+                inputs : Vec::new(),
+                outputs : Vec::new(),
+                faults : Vec::new(),
+            });
+        }
+
+        // every connection is exchanged through a time stamped buffer. direct connections
+        // are consumed in the frame they are produced, which orders the models of a thread.
+        // threads execute a frame concurrently, so only connections within a thread can be direct
+        let mut deps : Vec<Vec<(String, String)>> = vec![Vec::new(); threads.len()];
+        for c in self.connections.iter() {
+            let (dst_thread, dst_delta, dst) = find_model(&mut threads, &c.dst_model)?;
+            let consumer = sample_grid(dst_delta, dst);
+            let dst_meta = dst.instance.meta.clone();
+
+            let (src_thread, src_delta, src) = find_model(&mut threads, &c.src_model)?;
+            let producer = sample_grid(src_delta, src);
+            let pairs = match c.resolve(&src.instance.meta, &dst_meta) {
+                Ok(p) => p,
+                Err(e) => return Err(format!("[connections] {}", e)),
+            };
+
+            let delay = match c.delay {
+                Some(0) if src_thread != dst_thread => {
+                    return Err(format!("[connections] {} -> {}: connections between threads {} and {} need a delay of at least 1",
+                        c.src(), c.dst(), src_thread, dst_thread));
+                },
+                Some(d) => d,
+                // the same default on any thread, so that moving models does not change results
+                None => 1,
+            };
+            if delay == 0 {
+                deps[src_thread].push((c.src_model.clone(), c.dst_model.clone()));
+            }
+
+            let faster = producer.period < consumer.period - TIME_EPS;
+            match c.mode {
+                RateTransition::HOLD if faster && !producer.divides(&consumer) => {
+                    return Err(format!("[connections] {} -> {}: hold requires {} to step whenever {} does",
+                        c.src(), c.dst(), c.src_model, c.dst_model));
+                },
+                RateTransition::QUEUE => {
+                    if !producer.divides(&consumer) {
+                        return Err(format!("[connections] {} -> {}: queue requires {} to step whenever {} does",
+                            c.src(), c.dst(), c.src_model, c.dst_model));
+                    }
+                    let len = (consumer.period / producer.period).round() as i64;
+                    for (s, d) in pairs.iter() {
+                        let shape = d.shape();
+                        if shape.is_empty() || (shape[0] != len && shape[0] != -1) || shape[1..] != s.shape()[..] {
+                            return Err(format!("[connections] {} -> {}: queue delivers {} samples of dims {:?}, [{}] selects dims {:?}",
+                                c.src(), c.dst(), len, s.dims, d.field.path, d.dims));
+                        }
+                    }
+                },
+                _ => {},
+            }
+
+            let history = delay.saturating_sub(1) as f64 * consumer.period;
+            for (s, d) in pairs {
+                let transform = match Transform::new(c, &s.field, &d.field) {
+                    Ok(t) => t,
+                    Err(e) => return Err(format!("[connections] {}", e)),
+                };
+                let buffer = Arc::new(ConnectionBuffer::new(history));
+                let mut route = OutputRoute::new(c.dst(), s, Arc::clone(&buffer), c.mode, producer, consumer, delay == 0);
+                route.transform = transform;
+                let (_, _, src) = find_model(&mut threads, &c.src_model)?;
+                src.outputs.push(route);
+                let (_, _, dst) = find_model(&mut threads, &c.dst_model)?;
+                dst.inputs.push(InputRoute {
+                    name : c.src(),
+                    target : d,
+                    buffer,
+                    delay,
+                    period : consumer.period,
+                });
+            }
+        }
+        for (t, d) in threads.iter_mut().zip(deps.iter()) {
+            t.order(d)?;
+        }
+
+        for p in self.parameters.iter() {
+            for name in p.models.iter() {
+                let (_, _, m) = match find_model(&mut threads, name) {
+                    Ok(m) => m,
+                    Err(_) => return Err(format!("{} model [{}] is not scheduled", p.key, name)),
+                };
+                let values = match p.encoded(&m.instance.meta) {
+                    Ok(v) => v,
+                    Err(e) => return Err(format!("{} {}", p.key, e)),
+                };
+                // kept with the model, so that reset writes them again
+                for (index, mp) in values {
+                    if let Err(e) = m.set_param(index, mp) {
+                        return Err(format!("{} {}: {}", p.key, p.file.display(), e));
+                    }
+                }
+            }
+        }
+        for (signal, val) in self.initial.iter() {
+            let (name, _) = split_signal(signal)?;
+            let (_, _, m) = match find_model(&mut threads, name) {
+                Ok(m) => m,
+                Err(_) => return Err(format!("Initial value of [{}]: model [{}] is not scheduled", signal, name)),
+            };
+            if let Err(e) = write_initial(m, signal, val) {
+                return Err(format!("Initial value of [{}]: {}", signal, e));
+            }
+        }
+
+        for (i, f) in self.faults.iter().enumerate() {
+            let name = f.model()?;
+            let t = match threads.iter_mut().find(|t| t.models.iter().any(|m| m.name == name)) {
+                Some(t) => t,
+                None => return Err(format!("[faults][{}] model [{}] is not scheduled", i, name)),
+            };
+            if let Err(e) = t.arm(f.clone(), true) {
+                return Err(format!("[faults][{}] {}", i, e));
+            }
+        }
+        self.check_timeline(&mut threads)?;
+        Ok(threads)
+    }
+
+    // checks that the signals of the timeline, logs and monitors exist, so that it does not fail while running
+    fn check_timeline(&self, threads : &mut [ModelThread]) -> Result<(), String> {
+        for (i, e) in self.timeline.iter().enumerate() {
+            let ctxt = format!("[timeline][{}]", i);
+            for s in e.signals() {
+                check_signal(threads, &s, &ctxt)?;
+            }
+            for a in e.actions.iter() {
+                match a {
+                    Action::SET(s, _) | Action::CLEAR(s) => check_signal(threads, s, &ctxt)?,
+                    Action::FAULT(f, _) => {
+                        // resolved against the model interface without arming it
+                        let name = f.model()?;
+                        let (_, _, m) = match find_model(threads, name) {
+                            Ok(m) => m,
+                            Err(_) => return Err(format!("{} model [{}] is not scheduled", ctxt, name)),
+                        };
+                        if let Err(e) = FaultOverride::new(f.clone(), &m.instance.meta, false) {
+                            return Err(format!("{} {}", ctxt, e));
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        for (i, l) in self.logging.iter().enumerate() {
+            for s in l.signals.iter() {
+                check_signal(threads, s, &format!("[logging][{}]", i))?;
+            }
+        }
+        for (i, m) in self.monitors.iter().enumerate() {
+            for s in m.signals() {
+                check_signal(threads, &s, &format!("[monitors][{}]", i))?;
+            }
+        }
+        if let Some(Recorded::LIST(signals)) = self.recorder.as_ref().map(|r| &r.recorded) {
+            for s in signals {
+                check_signal(threads, s, "[recorder]")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the logs, hashes, trace and recorder dumps of the scene into a directory
+    pub fn relocate(&mut self, dir : &Path) {
+        let moved = |f : &Path| dir.join(f.file_name().unwrap_or_default());
+        for l in self.logging.iter_mut() {
+            l.file = moved(&l.file);
+        }
+        if let Some(f) = self.hashing.as_mut().and_then(|h| h.file.as_mut()) {
+            *f = moved(f);
+        }
+        if let Some(t) = self.tracing.as_mut() {
+            t.file = moved(&t.file);
+        }
+        if let Some(r) = self.recorder.as_mut() {
+            r.file = moved(&r.file);
+        }
+    }
+
+    /// Change the noise of the faults of the scene, the same seed repeats the same noise
+    pub fn reseed(&mut self, seed : u64) {
+        for f in self.faults.iter_mut() {
+            if let FaultKind::NOISE(_) = f.kind {
+                f.seed ^= seed;
+            }
+        }
+    }
+
+    /// Timeline of the scene, to be set on the engine before init
+    pub fn timeline(&self) -> Timeline {
+        let mut tl = Timeline::new(self.timeline.clone(), self.logging.clone(), self.monitors.clone());
+        tl.hasher = self.hashing.clone();
+        tl.recorder = self.recorder.clone();
+        if let Some(r) = tl.recorder.as_mut() {
+            r.watch_logs(&self.logging);
+        }
+        tl
+    }
+
+    /// Build the scene into contexts that can be passed to an engine
+    pub fn build(&self, loader : &mut PluginLoader) -> Result<Vec<Box<dyn ThreadContext + Send>>, String> {
+        let threads = self.build_threads(loader)?;
+        Ok(threads.into_iter().map(|t| Box::new(t) as Box<dyn ThreadContext + Send>).collect())
+    }
+}
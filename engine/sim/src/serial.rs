@@ -0,0 +1,345 @@
+
+use crate::state::EngineState;
+use crate::engine::Engine;
+use crate::connection::decode;
+use crate::threadcontext::{ThreadContext, ThreadTime};
+use crate::metadata::split_signal;
+use crate::fault::{Fault, FaultRecord};
+use crate::hash::FrameHash;
+use crate::monitor::Verdict;
+use crate::timeline::{Halt, Timeline};
+use crate::trace::{self, ThreadTrace, TraceKind};
+use crate::snapshot;
+
+use rmodel::{ConfigStatus, RunStatus};
+
+use std::path::PathBuf;
+
+/// SerialEngine
+/// Executes every ThreadContext cooperatively on the calling thread.
+/// Each frame steps the contexts that are due in thread id order, matching the frame
+/// semantics of SimEngine so that both backends produce identical results.
+/// Commands are executed before returning, there is no soft real-time behavior.
+pub struct SerialEngine {
+    pub state : EngineState,
+    pub contexts : Vec<Box<dyn ThreadContext + Send>>,
+
+    // number of frames executed since init, and the duration of a frame
+    pub frame : u64,
+    pub delta : f64,
+
+    /// scripted actions and logs, evaluated at frame boundaries
+    pub timeline : Timeline,
+}
+
+impl SerialEngine {
+    fn context_of(&mut self, name : &str) -> Result<&mut Box<dyn ThreadContext + Send>, String> {
+        match self.contexts.iter_mut().find(|tc| tc.model_names().iter().any(|n| n == name)) {
+            Some(tc) => Ok(tc),
+            None => Err(format!("Model [{}] not found", name)),
+        }
+    }
+
+    // samples the watched signals of every context, evaluates the timeline
+    // and executes the resulting actions, returning why it halts the engine
+    fn boundary(&mut self, frame : u64) -> Option<Halt> {
+        let mut status = Ok(());
+        for tc in self.contexts.iter_mut() {
+            let names = tc.model_names();
+            status = status.and_then(|_| self.timeline.sample(tc.as_mut(), &names));
+        }
+        if status.is_ok() {
+            self.timeline.boundary(frame, self.delta);
+            for tc in self.contexts.iter_mut() {
+                let names = tc.model_names();
+                status = status.and_then(|_| self.timeline.apply(tc.as_mut(), &names));
+            }
+        }
+        if let Err(e) = status {
+            self.timeline.fail(format!("Timeline failed at frame {}: {}", frame, e));
+        }
+        self.timeline.halt
+    }
+
+    // calls end on every context, regardless of the result of the others
+    fn shutdown(&mut self) {
+        self.state = EngineState::ENDING;
+        let mut errored = false;
+        for tc in self.contexts.iter_mut() {
+            if let RunStatus::ERR = tc.end() {
+                errored = true;
+            }
+            self.timeline.model_failures(tc.errors());
+        }
+        if errored {
+            self.state = EngineState::ERRORED;
+        } else {
+            self.state = EngineState::ENDED;
+        }
+    }
+}
+
+impl Engine for SerialEngine {
+
+    fn get_state(&self) -> EngineState {
+        self.state
+    }
+
+    fn get_time(&self) -> f64 {
+        self.frame as f64 * self.delta
+    }
+
+    fn init(&mut self) -> i32 {
+        if self.state != EngineState::CONFIG {
+            return 1; // invalid state
+        }
+        self.state = EngineState::INITIALIZING;
+        for tc in self.contexts.iter_mut() {
+            let status = tc.init();
+            self.timeline.model_failures(tc.errors());
+            match status {
+                ConfigStatus::OK => {},
+                _ => {
+                    self.state = EngineState::ERRORED;
+                    return 2; // a model failed to configure or initialize
+                }
+            }
+        }
+        self.state = EngineState::INITIALIZED;
+        0
+    }
+
+    fn step(&mut self, steps: u64) -> i32 {
+        match self.state {
+            EngineState::INITIALIZED | EngineState::PAUSED => {},
+            _ => {
+                return 1; // invalid state
+            }
+        }
+        self.state = EngineState::RUNNING;
+
+        let timed = !self.timeline.is_empty();
+        self.timeline.halt = None;
+        let mut halt = None;
+        let mut result = RunStatus::OK;
+        for _ in 0..steps {
+            let frame = self.frame;
+            if timed {
+                halt = self.boundary(frame);
+                if halt.is_some() {
+                    break;
+                }
+            }
+            self.frame += 1;
+            // every context completes the frame before the result is acted on,
+            // slower contexts only step on the engine frames that start one of their frames
+            for tc in self.contexts.iter_mut() {
+                if !frame.is_multiple_of(tc.get_time().frames(self.delta)) {
+                    continue;
+                }
+                let status = tc.step();
+                self.timeline.model_failures(tc.errors());
+                match status {
+                    RunStatus::OK => {},
+                    RunStatus::STOP => {
+                        match result {
+                            RunStatus::ERR => {},
+                            _ => {
+                                result = RunStatus::STOP;
+                            }
+                        }
+                    },
+                    RunStatus::ERR => {
+                        result = RunStatus::ERR;
+                        self.timeline.model_error(format!("model error on thread {}", tc.get_tid()));
+                    }
+                }
+            }
+            match result {
+                RunStatus::OK => {},
+                _ => break
+            }
+        }
+        // the boundary after the last frame, so that it is logged before returning
+        if timed && halt.is_none() {
+            halt = self.boundary(self.frame);
+        }
+        let span = self.contexts.first_mut().and_then(|tc| trace::begin(tc.tracer()));
+        self.timeline.flush();
+        if let Some(tc) = self.contexts.first_mut() {
+            trace::end(tc.tracer(), span, TraceKind::FLUSH, 0);
+        }
+        match halt {
+            Some(Halt::END) if matches!(result, RunStatus::OK) => result = RunStatus::STOP,
+            Some(Halt::ERR) => result = RunStatus::ERR,
+            _ => {},
+        }
+
+        match result {
+            RunStatus::OK => {
+                self.state = EngineState::PAUSED;
+            },
+            RunStatus::STOP => {
+                self.shutdown();
+            },
+            RunStatus::ERR => {
+                self.state = EngineState::ERRORED;
+            }
+        }
+        0
+    }
+
+    fn run_until(&mut self, time : f64) -> i32 {
+        let target = (time / self.delta).round() as u64;
+        if target <= self.frame {
+            return 1; // already past the requested time
+        }
+        self.step(target - self.frame)
+    }
+
+    fn pause(&mut self) -> i32 {
+        // steps are executed to completion before returning, so the
+        // engine is already paused whenever this can be called
+        match self.state {
+            EngineState::INITIALIZED | EngineState::PAUSED => 0,
+            _ => 1 // invalid state
+        }
+    }
+
+    fn end(&mut self) -> i32 {
+        match self.state {
+            EngineState::INITIALIZED | EngineState::PAUSED => {
+                self.shutdown();
+                0
+            },
+            _ => 1 // invalid state
+        }
+    }
+
+    fn reset(&mut self) -> i32 {
+        match self.state {
+            EngineState::INITIALIZED | EngineState::PAUSED | EngineState::ENDED => {},
+            _ => {
+                return 1; // invalid state
+            }
+        }
+        self.state = EngineState::RESETTING;
+        for tc in self.contexts.iter_mut() {
+            let delta = tc.get_time().delta;
+            tc.set_time(ThreadTime { delta, tick : 0 });
+            let status = tc.reset();
+            self.timeline.model_failures(tc.errors());
+            match status {
+                ConfigStatus::OK => {},
+                _ => {
+                    self.state = EngineState::ERRORED;
+                    return 0;
+                }
+            }
+        }
+        self.state = EngineState::CONFIG;
+        self.frame = 0;
+        self.timeline.reset();
+        0
+    }
+
+    fn get(&mut self, path : &str) -> Result<Vec<u8>, String> {
+        let (name, field) = split_signal(path)?;
+        self.context_of(name)?.get_signal(name, field)
+    }
+
+    fn set(&mut self, path : &str, mp : &[u8]) -> Result<(), String> {
+        let (name, field) = split_signal(path)?;
+        self.context_of(name)?.set_signal(name, field, mp)
+    }
+
+    fn inject(&mut self, fault : Fault) -> Result<(), String> {
+        self.context_of(fault.model()?)?.inject(fault)
+    }
+
+    fn clear_faults(&mut self, signal : &str) -> Result<(), String> {
+        let (name, _) = split_signal(signal)?;
+        self.context_of(name)?.clear_faults(signal)
+    }
+
+    fn fault_log(&mut self) -> Result<Vec<FaultRecord>, String> {
+        let mut log : Vec<FaultRecord> = self.contexts.iter().flat_map(|tc| tc.fault_log()).collect();
+        log.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(log)
+    }
+
+    fn set_timeline(&mut self, timeline : Timeline) -> i32 {
+        if self.state != EngineState::CONFIG {
+            return 1; // invalid state
+        }
+        self.timeline = timeline;
+        0
+    }
+
+    fn verdict(&mut self) -> Verdict {
+        let mut verdict = self.timeline.verdict();
+        if verdict.error.is_none() && self.state == EngineState::ERRORED {
+            verdict.error = Some("Engine errored".to_string());
+        }
+        verdict
+    }
+
+    fn events(&mut self) -> Vec<(f64, String)> {
+        std::mem::take(&mut self.timeline.events)
+    }
+
+    fn watch(&mut self, signals : &[String]) -> Result<(), String> {
+        for s in signals {
+            self.get(s)?;
+        }
+        self.timeline.watch(signals);
+        Ok(())
+    }
+
+    fn snapshot(&mut self, signals : &[String]) -> Result<(f64, Vec<(String, rmpv::Value)>), String> {
+        // commands complete before returning, every read is at the current frame boundary
+        let mut values = Vec::new();
+        for s in signals {
+            values.push((s.clone(), decode(&self.get(s)?)?));
+        }
+        Ok((self.get_time(), values))
+    }
+
+    fn state_hash(&mut self) -> Option<FrameHash> {
+        self.timeline.hasher.as_ref().and_then(|h| h.last.clone())
+    }
+
+    fn dump(&mut self, reason : &str) -> Result<PathBuf, String> {
+        let file = self.timeline.dump(reason)?;
+        for tc in self.contexts.iter_mut() {
+            let models = snapshot::capture(tc.as_mut())?;
+            self.timeline.add_state(models)?;
+        }
+        Ok(file)
+    }
+
+    fn trace(&mut self) -> Result<Vec<ThreadTrace>, String> {
+        Ok(self.contexts.iter().filter_map(|tc| tc.trace()).collect())
+    }
+}
+
+// creates the SerialEngine struct, ready to initialize
+// @param[in] tcs - ThreadContext objects containing models to execute, in execution order
+pub fn start_serial_engine(tcs : Vec<Box<dyn ThreadContext + Send>>) -> Result<SerialEngine, String> {
+    if tcs.is_empty() {
+        return Err("SerialEngine executes at least 1 thread, not 0".to_string());
+    }
+    let mut contexts = tcs;
+    for (ind, tc) in contexts.iter_mut().enumerate() {
+        tc.set_tid(ind);
+    }
+    // the engine frame is the fastest thread rate
+    let delta = contexts.iter().map(|tc| tc.get_time().delta).fold(f64::INFINITY, f64::min);
+    Ok(SerialEngine {
+        state : EngineState::CONFIG,
+        contexts,
+        frame : 0,
+        delta,
+        timeline : Timeline::default(),
+    })
+}
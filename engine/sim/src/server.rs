@@ -0,0 +1,358 @@
+extern crate rmp_serde;
+extern crate rmpv;
+extern crate serde;
+
+use crate::engine::Engine;
+use crate::state::EngineState;
+use crate::fault::{Fault, FaultKind, FaultRecord};
+use crate::monitor::MonitorResult;
+
+use serde::{Deserialize, Serialize};
+
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub type SharedEngine = Arc<Mutex<Box<dyn Engine + Send>>>;
+
+/// Commands accepted by the server, one MessagePack map per command
+/// with the command name under the `cmd` key
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    Init,
+    Step { steps : u64 },
+    RunUntil { time : f64 },
+    Pause,
+    End,
+    Reset,
+    State,
+    Get { path : String },
+    Set { path : String, value : rmpv::Value },
+    /// stream the signals every `period` seconds of wall time, an empty list unsubscribes
+    Subscribe { signals : Vec<String>, period : Option<f64> },
+    /// override a signal, active immediately and until cleared unless start or stop are given
+    Fault { signal : String, kind : String, value : Option<rmpv::Value>, start : Option<f64>, stop : Option<f64>, seed : Option<u64> },
+    ClearFault { signal : String },
+    Faults,
+    Verdict,
+    /// write the flight recorder to files
+    Dump,
+}
+
+/// Messages sent by the server, with the message type under the `event` key
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error { message : String },
+    State { state : String, time : f64 },
+    Value { path : String, value : rmpv::Value },
+    Signals { time : f64, values : Vec<(String, rmpv::Value)> },
+    Faults { records : Vec<FaultRecord> },
+    Verdict { passed : bool, exit_code : i32, monitors : Vec<MonitorResult>, error : Option<String> },
+    Dumped { file : String },
+}
+
+pub fn state_name(state : EngineState) -> String {
+    (match state {
+        EngineState::CONFIG => "config",
+        EngineState::INITIALIZING => "initializing",
+        EngineState::INITIALIZED => "initialized",
+        EngineState::RUNNING => "running",
+        EngineState::PAUSED => "paused",
+        EngineState::ENDING => "ending",
+        EngineState::ENDED => "ended",
+        EngineState::ERRORED => "errored",
+        EngineState::RESETTING => "resetting",
+    }).to_string()
+}
+
+type Writer = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// one subscription per client
+struct Subscription {
+    signals : Vec<String>,
+    period : Duration,
+}
+
+fn send(writer : &Writer, rsp : &Response) -> bool {
+    let data = match rmp_serde::to_vec_named(rsp) {
+        Ok(d) => d,
+        Err(_) => return false,
+    };
+    match writer.lock() {
+        Ok(mut w) => w.write_all(&data).and_then(|_| w.flush()).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn status(code : i32) -> Response {
+    match code {
+        0 => Response::Ok,
+        1 => Response::Error { message : "Command is not valid in the current state".to_string() },
+        _ => Response::Error { message : format!("Command failed with code {}", code) },
+    }
+}
+
+fn decode(mp : &[u8]) -> Result<rmpv::Value, String> {
+    let mut rd = mp;
+    match rmpv::decode::read_value(&mut rd) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn handle(engine : &SharedEngine, req : Request, sub : &Arc<Mutex<Subscription>>) -> Response {
+    let mut e = match engine.lock() {
+        Ok(e) => e,
+        Err(_) => return Response::Error { message : "Engine lock poisoned".to_string() },
+    };
+    match req {
+        Request::Subscribe { signals, period } => {
+            // sampled by the engine at frame boundaries, so that streamed values are of one frame
+            if let Err(msg) = e.watch(&signals) {
+                return Response::Error { message : msg };
+            }
+            let mut s = sub.lock().unwrap();
+            s.signals = signals;
+            s.period = Duration::from_secs_f64(period.unwrap_or(0.1).max(0.01));
+            Response::Ok
+        },
+        Request::Init => status(e.init()),
+        Request::Step { steps } => status(e.step(steps)),
+        Request::RunUntil { time } => status(e.run_until(time)),
+        Request::Pause => status(e.pause()),
+        Request::End => status(e.end()),
+        Request::Reset => status(e.reset()),
+        Request::State => Response::State { state : state_name(e.get_state()), time : e.get_time() },
+        Request::Get { path } => {
+            match e.get(&path).and_then(|mp| decode(&mp)) {
+                Ok(value) => Response::Value { path, value },
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+        Request::Set { path, value } => {
+            let mut mp = Vec::new();
+            if rmpv::encode::write_value(&mut mp, &value).is_err() {
+                return Response::Error { message : "Failed to encode value".to_string() };
+            }
+            match e.set(&path, &mp) {
+                Ok(_) => Response::Ok,
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+        Request::Fault { signal, kind, value, start, stop, seed } => {
+            let mut fault = match FaultKind::parse(&kind, value) {
+                Ok(k) => Fault::new(&signal, k),
+                Err(msg) => return Response::Error { message : msg },
+            };
+            fault.start = start.unwrap_or(0.0);
+            fault.stop = stop;
+            fault.seed = seed.unwrap_or(0);
+            match e.inject(fault) {
+                Ok(_) => Response::Ok,
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+        Request::ClearFault { signal } => {
+            match e.clear_faults(&signal) {
+                Ok(_) => Response::Ok,
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+        Request::Faults => {
+            match e.fault_log() {
+                Ok(records) => Response::Faults { records },
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+        Request::Verdict => {
+            let v = e.verdict();
+            Response::Verdict {
+                passed : v.passed(),
+                exit_code : v.exit_code(),
+                monitors : v.monitors,
+                error : v.error,
+            }
+        },
+        Request::Dump => {
+            match e.dump("requested") {
+                Ok(file) => Response::Dumped { file : file.display().to_string() },
+                Err(msg) => Response::Error { message : msg },
+            }
+        },
+    }
+}
+
+// streams state changes and subscribed signals until the client disconnects
+fn stream_events(engine : SharedEngine, writer : Writer, sub : Arc<Mutex<Subscription>>, alive : Arc<AtomicBool>) {
+    let mut last_state : Option<EngineState> = None;
+    let tick = Duration::from_millis(10);
+    let mut elapsed = Duration::ZERO;
+    while alive.load(Ordering::SeqCst) {
+        thread::sleep(tick);
+        elapsed += tick;
+
+        let (signals, period) = {
+            let s = sub.lock().unwrap();
+            (s.signals.clone(), s.period)
+        };
+        let due = !signals.is_empty() && elapsed >= period;
+        // the state and the signals are read under one lock, the signals at a single frame boundary
+        let (state, time, snapshot) = match engine.lock() {
+            Ok(mut e) => {
+                let snapshot = match due {
+                    true => Some(e.snapshot(&signals)),
+                    false => None,
+                };
+                (e.get_state(), e.get_time(), snapshot)
+            },
+            Err(_) => break,
+        };
+        if last_state != Some(state) {
+            last_state = Some(state);
+            if !send(&writer, &Response::State { state : state_name(state), time }) {
+                break;
+            }
+        }
+        if !due {
+            continue;
+        }
+        elapsed = Duration::ZERO;
+        // nothing is sent until a running engine has sampled the signals
+        let (time, values) = match snapshot {
+            Some(Ok(s)) => s,
+            _ => continue,
+        };
+        if !send(&writer, &Response::Signals { time, values }) {
+            break;
+        }
+    }
+}
+
+fn serve_client(engine : SharedEngine, reader : Box<dyn Read + Send>, writer : Box<dyn Write + Send>) {
+    let writer : Writer = Arc::new(Mutex::new(writer));
+    let sub = Arc::new(Mutex::new(Subscription { signals : Vec::new(), period : Duration::from_millis(100) }));
+    let alive = Arc::new(AtomicBool::new(true));
+
+    let events = {
+        let (e, w, s, a) = (Arc::clone(&engine), Arc::clone(&writer), Arc::clone(&sub), Arc::clone(&alive));
+        thread::spawn(move || stream_events(e, w, s, a))
+    };
+
+    let mut rd = BufReader::new(reader);
+    loop {
+        let rsp = match rmp_serde::from_read::<_, Request>(&mut rd) {
+            Ok(req) => handle(&engine, req, &sub),
+            Err(rmp_serde::decode::Error::InvalidMarkerRead(_)) => break, // disconnected
+            Err(rmp_serde::decode::Error::InvalidDataRead(_)) => break,
+            Err(e) => Response::Error { message : format!("Invalid command: {}", e) },
+        };
+        if !send(&writer, &rsp) {
+            break;
+        }
+    }
+    alive.store(false, Ordering::SeqCst);
+    let _ = events.join();
+}
+
+/// Endpoint the server listens on. TCP is restricted to localhost
+pub enum Endpoint {
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Tcp(u16),
+}
+
+impl Endpoint {
+    /// Parse `unix:<path>` or `tcp:<port>`
+    pub fn parse(txt : &str) -> Result<Endpoint, String> {
+        #[cfg(unix)]
+        if let Some(path) = txt.strip_prefix("unix:") {
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+        #[cfg(not(unix))]
+        if txt.starts_with("unix:") {
+            return Err(format!("Endpoint [{}]: unix sockets are not supported on this platform", txt));
+        }
+        if let Some(port) = txt.strip_prefix("tcp:") {
+            return match port.parse::<u16>() {
+                Ok(p) => Ok(Endpoint::Tcp(p)),
+                Err(_) => Err(format!("Invalid port: {}", port)),
+            };
+        }
+        Err(format!("Endpoint [{}] is not of the form unix:<path> or tcp:<port>", txt))
+    }
+}
+
+/// Start accepting clients in the background. Each client is served by
+/// its own thread, commands are executed in the order they are received
+pub fn start_server(engine : SharedEngine, endpoint : Endpoint) -> Result<thread::JoinHandle<()>, String> {
+    match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            remove_stale_socket(&path)?;
+            let listener = match UnixListener::bind(&path) {
+                Ok(l) => l,
+                Err(e) => return Err(format!("Failed to bind {}: {}", path.display(), e)),
+            };
+            Ok(thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream : UnixStream = match stream {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    let reader = match stream.try_clone() {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    let e = Arc::clone(&engine);
+                    thread::spawn(move || serve_client(e, Box::new(reader), Box::new(stream)));
+                }
+            }))
+        },
+        Endpoint::Tcp(port) => {
+            let listener = match TcpListener::bind(("127.0.0.1", port)) {
+                Ok(l) => l,
+                Err(e) => return Err(format!("Failed to bind 127.0.0.1:{}: {}", port, e)),
+            };
+            Ok(thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let stream : TcpStream = match stream {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    let _ = stream.set_nodelay(true);
+                    let reader = match stream.try_clone() {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    let e = Arc::clone(&engine);
+                    thread::spawn(move || serve_client(e, Box::new(reader), Box::new(stream)));
+                }
+            }))
+        }
+    }
+}
+
+// removes a socket left by a previous run, any other file at the path is kept
+#[cfg(unix)]
+fn remove_stale_socket(path : &Path) -> Result<(), String> {
+    match std::fs::symlink_metadata(path) {
+        Ok(m) if m.file_type().is_socket() => match std::fs::remove_file(path) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to remove stale socket {}: {}", path.display(), e)),
+        },
+        Ok(_) => Err(format!("{} exists and is not a socket", path.display())),
+        Err(_) => Ok(()),
+    }
+}
@@ -0,0 +1,199 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::connection::decode;
+use crate::metadata::FieldInfo;
+use crate::threadcontext::ThreadContext;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// ModelState
+/// Interface of a model, every field read through its generated MessagePack functions
+#[derive(Clone, Debug)]
+pub struct ModelState {
+    pub name : String,
+    pub thread : usize,
+    pub fields : Vec<(FieldInfo, rmpv::Value)>,
+}
+
+/// Read every field of the models executed by a context. A field that cannot be
+/// read is reported in place of its value, so that a faulty model is still captured
+pub fn capture(tc : &mut dyn ThreadContext) -> Result<Vec<ModelState>, String> {
+    let mut out = Vec::new();
+    for name in tc.model_names() {
+        let mut fields = Vec::new();
+        for f in tc.fields(&name)? {
+            let val = match tc.get_signal(&name, &f.path).and_then(|mp| decode(&mp)) {
+                Ok(v) => v,
+                Err(e) => rmpv::Value::from(format!("unreadable: {}", e)),
+            };
+            fields.push((f, val));
+        }
+        out.push(ModelState { name, thread : tc.get_tid(), fields });
+    }
+    Ok(out)
+}
+
+/// Convert a field value to TOML, which has no nil and only signed integers
+pub fn to_toml_value(val : &rmpv::Value) -> toml::Value {
+    match val {
+        rmpv::Value::Nil => toml::Value::String("nil".to_string()),
+        rmpv::Value::Boolean(b) => toml::Value::Boolean(*b),
+        rmpv::Value::Integer(i) => match i.as_i64() {
+            Some(n) => toml::Value::Integer(n),
+            None => toml::Value::Float(i.as_f64().unwrap_or(f64::NAN)),
+        },
+        rmpv::Value::F32(f) => toml::Value::Float(*f as f64),
+        rmpv::Value::F64(f) => toml::Value::Float(*f),
+        rmpv::Value::String(s) => toml::Value::String(s.as_str().unwrap_or("").to_string()),
+        rmpv::Value::Binary(b) => toml::Value::Array(b.iter().map(|x| toml::Value::Integer(*x as i64)).collect()),
+        rmpv::Value::Array(arr) => toml::Value::Array(arr.iter().map(to_toml_value).collect()),
+        rmpv::Value::Map(m) => {
+            let mut tbl = toml::Table::new();
+            for (k, v) in m.iter() {
+                let key = match k.as_str() {
+                    Some(s) => s.to_string(),
+                    None => k.to_string(),
+                };
+                tbl.insert(key, to_toml_value(v));
+            }
+            toml::Value::Table(tbl)
+        },
+        rmpv::Value::Ext(t, data) => toml::Value::String(format!("ext {} {:?}", t, data)),
+    }
+}
+
+// nests a field into the tables of its path
+fn insert_path(tbl : &mut toml::Table, parts : &[&str], val : toml::Value) {
+    match parts {
+        [] => {},
+        [last] => {
+            tbl.insert(last.to_string(), val);
+        },
+        [first, rest @ ..] => {
+            let next = tbl.entry(first.to_string()).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let toml::Value::Table(t) = next {
+                insert_path(t, rest, val);
+            }
+        },
+    }
+}
+
+/// StateReport
+/// State of every model when the flight recorder was dumped, written as TOML
+/// with a table per model and its fields nested by path
+#[derive(Clone, Debug)]
+pub struct StateReport {
+    pub file : PathBuf,
+    /// time of the last recorded frame, seconds
+    pub time : f64,
+    pub reason : String,
+    pub models : Vec<ModelState>,
+}
+
+impl StateReport {
+    pub fn new(file : PathBuf, time : f64, reason : &str) -> StateReport {
+        StateReport { file, time, reason : reason.to_string(), models : Vec::new() }
+    }
+
+    /// Add the models of a context, models already in the report are kept
+    pub fn add(&mut self, models : Vec<ModelState>) {
+        for m in models {
+            if !self.models.iter().any(|x| x.name == m.name) {
+                self.models.push(m);
+            }
+        }
+        self.models.sort_by(|a, b| (a.thread, &a.name).cmp(&(b.thread, &b.name)));
+    }
+
+    pub fn to_toml(&self) -> toml::Table {
+        let mut dump = toml::Table::new();
+        dump.insert("time".to_string(), toml::Value::Float((self.time * 1e9).round() / 1e9));
+        dump.insert("reason".to_string(), toml::Value::String(self.reason.clone()));
+        let mut models = toml::Table::new();
+        for m in self.models.iter() {
+            let mut tbl = toml::Table::new();
+            tbl.insert("thread".to_string(), toml::Value::Integer(m.thread as i64));
+            for (f, v) in m.fields.iter() {
+                let parts : Vec<&str> = f.path.split('.').collect();
+                insert_path(&mut tbl, &parts, to_toml_value(v));
+            }
+            models.insert(m.name.clone(), toml::Value::Table(tbl));
+        }
+        let mut out = toml::Table::new();
+        out.insert("dump".to_string(), toml::Value::Table(dump));
+        out.insert("models".to_string(), toml::Value::Table(models));
+        out
+    }
+
+    /// Write the report, replacing the file written for fewer models
+    pub fn write(&self) -> Result<(), String> {
+        match fs::write(&self.file, self.to_toml().to_string()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Unable to write state report {}: {}", self.file.display(), e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(path : &str) -> FieldInfo {
+        FieldInfo { path : path.to_string(), index : vec![], typename : "f64".to_string(), dims : vec![], tag : "data".to_string(), unit : String::new() }
+    }
+
+    fn model(name : &str, thread : usize, x : f64) -> ModelState {
+        ModelState {
+            name : name.to_string(),
+            thread,
+            fields : vec![(field("input.x"), rmpv::Value::F64(x)), (field("data.gains.kp"), rmpv::Value::from(2))],
+        }
+    }
+
+    #[test]
+    fn values_convert_to_toml() {
+        assert_eq!(to_toml_value(&rmpv::Value::Nil), toml::Value::String("nil".to_string()));
+        assert_eq!(to_toml_value(&rmpv::Value::from(-3)), toml::Value::Integer(-3));
+        assert_eq!(to_toml_value(&rmpv::Value::from(u64::MAX)), toml::Value::Float(u64::MAX as f64));
+        assert_eq!(to_toml_value(&rmpv::Value::F32(0.5)), toml::Value::Float(0.5));
+        assert_eq!(to_toml_value(&rmpv::Value::Binary(vec![1, 2])), toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(2)]));
+        let map = rmpv::Value::Map(vec![(rmpv::Value::from("a"), rmpv::Value::from(true)), (rmpv::Value::from(1), rmpv::Value::from("b"))]);
+        assert_eq!(to_toml_value(&map).to_string(), "{ 1 = \"b\", a = true }");
+    }
+
+    #[test]
+    fn reports_nest_fields_by_model_and_path() {
+        let mut report = StateReport::new(PathBuf::from("state.toml"), 0.30000000000000004, "monitor [m] failed");
+        report.add(vec![model("gen2", 1, 2.0)]);
+        report.add(vec![model("gen1", 0, 1.0), model("gen2", 1, 9.0)]);
+        assert_eq!(report.models.iter().map(|m| m.name.as_str()).collect::<Vec<&str>>(), vec!["gen1", "gen2"]);
+        let tbl = report.to_toml();
+        assert_eq!(tbl["dump"]["time"].as_float(), Some(0.3));
+        assert_eq!(tbl["dump"]["reason"].as_str(), Some("monitor [m] failed"));
+        let gen2 = &tbl["models"]["gen2"];
+        assert_eq!(gen2["thread"].as_integer(), Some(1));
+        assert_eq!(gen2["input"]["x"].as_float(), Some(2.0));
+        assert_eq!(gen2["data"]["gains"]["kp"].as_integer(), Some(2));
+    }
+
+    #[test]
+    fn recorder_dumps_write_the_state_of_every_context() {
+        let dir = std::env::temp_dir().join(format!("rsis_snapshot_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut r = crate::recorder::Recorder::parse(&toml::Value::Table(toml::Table::new()), "st").unwrap();
+        r.file = dir.join("rec.csv");
+        assert!(!r.capturing());
+        r.dump("on demand").unwrap();
+        assert!(r.capturing());
+        r.add_state(vec![model("gen1", 0, 1.0)]).unwrap();
+        r.add_state(vec![model("gen2", 1, 2.0)]).unwrap();
+        let txt = fs::read_to_string(dir.join("rec_0_state.toml")).unwrap();
+        let tbl = txt.parse::<toml::Table>().unwrap();
+        assert_eq!(tbl["models"].as_table().unwrap().len(), 2);
+        r.settle();
+        assert!(!r.capturing());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -9,4 +9,5 @@ pub enum EngineState {
     ENDING       = 5,
     ENDED        = 6,
     ERRORED      = 7,
+    RESETTING    = 8,
 }
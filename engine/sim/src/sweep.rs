@@ -0,0 +1,261 @@
+extern crate toml;
+
+use crate::metadata::split_signal;
+use crate::random::Random;
+
+/// Design of a sweep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// every combination of the parameter values
+    GRID,
+    /// the nominal case, then each parameter through its values with the others nominal
+    OAT,
+    /// latin hypercube, one sample per stratum of every parameter range
+    LHS,
+}
+
+/// Parameter
+/// Model signal varied by a sweep
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub signal : String,
+    /// values of grid and one-at-a-time sweeps
+    pub values : Vec<toml::Value>,
+    /// range of latin hypercube sweeps
+    pub range : Option<(f64, f64)>,
+    /// value while other parameters vary, the scene value if not given
+    pub nominal : Option<toml::Value>,
+}
+
+fn number(val : Option<&toml::Value>, ctxt : &str) -> Result<f64, String> {
+    match val {
+        Some(toml::Value::Float(f)) => Ok(*f),
+        Some(toml::Value::Integer(i)) => Ok(*i as f64),
+        Some(_) => Err(format!("{} is not a number", ctxt)),
+        None => Err(format!("Missing {}", ctxt)),
+    }
+}
+
+impl Parameter {
+    /// Parse an entry of [sweep.parameters], either an array of values or a table of
+    /// `values`, or `min`, `max` and `steps`, with an optional `nominal`
+    pub fn parse(signal : &str, val : &toml::Value) -> Result<Parameter, String> {
+        split_signal(signal)?;
+        let ctxt = format!("[sweep.parameters].\"{}\"", signal);
+        let mut p = Parameter { signal : signal.to_string(), values : Vec::new(), range : None, nominal : None };
+        let tbl = match val {
+            toml::Value::Array(arr) => {
+                p.values = arr.clone();
+                return Ok(p);
+            },
+            toml::Value::Table(t) => t,
+            _ => return Err(format!("{} is not an array of values or a table", ctxt)),
+        };
+        for key in tbl.keys() {
+            if !["values", "min", "max", "steps", "nominal"].contains(&key.as_str()) {
+                return Err(format!("{}.{} is not a parameter setting", ctxt, key));
+            }
+        }
+        p.nominal = tbl.get("nominal").cloned();
+        match (tbl.get("values"), tbl.get("min"), tbl.get("max")) {
+            (Some(toml::Value::Array(arr)), None, None) => p.values = arr.clone(),
+            (Some(_), None, None) => return Err(format!("{}.values is not an array", ctxt)),
+            (None, Some(_), Some(_)) => {
+                let min = number(tbl.get("min"), &format!("{}.min", ctxt))?;
+                let max = number(tbl.get("max"), &format!("{}.max", ctxt))?;
+                if min > max {
+                    return Err(format!("{}.min is greater than its max", ctxt));
+                }
+                p.range = Some((min, max));
+                if let Some(steps) = tbl.get("steps") {
+                    let n = match steps.as_integer() {
+                        Some(n) if n >= 2 => n as usize,
+                        _ => return Err(format!("{}.steps must be an integer of at least 2", ctxt)),
+                    };
+                    p.values = (0..n).map(|i| toml::Value::Float(min + (max - min) * i as f64 / (n - 1) as f64)).collect();
+                }
+            },
+            _ => return Err(format!("{} needs values, or min and max", ctxt)),
+        }
+        Ok(p)
+    }
+}
+
+/// Sweep
+/// Deterministic cases of a batch, from the [sweep] table of a batch file
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    pub method : Method,
+    pub parameters : Vec<Parameter>,
+    /// signal values of each case
+    pub cases : Vec<Vec<(String, toml::Value)>>,
+}
+
+impl Sweep {
+    /// Parse the [sweep] table
+    /// @param[in] seed - seed of latin hypercube sampling
+    pub fn parse(val : &toml::Value, seed : u64) -> Result<Sweep, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err("[sweep] is not a table".to_string()),
+        };
+        for key in tbl.keys() {
+            if !["method", "samples", "parameters"].contains(&key.as_str()) {
+                return Err(format!("[sweep].{} is not a sweep setting", key));
+            }
+        }
+        let method = match tbl.get("method").and_then(|m| m.as_str()) {
+            Some("grid") => Method::GRID,
+            Some("one_at_a_time") => Method::OAT,
+            Some("latin_hypercube") => Method::LHS,
+            _ => return Err("[sweep].method must be grid, one_at_a_time or latin_hypercube".to_string()),
+        };
+        let mut parameters = Vec::new();
+        match tbl.get("parameters").and_then(|p| p.as_table()) {
+            Some(p) if !p.is_empty() => {
+                for (signal, v) in p {
+                    parameters.push(Parameter::parse(signal, v)?);
+                }
+            },
+            _ => return Err("[sweep.parameters] is missing or empty".to_string()),
+        }
+        let samples = match (method, tbl.get("samples")) {
+            (Method::LHS, Some(toml::Value::Integer(n))) if *n > 0 => *n as usize,
+            (Method::LHS, _) => return Err("[sweep].samples must be a positive integer".to_string()),
+            (_, Some(_)) => return Err("[sweep].samples applies to latin_hypercube sweeps only".to_string()),
+            (_, None) => 0,
+        };
+        for p in parameters.iter() {
+            match method {
+                Method::LHS if p.range.is_none() => {
+                    return Err(format!("[sweep.parameters].\"{}\" needs min and max for latin_hypercube", p.signal));
+                },
+                Method::GRID | Method::OAT if p.values.is_empty() => {
+                    return Err(format!("[sweep.parameters].\"{}\" needs values, or min, max and steps", p.signal));
+                },
+                _ => {},
+            }
+        }
+
+        let cases = match method {
+            Method::GRID => grid(&parameters),
+            Method::OAT => one_at_a_time(&parameters),
+            Method::LHS => latin_hypercube(&parameters, samples, seed),
+        };
+        Ok(Sweep { method, parameters, cases })
+    }
+}
+
+// the last parameter varies fastest
+fn grid(parameters : &[Parameter]) -> Vec<Vec<(String, toml::Value)>> {
+    let mut cases = vec![Vec::new()];
+    for p in parameters {
+        let mut next = Vec::new();
+        for c in cases.iter() {
+            for v in p.values.iter() {
+                let mut case = c.clone();
+                case.push((p.signal.clone(), v.clone()));
+                next.push(case);
+            }
+        }
+        cases = next;
+    }
+    cases
+}
+
+fn one_at_a_time(parameters : &[Parameter]) -> Vec<Vec<(String, toml::Value)>> {
+    let nominal = |skip : usize| -> Vec<(String, toml::Value)> {
+        parameters.iter().enumerate()
+            .filter(|(i, _)| *i != skip)
+            .filter_map(|(_, p)| p.nominal.clone().map(|v| (p.signal.clone(), v)))
+            .collect()
+    };
+    let mut cases = vec![nominal(usize::MAX)];
+    for (i, p) in parameters.iter().enumerate() {
+        for v in p.values.iter() {
+            let mut case = nominal(i);
+            case.push((p.signal.clone(), v.clone()));
+            cases.push(case);
+        }
+    }
+    cases
+}
+
+fn latin_hypercube(parameters : &[Parameter], samples : usize, seed : u64) -> Vec<Vec<(String, toml::Value)>> {
+    let mut rng = Random::new(seed);
+    let mut cases = vec![Vec::new(); samples];
+    for p in parameters {
+        let (min, max) = p.range.unwrap_or_default();
+        // shuffled strata, Fisher-Yates
+        let mut strata : Vec<usize> = (0..samples).collect();
+        for i in (1..samples).rev() {
+            let j = ((rng.uniform() * (i + 1) as f64) as usize).min(i);
+            strata.swap(i, j);
+        }
+        for (case, s) in cases.iter_mut().zip(strata) {
+            let u = (s as f64 + rng.uniform()) / samples as f64;
+            case.push((p.signal.clone(), toml::Value::Float(min + (max - min) * u)));
+        }
+    }
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(txt : &str) -> Result<Sweep, String> {
+        Sweep::parse(&toml::Value::Table(txt.parse::<toml::Table>().unwrap()), 7)
+    }
+
+    fn floats(case : &[(String, toml::Value)]) -> Vec<(&str, f64)> {
+        case.iter().map(|(s, v)| (s.as_str(), v.as_float().unwrap_or_else(|| v.as_integer().unwrap() as f64))).collect()
+    }
+
+    #[test]
+    fn grids_vary_the_last_parameter_fastest() {
+        let s = sweep("method = \"grid\"\n[parameters]\n\"a.x\" = [1, 2]\n\"b.y\" = {min = 0.0, max = 1.0, steps = 3}").unwrap();
+        assert_eq!(s.cases.len(), 6);
+        assert_eq!(floats(&s.cases[0]), vec![("a.x", 1.0), ("b.y", 0.0)]);
+        assert_eq!(floats(&s.cases[1]), vec![("a.x", 1.0), ("b.y", 0.5)]);
+        assert_eq!(floats(&s.cases[5]), vec![("a.x", 2.0), ("b.y", 1.0)]);
+    }
+
+    #[test]
+    fn one_at_a_time_keeps_the_others_nominal() {
+        let s = sweep("method = \"one_at_a_time\"\n[parameters]\n\"a.x\" = {values = [1, 2], nominal = 0}\n\"b.y\" = [5]").unwrap();
+        let cases : Vec<Vec<(&str, f64)>> = s.cases.iter().map(|c| floats(c)).collect();
+        assert_eq!(cases, vec![
+            vec![("a.x", 0.0)],
+            vec![("a.x", 1.0)],
+            vec![("a.x", 2.0)],
+            vec![("a.x", 0.0), ("b.y", 5.0)],
+        ]);
+    }
+
+    #[test]
+    fn latin_hypercubes_sample_every_stratum_once() {
+        let txt = "method = \"latin_hypercube\"\nsamples = 5\n[parameters]\n\"a.x\" = {min = 10.0, max = 20.0}\n\"b.y\" = {min = -1, max = 0}";
+        let s = sweep(txt).unwrap();
+        assert_eq!(s.cases.len(), 5);
+        for (i, (min, width)) in [(10.0, 10.0), (-1.0, 1.0)].into_iter().enumerate() {
+            let mut strata : Vec<usize> = s.cases.iter().map(|c| ((floats(c)[i].1 - min) / width * 5.0) as usize).collect();
+            strata.sort();
+            assert_eq!(strata, vec![0, 1, 2, 3, 4]);
+        }
+        assert_eq!(s.cases, sweep(txt).unwrap().cases);
+    }
+
+    #[test]
+    fn malformed_sweeps_are_rejected() {
+        assert!(sweep("method = \"random\"\n[parameters]\n\"a.x\" = [1]").is_err());
+        assert!(sweep("method = \"grid\"\n[parameters]").is_err());
+        assert!(sweep("method = \"grid\"\nsamples = 3\n[parameters]\n\"a.x\" = [1]").is_err());
+        assert!(sweep("method = \"grid\"\n[parameters]\n\"a.x\" = {min = 0, max = 1}").is_err());
+        assert!(sweep("method = \"grid\"\n[parameters]\n\"a.x\" = {min = 1, max = 0, steps = 2}").is_err());
+        assert!(sweep("method = \"grid\"\n[parameters]\n\"a.x\" = {min = 0, max = 1, steps = 1}").is_err());
+        assert!(sweep("method = \"grid\"\n[parameters]\n\"x\" = [1]").is_err());
+        assert!(sweep("method = \"latin_hypercube\"\nsamples = 2\n[parameters]\n\"a.x\" = [1]").is_err());
+        assert!(sweep("method = \"latin_hypercube\"\n[parameters]\n\"a.x\" = {min = 0, max = 1}").is_err());
+    }
+}
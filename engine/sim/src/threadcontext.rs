@@ -1,13 +1,24 @@
 extern crate rmodel;
 
+use crate::fault::{Fault, FaultRecord};
+use crate::metadata::FieldInfo;
+use crate::trace::{ThreadTrace, TraceBuffer};
+
 use rmodel::{ConfigStatus, RunStatus};
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct ThreadTime {
     pub delta : f64,
     pub tick : i64,
 }
 
+impl ThreadTime {
+    /// Number of engine frames per frame of this thread, for an engine frame of `base` seconds
+    pub fn frames(&self, base : f64) -> u64 {
+        ((self.delta / base).round() as u64).max(1)
+    }
+}
+
 /// ThreadContext
 /// JRunner will autogenerate structs implementing this
 /// trait for integration with the engine.
@@ -35,4 +46,43 @@ pub trait ThreadContext {
 
     /// Executes RModel::halt
     fn end(&mut self) -> RunStatus;
+
+    /// Names of the model instances executed by this context
+    fn model_names(&self) -> Vec<String>;
+
+    /// Interface fields of a model of this context
+    fn fields(&self, name : &str) -> Result<Vec<FieldInfo>, String>;
+
+    /// Encode the field at the path of a model as MessagePack
+    fn get_signal(&mut self, name : &str, path : &str) -> Result<Vec<u8>, String>;
+
+    /// Decode MessagePack into the field at the path of a model
+    fn set_signal(&mut self, name : &str, path : &str, mp : &[u8]) -> Result<(), String>;
+
+    /// Hash the MessagePack encoding of every field of the models, by signal
+    fn hash_state(&mut self) -> Result<Vec<(String, u64)>, String>;
+
+    /// Arm a fault on a signal of one of the models of this context
+    fn inject(&mut self, fault : Fault) -> Result<(), String>;
+
+    /// Clear the faults on the signal, logging those that were active
+    fn clear_faults(&mut self, signal : &str) -> Result<(), String>;
+
+    /// Faults that became active or were cleared since init
+    fn fault_log(&self) -> Vec<FaultRecord>;
+
+    /// Ring buffer of the spans of this thread, None unless the scene is traced
+    fn tracer(&mut self) -> &mut Option<TraceBuffer>;
+
+    /// Spans recorded since the scene was built or reset, None unless the scene is traced
+    fn trace(&self) -> Option<ThreadTrace>;
+
+    /// Model failures since they were last taken, with their simulation time
+    fn errors(&mut self) -> Vec<(f64, String)>;
+
+    /// Returns all models to their post-load state
+    /// - Re-apply scene parameters
+    /// - Re-arm scene faults, drop faults injected at run time
+    /// - Thread time is zeroed by the engine before this is called
+    fn reset(&mut self) -> ConfigStatus;
 }
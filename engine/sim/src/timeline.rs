@@ -0,0 +1,820 @@
+extern crate rmpv;
+extern crate toml;
+
+use crate::connection::{decode, encode};
+use crate::expr::Condition;
+use crate::fault::Fault;
+use crate::hash::StateHasher;
+use crate::recorder::Recorder;
+use crate::snapshot::{capture, ModelState};
+use crate::monitor::{Monitor, MonitorResult, Verdict};
+use crate::metadata::split_signal;
+use crate::scene::{parse_duration, to_msgpack_value};
+use crate::threadcontext::ThreadContext;
+use crate::TIME_EPS;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// What starts a timeline entry
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    /// simulation time, seconds
+    AT(f64),
+    /// a condition becoming true
+    WHEN(Condition),
+}
+
+/// Action of a timeline entry
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// signal, value
+    SET(String, rmpv::Value),
+    /// fault, duration from the time the entry fires
+    FAULT(Fault, Option<f64>),
+    /// signal with faults to clear
+    CLEAR(String),
+    /// start (true) or stop (false) every signal log
+    LOG(bool),
+    PAUSE,
+    END,
+}
+
+/// TimelineEntry
+/// Entry of the scene [[timeline]] array
+#[derive(Clone, Debug)]
+pub struct TimelineEntry {
+    pub trigger : Trigger,
+    pub actions : Vec<Action>,
+    /// fire on every rising edge of the condition instead of once
+    pub repeat : bool,
+    fired : bool,
+    /// value of the condition at the previous frame boundary
+    was_true : bool,
+}
+
+impl TimelineEntry {
+    /// Parse an entry of the [[timeline]] array
+    pub fn parse(entry : &toml::Value, ctxt : &str) -> Result<TimelineEntry, String> {
+        let tbl = match entry.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        let trigger = match (tbl.get("at"), tbl.get("when")) {
+            (Some(at), None) => Trigger::AT(parse_duration(at)?),
+            (None, Some(toml::Value::String(when))) => match Condition::parse(when) {
+                Ok(c) => Trigger::WHEN(c),
+                Err(e) => return Err(format!("{}.when: {}", ctxt, e)),
+            },
+            (None, Some(_)) => return Err(format!("{}.when is not a string", ctxt)),
+            _ => return Err(format!("{} needs exactly one of at or when", ctxt)),
+        };
+
+        for key in tbl.keys() {
+            if !["at", "when", "repeat", "set", "clear", "fault", "log", "pause", "end"].contains(&key.as_str()) {
+                return Err(format!("{}.{} is not a timeline action", ctxt, key));
+            }
+        }
+        // actions execute in a fixed order, whatever the order of the keys
+        let mut actions = Vec::new();
+        if let Some(val) = tbl.get("set") {
+            let sets = match val.as_table() {
+                Some(t) => t,
+                None => return Err(format!("{}.set is not a table of signal values", ctxt)),
+            };
+            for (signal, v) in sets {
+                split_signal(signal)?;
+                actions.push(Action::SET(signal.clone(), to_msgpack_value(v)?));
+            }
+        }
+        if let Some(val) = tbl.get("clear") {
+            match val.as_str() {
+                Some(signal) => {
+                    split_signal(signal)?;
+                    actions.push(Action::CLEAR(signal.to_string()));
+                },
+                None => return Err(format!("{}.clear is not a signal", ctxt)),
+            }
+        }
+        if let Some(val) = tbl.get("fault") {
+            let fault = Fault::parse(val, &format!("{}.fault", ctxt))?;
+            let duration = match val.get("duration") {
+                Some(d) => Some(parse_duration(d)?),
+                None => None,
+            };
+            actions.push(Action::FAULT(fault, duration));
+        }
+        if let Some(val) = tbl.get("log") {
+            match val.as_str() {
+                Some("start") => actions.push(Action::LOG(true)),
+                Some("stop") => actions.push(Action::LOG(false)),
+                _ => return Err(format!("{}.log is not start or stop", ctxt)),
+            }
+        }
+        for (key, action) in [("pause", Action::PAUSE), ("end", Action::END)] {
+            match tbl.get(key).map(|v| v.as_bool()) {
+                Some(Some(true)) => actions.push(action),
+                Some(Some(false)) | None => {},
+                Some(None) => return Err(format!("{}.{} is not a boolean", ctxt, key)),
+            }
+        }
+        if actions.is_empty() {
+            return Err(format!("{} has no action, expected set, fault, clear, log, pause or end", ctxt));
+        }
+        let repeat = match tbl.get("repeat") {
+            Some(toml::Value::Boolean(b)) => *b,
+            Some(_) => return Err(format!("{}.repeat is not a boolean", ctxt)),
+            None => false,
+        };
+        if repeat && matches!(trigger, Trigger::AT(_)) {
+            return Err(format!("{}.repeat applies to when conditions only", ctxt));
+        }
+        Ok(TimelineEntry {
+            trigger,
+            actions,
+            repeat,
+            fired : false,
+            was_true : false,
+        })
+    }
+
+    /// Signals read by the entry
+    pub fn signals(&self) -> Vec<String> {
+        match &self.trigger {
+            Trigger::WHEN(c) => c.signals(),
+            Trigger::AT(_) => Vec::new(),
+        }
+    }
+}
+
+/// SignalLog
+/// Entry of the scene [[logging]] array, signals recorded to a CSV file
+#[derive(Debug)]
+pub struct SignalLog {
+    pub file : PathBuf,
+    /// samples per second
+    pub rate : f64,
+    pub signals : Vec<String>,
+    /// recording when the scenario starts
+    pub enabled : bool,
+    recording : bool,
+    writer : Option<BufWriter<File>>,
+}
+
+impl Clone for SignalLog {
+    fn clone(&self) -> SignalLog {
+        SignalLog {
+            file : self.file.clone(),
+            rate : self.rate,
+            signals : self.signals.clone(),
+            enabled : self.enabled,
+            recording : self.enabled,
+            writer : None,
+        }
+    }
+}
+
+impl SignalLog {
+    /// Parse an entry of the [[logging]] array
+    /// @param[in] name - default file name, without the extension
+    pub fn parse(entry : &toml::Value, ctxt : &str, name : &str) -> Result<SignalLog, String> {
+        let tbl = match entry.as_table() {
+            Some(t) => t,
+            None => return Err(format!("{} is not a table", ctxt)),
+        };
+        let rate = match tbl.get("rate") {
+            Some(toml::Value::Float(f)) if *f > 0.0 => *f,
+            Some(toml::Value::Integer(i)) if *i > 0 => *i as f64,
+            Some(_) => return Err(format!("{}.rate must be a positive number", ctxt)),
+            None => return Err(format!("Missing {}.rate", ctxt)),
+        };
+        let mut signals = Vec::new();
+        match tbl.get("signals").and_then(|v| v.as_array()) {
+            Some(arr) => {
+                for s in arr {
+                    match s.as_str() {
+                        Some(txt) => {
+                            split_signal(txt)?;
+                            signals.push(txt.to_string());
+                        },
+                        None => return Err(format!("{}.signals contains a value that is not a string", ctxt)),
+                    }
+                }
+            },
+            None => return Err(format!("{}.signals is missing or not an array", ctxt)),
+        }
+        let file = match tbl.get("file") {
+            Some(toml::Value::String(f)) => PathBuf::from(f),
+            Some(_) => return Err(format!("{}.file is not a string", ctxt)),
+            None => PathBuf::from(format!("{}.csv", name)),
+        };
+        let enabled = match tbl.get("enabled") {
+            Some(toml::Value::Boolean(b)) => *b,
+            Some(_) => return Err(format!("{}.enabled is not a boolean", ctxt)),
+            None => true,
+        };
+        Ok(SignalLog {
+            file,
+            rate,
+            signals,
+            enabled,
+            recording : enabled,
+            writer : None,
+        })
+    }
+
+    // appends a row, writing the header with the first row
+    fn record(&mut self, time : f64, values : &HashMap<String, rmpv::Value>) -> Result<(), String> {
+        // frame times are multiples of the frame duration, rounded to print without float noise
+        let mut row = vec![((time * 1e9).round() / 1e9).to_string()];
+        let mut header = vec!["time".to_string()];
+        for s in self.signals.iter() {
+            match values.get(s) {
+                Some(v) => flatten(s, v, &mut header, &mut row),
+                None => return Err(format!("Logged signal [{}] was not sampled", s)),
+            }
+        }
+        if self.writer.is_none() {
+            let mut w = match File::create(&self.file) {
+                Ok(f) => BufWriter::new(f),
+                Err(e) => return Err(format!("Unable to create log {}: {}", self.file.display(), e)),
+            };
+            if writeln!(w, "{}", header.join(",")).is_err() {
+                return Err(format!("Failed to write log {}", self.file.display()));
+            }
+            self.writer = Some(w);
+        }
+        match self.writer.as_mut().map(|w| writeln!(w, "{}", row.join(","))) {
+            Some(Ok(_)) => Ok(()),
+            _ => Err(format!("Failed to write log {}", self.file.display())),
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(w) = self.writer.as_mut() {
+            let _ = w.flush();
+        }
+    }
+}
+
+// one column per scalar, arrays are expanded into their elements
+pub(crate) fn flatten(name : &str, val : &rmpv::Value, header : &mut Vec<String>, row : &mut Vec<String>) {
+    match val {
+        rmpv::Value::Array(arr) => {
+            for (i, v) in arr.iter().enumerate() {
+                flatten(&format!("{}[{}]", name, i), v, header, row);
+            }
+        },
+        rmpv::Value::String(s) => {
+            header.push(name.to_string());
+            row.push(format!("\"{}\"", s.as_str().unwrap_or_default().replace('"', "\"\"")));
+        },
+        v => {
+            header.push(name.to_string());
+            row.push(v.to_string());
+        }
+    }
+}
+
+/// Why the timeline halted the engine at a frame boundary
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Halt {
+    PAUSE,
+    END,
+    ERR,
+}
+
+/// Action executed by the context owning the model it applies to
+#[derive(Clone)]
+pub enum TimelineRequest {
+    /// model, field path, msgpack
+    SET(String, String, Vec<u8>),
+    INJECT(Fault),
+    /// signal
+    CLEAR(String),
+}
+
+impl TimelineRequest {
+    fn model(&self) -> &str {
+        match self {
+            TimelineRequest::SET(name, _, _) => name,
+            TimelineRequest::INJECT(f) => f.model().unwrap_or_default(),
+            TimelineRequest::CLEAR(signal) => split_signal(signal).map(|s| s.0).unwrap_or_default(),
+        }
+    }
+}
+
+/// Timeline
+/// Scripted scenario evaluated by the engine at frame boundaries. At the boundary
+/// before a frame every context has completed the previous frame: the watched
+/// signals are sampled, logs are recorded, and the entries whose time has come or
+/// whose condition became true fire. Their actions apply from the frame that follows,
+/// so a scenario behaves the same on every engine
+#[derive(Clone, Default)]
+pub struct Timeline {
+    pub entries : Vec<TimelineEntry>,
+    pub logs : Vec<SignalLog>,
+    pub monitors : Vec<Monitor>,
+    /// hashes of the model states, for determinism checks
+    pub hasher : Option<StateHasher>,
+    /// history of signals and events, written to files when the run fails
+    pub recorder : Option<Recorder>,
+    /// signal values sampled at the current frame boundary
+    values : HashMap<String, rmpv::Value>,
+    pending : Vec<TimelineRequest>,
+    /// set when an action or error halts the engine, cleared when execution resumes
+    pub halt : Option<Halt>,
+    /// why the timeline failed
+    pub error : Option<String>,
+    /// actions, monitor failures, recorder dumps and errors, with their simulation time, until taken by the engine user
+    pub events : Vec<(f64, String)>,
+    /// last frame boundary evaluated
+    evaluated : Option<u64>,
+    /// simulation time of the last frame boundary evaluated, seconds
+    now : f64,
+    /// model errors of the current frame, dumped at the boundary after it
+    errors : Vec<String>,
+    /// signals sampled for engine users, e.g. streamed by the server
+    pub watched : Vec<String>,
+    /// values of the watched signals at the last frame boundary evaluated, with its time
+    snapshot : Option<(f64, Vec<(String, rmpv::Value)>)>,
+}
+
+impl Timeline {
+    pub fn new(entries : Vec<TimelineEntry>, logs : Vec<SignalLog>, monitors : Vec<Monitor>) -> Timeline {
+        Timeline {
+            entries,
+            logs,
+            monitors,
+            ..Default::default()
+        }
+    }
+
+    /// True if there is nothing to evaluate at frame boundaries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.logs.is_empty() && self.monitors.is_empty() && self.hasher.is_none() && self.recorder.is_none()
+            && self.watched.is_empty()
+    }
+
+    /// Signals sampled at every frame boundary
+    pub fn watches(&self) -> Vec<String> {
+        let mut out : Vec<String> = Vec::new();
+        let signals = self.entries.iter().flat_map(|e| e.signals())
+            .chain(self.logs.iter().flat_map(|l| l.signals.clone()))
+            .chain(self.monitors.iter().flat_map(|m| m.signals()))
+            .chain(self.recorder.iter().flat_map(|r| r.signals.clone()))
+            .chain(self.watched.iter().cloned());
+        for s in signals {
+            if !out.contains(&s) {
+                out.push(s);
+            }
+        }
+        out
+    }
+
+    /// Return to the state before the scenario started, logs are rewritten by the next run
+    pub fn reset(&mut self) {
+        for e in self.entries.iter_mut() {
+            e.fired = false;
+            e.was_true = false;
+        }
+        for l in self.logs.iter_mut() {
+            l.flush();
+            l.writer = None;
+            l.recording = l.enabled;
+        }
+        for m in self.monitors.iter_mut() {
+            m.reset();
+        }
+        if let Some(h) = self.hasher.as_mut() {
+            h.reset();
+        }
+        if let Some(r) = self.recorder.as_mut() {
+            r.reset();
+        }
+        self.values.clear();
+        self.pending.clear();
+        self.halt = None;
+        self.error = None;
+        self.events.clear();
+        self.evaluated = None;
+        self.now = 0.0;
+        self.errors.clear();
+        self.snapshot = None;
+    }
+
+    /// Sample the signals at every frame boundary from the next run, in addition to those already watched
+    pub fn watch(&mut self, signals : &[String]) {
+        for s in signals {
+            if !self.watched.contains(s) {
+                self.watched.push(s.clone());
+            }
+        }
+    }
+
+    /// Values of watched signals at the last frame boundary evaluated, with its time.
+    /// None until a boundary is evaluated, or if a signal is not watched
+    pub fn snapshot(&self, signals : &[String]) -> Option<(f64, Vec<(String, rmpv::Value)>)> {
+        let (time, values) = self.snapshot.as_ref()?;
+        let mut out = Vec::new();
+        for s in signals {
+            out.push(values.iter().find(|(v, _)| v == s)?.clone());
+        }
+        Some((*time, out))
+    }
+
+    /// Sample the watched signals of the models executed by a context
+    /// @param[in] names - model names of the context
+    pub fn sample(&mut self, tc : &mut dyn ThreadContext, names : &[String]) -> Result<(), String> {
+        if let Some(r) = self.recorder.as_mut() {
+            r.watch_outputs(tc, names)?;
+        }
+        for signal in self.watches() {
+            let (name, path) = split_signal(&signal)?;
+            if !names.iter().any(|n| n == name) {
+                continue;
+            }
+            let val = decode(&tc.get_signal(name, path)?)?;
+            self.values.insert(signal, val);
+        }
+        // boundaries are evaluated in order, a boundary sampled again is not evaluated again
+        let boundary = self.evaluated.map_or(0, |f| f + 1);
+        if let Some(h) = self.hasher.as_mut() {
+            if h.due(boundary) {
+                h.add(tc.hash_state()?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute the actions queued for the models of a context
+    /// @param[in] names - model names of the context
+    pub fn apply(&mut self, tc : &mut dyn ThreadContext, names : &[String]) -> Result<(), String> {
+        // the state of the models is captured before the actions change it
+        if self.recorder.as_ref().is_some_and(|r| r.capturing()) {
+            let models = capture(tc)?;
+            self.add_state(models)?;
+        }
+        let (mine, rest) : (Vec<TimelineRequest>, Vec<TimelineRequest>) = self.pending.drain(..)
+            .partition(|r| names.iter().any(|n| n == r.model()));
+        self.pending = rest;
+        for req in mine {
+            match req {
+                TimelineRequest::SET(name, path, mp) => tc.set_signal(&name, &path, &mp)?,
+                TimelineRequest::INJECT(fault) => tc.inject(fault)?,
+                TimelineRequest::CLEAR(signal) => tc.clear_faults(&signal)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the frame boundary before a frame, once the watched signals are sampled.
+    /// Evaluating a boundary again has no effect
+    /// @param[in] frame - number of frames executed
+    /// @param[in] delta - duration of a frame, seconds
+    pub fn boundary(&mut self, frame : u64, delta : f64) {
+        if self.evaluated == Some(frame) {
+            if let Some(h) = self.hasher.as_mut() {
+                h.discard();
+            }
+            return;
+        }
+        self.evaluated = Some(frame);
+        self.now = frame as f64 * delta;
+        // every context has sampled this boundary, so the values are of the same frame
+        if !self.watched.is_empty() {
+            let values = self.watched.iter().filter_map(|s| self.values.get(s).map(|v| (s.clone(), v.clone()))).collect();
+            self.snapshot = Some((self.now, values));
+        }
+        if let Some(r) = self.recorder.as_mut() {
+            r.settle();
+        }
+        if let Err(e) = self.evaluate(frame, delta) {
+            self.fail(format!("Timeline failed at frame {}: {}", frame, e));
+        }
+    }
+
+    /// Halt the engine on an error, which fails the verdict
+    pub fn fail(&mut self, error : String) {
+        self.events.push((self.now, error.clone()));
+        if let Some(r) = self.recorder.as_mut() {
+            r.event(&error);
+        }
+        self.trip("timeline error");
+        self.halt = Some(Halt::ERR);
+        self.error = Some(error);
+    }
+
+    /// Write the flight recorder to files, returning the signal file
+    pub fn dump(&mut self, reason : &str) -> Result<PathBuf, String> {
+        match self.recorder.as_mut() {
+            Some(r) => r.dump(reason),
+            None => Err("The scene has no [recorder]".to_string()),
+        }
+    }
+
+    /// Add models to the state reports of the recorder dumps at the current frame boundary
+    pub fn add_state(&mut self, models : Vec<ModelState>) -> Result<(), String> {
+        match self.recorder.as_mut() {
+            Some(r) => r.add_state(models),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep the failures of the models of a context with the engine events
+    pub fn model_failures(&mut self, failures : Vec<(f64, String)>) {
+        for (time, text) in failures {
+            if let Some(r) = self.recorder.as_mut() {
+                r.event(&text);
+            }
+            self.events.push((time, text));
+        }
+    }
+
+    /// A model errored during the frame, the recorder is dumped at the boundary after it
+    /// so that the history includes the frame
+    pub fn model_error(&mut self, reason : String) {
+        if self.recorder.is_some() {
+            self.errors.push(reason);
+        }
+    }
+
+    /// Dump the flight recorder if there is one, e.g. when a monitor fails
+    pub fn trip(&mut self, reason : &str) {
+        let before = match self.recorder.as_ref() {
+            Some(r) => r.dumps(),
+            None => return,
+        };
+        match self.dump(reason) {
+            // a reason already dumped at this time is not written again
+            Ok(file) if self.recorder.as_ref().is_some_and(|r| r.dumps() > before) => {
+                self.events.push((self.now, format!("recorder dumped to {}: {}", file.display(), reason)));
+            },
+            Ok(_) => {},
+            Err(e) => self.events.push((self.now, e)),
+        }
+    }
+
+    fn evaluate(&mut self, frame : u64, delta : f64) -> Result<(), String> {
+        let time = frame as f64 * delta;
+
+        // logs and monitors see the values produced by the previous frame, stamped with its time
+        if frame > 0 {
+            let produced = frame - 1;
+            for l in self.logs.iter_mut() {
+                let every = ((1.0 / (l.rate * delta)).round() as u64).max(1);
+                if l.recording && produced.is_multiple_of(every) {
+                    l.record(produced as f64 * delta, &self.values)?;
+                }
+            }
+            if let Some(r) = self.recorder.as_mut() {
+                r.record(produced as f64 * delta, &self.values);
+            }
+            let mut tripped = Vec::new();
+            for m in self.monitors.iter_mut() {
+                let passing = m.violation.is_none();
+                if let Err(e) = m.evaluate(produced as f64 * delta, &self.values) {
+                    return Err(format!("monitor [{}]: {}", m.name, e));
+                }
+                if let (true, Some(v)) = (passing, &m.violation) {
+                    let shown = v.values.iter().map(|(s, x)| format!("{} = {}", s, x)).collect::<Vec<String>>();
+                    self.events.push((v.time, format!("monitor [{}] failed: {} ({})", m.name, v.message, shown.join(", "))));
+                    tripped.push(m.name.clone());
+                }
+            }
+            for name in tripped {
+                if let Some(r) = self.recorder.as_mut() {
+                    r.event(&format!("monitor [{}] failed", name));
+                }
+                self.trip(&format!("monitor [{}] failed", name));
+            }
+            for reason in std::mem::take(&mut self.errors) {
+                if let Some(r) = self.recorder.as_mut() {
+                    r.event(&reason);
+                }
+                self.trip(&reason);
+            }
+            let overrun = self.recorder.as_mut().and_then(|r| r.overrun());
+            if let Some(elapsed) = overrun {
+                self.trip(&format!("watchdog, frame {} took {:.3} ms", produced, elapsed * 1e3));
+            }
+        }
+        if let Some(h) = self.hasher.as_mut() {
+            match h.due(frame) {
+                true => h.record(frame, delta)?,
+                false => h.discard(),
+            }
+        }
+
+        let mut fired = Vec::new();
+        for (i, e) in self.entries.iter_mut().enumerate() {
+            let fire = match &e.trigger {
+                Trigger::AT(at) => !e.fired && time >= at - TIME_EPS,
+                Trigger::WHEN(c) => {
+                    let now = c.holds(&self.values)?;
+                    let rising = now && !e.was_true;
+                    e.was_true = now;
+                    rising && (e.repeat || !e.fired)
+                },
+            };
+            if fire {
+                e.fired = true;
+                fired.push(i);
+            }
+        }
+
+        for i in fired {
+            let actions = self.entries[i].actions.clone();
+            for a in actions {
+                self.events.push((time, format!("timeline [{}]: {}", i, describe(&a))));
+                if let Some(r) = self.recorder.as_mut() {
+                    r.event(&format!("timeline [{}]: {}", i, describe(&a)));
+                }
+                match a {
+                    Action::SET(signal, val) => {
+                        let (name, path) = split_signal(&signal)?;
+                        self.pending.push(TimelineRequest::SET(name.to_string(), path.to_string(), encode(&val)?));
+                    },
+                    Action::FAULT(fault, duration) => {
+                        let mut fault = fault;
+                        if let Some(d) = duration {
+                            fault.start = time;
+                            fault.stop = Some(time + d);
+                        }
+                        self.pending.push(TimelineRequest::INJECT(fault));
+                    },
+                    Action::CLEAR(signal) => self.pending.push(TimelineRequest::CLEAR(signal)),
+                    Action::LOG(on) => {
+                        for l in self.logs.iter_mut() {
+                            l.recording = on;
+                            l.flush();
+                        }
+                    },
+                    Action::PAUSE => {
+                        if self.halt.is_none() {
+                            self.halt = Some(Halt::PAUSE);
+                        }
+                    },
+                    Action::END => {
+                        if self.halt != Some(Halt::ERR) {
+                            self.halt = Some(Halt::END);
+                        }
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Monitor results so far, failed if the timeline failed
+    pub fn verdict(&self) -> Verdict {
+        Verdict {
+            monitors : self.monitors.iter().map(|m| MonitorResult {
+                name : m.name.clone(),
+                check : m.check.text.clone(),
+                violation : m.violation.clone(),
+            }).collect(),
+            error : self.error.clone(),
+        }
+    }
+
+    /// Write buffered log rows to their files, at the end of every step
+    pub fn flush(&mut self) {
+        // frames are not timed across a pause
+        if let Some(r) = self.recorder.as_mut() {
+            r.pause();
+        }
+        for l in self.logs.iter_mut() {
+            l.flush();
+        }
+        if let Some(h) = self.hasher.as_mut() {
+            h.flush();
+        }
+    }
+}
+
+fn describe(a : &Action) -> String {
+    match a {
+        Action::SET(signal, val) => format!("set {} = {}", signal, val),
+        Action::FAULT(fault, _) => format!("fault {} {}", fault.signal, fault.kind.describe()),
+        Action::CLEAR(signal) => format!("clear faults on {}", signal),
+        Action::LOG(true) => "start logging".to_string(),
+        Action::LOG(false) => "stop logging".to_string(),
+        Action::PAUSE => "pause".to_string(),
+        Action::END => "end".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(txt : &str) -> Result<TimelineEntry, String> {
+        TimelineEntry::parse(&toml::Value::Table(txt.parse::<toml::Table>().unwrap()), "timeline[0]")
+    }
+
+    fn log(txt : &str) -> Result<SignalLog, String> {
+        SignalLog::parse(&toml::Value::Table(txt.parse::<toml::Table>().unwrap()), "logging[0]", "log0")
+    }
+
+    // evaluates the boundaries before the frames, x counting the frames executed
+    fn run(timeline : &mut Timeline, frames : u64) -> Vec<u64> {
+        let mut halted = Vec::new();
+        for frame in 0..frames {
+            timeline.values.insert("m.x".to_string(), rmpv::Value::F64(frame as f64));
+            timeline.boundary(frame, 0.1);
+            if timeline.halt.take().is_some() {
+                halted.push(frame);
+            }
+        }
+        halted
+    }
+
+    #[test]
+    fn actions_parse_in_a_fixed_order() {
+        let e = entry("end = true\nat = \"2 s\"\nlog = \"stop\"\nclear = \"m.x\"\nset = { \"m.y\" = 1.5 }").unwrap();
+        assert!(matches!(e.trigger, Trigger::AT(t) if t == 2.0));
+        let described : Vec<String> = e.actions.iter().map(describe).collect();
+        assert_eq!(described, vec!["set m.y = 1.5", "clear faults on m.x", "stop logging", "end"]);
+        assert_eq!(entry("when = \"m.x > 1\"\npause = true").unwrap().signals(), vec!["m.x"]);
+    }
+
+    #[test]
+    fn malformed_entries_are_rejected() {
+        assert!(entry("pause = true").is_err());
+        assert!(entry("at = 1\nwhen = \"m.x > 1\"\npause = true").is_err());
+        assert!(entry("at = 1").is_err());
+        assert!(entry("at = 1\npause = false").is_err());
+        assert!(entry("at = 1\nstop = true").is_err());
+        assert!(entry("at = 1\nrepeat = true\npause = true").is_err());
+        assert!(entry("at = 1\nset = { x = 1 }").is_err());
+        assert!(entry("at = 1\nlog = \"restart\"").is_err());
+    }
+
+    #[test]
+    fn entries_fire_at_their_time_and_on_rising_edges() {
+        let entries = vec![
+            entry("at = 0.3\nset = { \"m.y\" = 2 }").unwrap(),
+            entry("when = \"m.x >= 2 && m.x < 4 || m.x >= 6\"\nrepeat = true\npause = true").unwrap(),
+            entry("when = \"m.x >= 8\"\nend = true").unwrap(),
+        ];
+        let mut timeline = Timeline::new(entries, vec![], vec![]);
+        assert_eq!(timeline.watches(), vec!["m.x"]);
+        assert_eq!(run(&mut timeline, 10), vec![2, 6, 8]);
+        assert_eq!(timeline.pending.len(), 1);
+        assert!(matches!(&timeline.pending[0], TimelineRequest::SET(m, p, _) if m == "m" && p == "y"));
+        let times : Vec<f64> = timeline.events.iter().map(|(t, _)| (t * 10.0).round()).collect();
+        assert_eq!(times, vec![2.0, 3.0, 6.0, 8.0]);
+
+        timeline.reset();
+        assert!(timeline.events.is_empty() && timeline.pending.is_empty());
+        assert_eq!(run(&mut timeline, 3), vec![2]);
+    }
+
+    #[test]
+    fn boundaries_are_evaluated_once() {
+        let mut timeline = Timeline::new(vec![entry("at = 0\npause = true").unwrap()], vec![], vec![]);
+        timeline.boundary(0, 0.1);
+        assert_eq!(timeline.halt.take(), Some(Halt::PAUSE));
+        timeline.boundary(0, 0.1);
+        assert_eq!(timeline.halt, None);
+        assert_eq!(timeline.events.len(), 1);
+    }
+
+    #[test]
+    fn logs_parse_with_defaults() {
+        let l = log("rate = 10\nsignals = [\"m.x\"]").unwrap();
+        assert_eq!(l.file, PathBuf::from("log0.csv"));
+        assert_eq!(l.rate, 10.0);
+        assert!(l.enabled);
+        assert!(log("rate = 0\nsignals = [\"m.x\"]").is_err());
+        assert!(log("signals = [\"m.x\"]").is_err());
+        assert!(log("rate = 1\nsignals = [\"x\"]").is_err());
+        assert!(log("rate = 1\nsignals = [\"m.x\"]\nenabled = 1").is_err());
+    }
+
+    #[test]
+    fn logs_record_the_previous_frame_at_their_rate() {
+        let file = std::env::temp_dir().join(format!("rsis_timeline_{}.csv", std::process::id()));
+        let mut l = log("rate = 5\nsignals = [\"m.x\"]").unwrap();
+        l.file = file.clone();
+        let stop = entry("at = 0.5\nlog = \"stop\"").unwrap();
+        let mut timeline = Timeline::new(vec![stop], vec![l], vec![]);
+        run(&mut timeline, 10);
+        timeline.flush();
+        let txt = std::fs::read_to_string(&file).unwrap();
+        assert_eq!(txt, "time,m.x\n0,1\n0.2,3\n0.4,5\n");
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn arrays_and_strings_are_flattened() {
+        let (mut header, mut row) = (vec![], vec![]);
+        let val = rmpv::Value::Array(vec![rmpv::Value::from(1), rmpv::Value::Array(vec![rmpv::Value::from("a\"b")])]);
+        flatten("m.v", &val, &mut header, &mut row);
+        assert_eq!(header, vec!["m.v[0]", "m.v[1][0]"]);
+        assert_eq!(row, vec!["1", "\"a\"\"b\""]);
+    }
+}
@@ -0,0 +1,272 @@
+extern crate toml;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// What a traced span measures
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TraceKind {
+    /// model step
+    #[default]
+    STEP,
+    /// connections copied into the model before its step
+    INPUTS,
+    /// connections published by the model after its step
+    OUTPUTS,
+    /// waiting for the other threads at a frame boundary
+    BARRIER,
+    /// signal logs written to their files
+    FLUSH,
+}
+
+impl TraceKind {
+    fn category(&self) -> &'static str {
+        match self {
+            TraceKind::STEP => "step",
+            TraceKind::INPUTS | TraceKind::OUTPUTS => "connection",
+            TraceKind::BARRIER => "barrier",
+            TraceKind::FLUSH => "flush",
+        }
+    }
+}
+
+/// TraceEvent
+/// Span recorded by a thread, nanoseconds from the start of the trace
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceEvent {
+    pub kind : TraceKind,
+    /// index of the model in its thread, for model spans
+    pub model : u32,
+    pub begin : u64,
+    pub end : u64,
+}
+
+/// TraceBuffer
+/// Ring buffer of the spans of a thread, allocated when the scene is built so that
+/// recording does not allocate. The oldest spans are overwritten when it is full
+#[derive(Clone, Debug)]
+pub struct TraceBuffer {
+    epoch : Instant,
+    events : Vec<TraceEvent>,
+    next : usize,
+    /// spans recorded since the start, including those overwritten
+    pub recorded : u64,
+}
+
+impl TraceBuffer {
+    /// @param[in] epoch - start of the trace, shared by every thread
+    pub fn new(capacity : usize, epoch : Instant) -> TraceBuffer {
+        TraceBuffer {
+            epoch,
+            events : vec![TraceEvent::default(); capacity.max(1)],
+            next : 0,
+            recorded : 0,
+        }
+    }
+
+    pub fn record(&mut self, kind : TraceKind, model : usize, begin : Instant, end : Instant) {
+        self.events[self.next] = TraceEvent {
+            kind,
+            model : model as u32,
+            begin : begin.saturating_duration_since(self.epoch).as_nanos() as u64,
+            end : end.saturating_duration_since(self.epoch).as_nanos() as u64,
+        };
+        self.next = (self.next + 1) % self.events.len();
+        self.recorded += 1;
+    }
+
+    /// Spans kept by the buffer, oldest first
+    pub fn events(&self) -> Vec<TraceEvent> {
+        let cap = self.events.len();
+        if self.recorded < cap as u64 {
+            return self.events[..self.next].to_vec();
+        }
+        self.events[self.next..].iter().chain(self.events[..self.next].iter()).cloned().collect()
+    }
+
+    /// Spans overwritten because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.recorded.saturating_sub(self.events.len() as u64)
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.recorded = 0;
+    }
+}
+
+/// Start a span if the thread is traced
+pub fn begin(buf : &Option<TraceBuffer>) -> Option<Instant> {
+    buf.as_ref().map(|_| Instant::now())
+}
+
+/// End a span started by begin
+pub fn end(buf : &mut Option<TraceBuffer>, start : Option<Instant>, kind : TraceKind, model : usize) {
+    if let (Some(b), Some(s)) = (buf.as_mut(), start) {
+        b.record(kind, model, s, Instant::now());
+    }
+}
+
+/// ThreadTrace
+/// Spans of a thread with the names of its models
+#[derive(Clone, Debug)]
+pub struct ThreadTrace {
+    pub tid : usize,
+    pub models : Vec<String>,
+    pub events : Vec<TraceEvent>,
+    pub dropped : u64,
+}
+
+/// Tracing
+/// Scene [tracing] table
+#[derive(Clone, Debug)]
+pub struct Tracing {
+    /// spans kept per thread
+    pub capacity : usize,
+    /// Chrome trace-event JSON file
+    pub file : PathBuf,
+}
+
+impl Tracing {
+    /// Parse the [tracing] table
+    /// @param[in] name - default file name, without the extension
+    pub fn parse(val : &toml::Value, name : &str) -> Result<Tracing, String> {
+        let tbl = match val.as_table() {
+            Some(t) => t,
+            None => return Err("[tracing] is not a table".to_string()),
+        };
+        for key in tbl.keys() {
+            if !["capacity", "file"].contains(&key.as_str()) {
+                return Err(format!("[tracing].{} is not a tracing setting", key));
+            }
+        }
+        let capacity = match tbl.get("capacity") {
+            Some(toml::Value::Integer(n)) if *n > 0 => *n as usize,
+            Some(_) => return Err("[tracing].capacity must be a positive number of spans".to_string()),
+            None => 1 << 16,
+        };
+        let file = match tbl.get("file") {
+            Some(toml::Value::String(f)) => PathBuf::from(f),
+            Some(_) => return Err("[tracing].file is not a string".to_string()),
+            None => PathBuf::from(format!("{}_trace.json", name)),
+        };
+        Ok(Tracing { capacity, file })
+    }
+}
+
+fn escape(txt : &str) -> String {
+    let mut out = String::new();
+    for c in txt.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write spans as Chrome trace-event JSON, which loads in Perfetto and chrome://tracing.
+/// Each engine thread is a track named after its models and the number of spans its buffer dropped
+pub fn write_chrome_trace(file : &Path, threads : &[ThreadTrace]) -> Result<(), String> {
+    let mut rows = Vec::new();
+    for t in threads {
+        let mut label = format!("thread {} ({})", t.tid, t.models.join(", "));
+        if t.dropped > 0 {
+            label.push_str(&format!(", {} oldest spans dropped", t.dropped));
+        }
+        rows.push(format!("{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+            t.tid, escape(&label)));
+        for e in t.events.iter() {
+            let model = t.models.get(e.model as usize).map(|m| m.as_str()).unwrap_or("?");
+            let name = match e.kind {
+                TraceKind::STEP => model.to_string(),
+                TraceKind::INPUTS => format!("{} inputs", model),
+                TraceKind::OUTPUTS => format!("{} outputs", model),
+                TraceKind::BARRIER => "barrier".to_string(),
+                TraceKind::FLUSH => "log flush".to_string(),
+            };
+            // timestamps are microseconds
+            rows.push(format!("{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}",
+                escape(&name), e.kind.category(), t.tid, e.begin as f64 / 1e3, e.end.saturating_sub(e.begin) as f64 / 1e3));
+        }
+    }
+    let mut w = match File::create(file) {
+        Ok(f) => BufWriter::new(f),
+        Err(e) => return Err(format!("Unable to create trace {}: {}", file.display(), e)),
+    };
+    let txt = format!("{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[\n{}\n]}}\n", rows.join(",\n"));
+    match w.write_all(txt.as_bytes()).and_then(|_| w.flush()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to write trace {}: {}", file.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    fn span(buf : &mut TraceBuffer, kind : TraceKind, model : usize, begin : u64, end : u64) {
+        let epoch = buf.epoch;
+        buf.record(kind, model, epoch + Duration::from_nanos(begin), epoch + Duration::from_nanos(end));
+    }
+
+    #[test]
+    fn full_buffers_keep_the_newest_spans() {
+        let mut buf = TraceBuffer::new(3, Instant::now());
+        for i in 0..2 {
+            span(&mut buf, TraceKind::STEP, i, i as u64 * 10, i as u64 * 10 + 5);
+        }
+        assert_eq!(buf.events().iter().map(|e| e.model).collect::<Vec<u32>>(), vec![0, 1]);
+        assert_eq!(buf.dropped(), 0);
+        for i in 2..5 {
+            span(&mut buf, TraceKind::STEP, i, i as u64 * 10, i as u64 * 10 + 5);
+        }
+        assert_eq!(buf.events().iter().map(|e| e.model).collect::<Vec<u32>>(), vec![2, 3, 4]);
+        assert_eq!(buf.events()[0].begin, 20);
+        assert_eq!(buf.dropped(), 2);
+        buf.clear();
+        assert!(buf.events().is_empty());
+    }
+
+    #[test]
+    fn tracing_parses_with_defaults() {
+        let parse = |txt : &str| Tracing::parse(&toml::Value::Table(txt.parse::<toml::Table>().unwrap()), "st");
+        let t = parse("").unwrap();
+        assert_eq!((t.capacity, t.file), (1 << 16, PathBuf::from("st_trace.json")));
+        assert_eq!(parse("capacity = 8\nfile = \"t.json\"").unwrap().capacity, 8);
+        assert!(parse("capacity = 0").is_err());
+        assert!(parse("rate = 1").is_err());
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\u000ad");
+    }
+
+    #[test]
+    fn threads_are_tracks_of_named_spans() {
+        let mut buf = TraceBuffer::new(2, Instant::now());
+        span(&mut buf, TraceKind::INPUTS, 0, 1000, 1500);
+        span(&mut buf, TraceKind::STEP, 0, 1500, 4000);
+        span(&mut buf, TraceKind::BARRIER, 0, 4000, 4250);
+        let thread = ThreadTrace { tid : 1, models : vec!["gen1".to_string()], events : buf.events(), dropped : buf.dropped() };
+        let file = std::env::temp_dir().join(format!("rsis_trace_{}.json", std::process::id()));
+        write_chrome_trace(&file, &[thread]).unwrap();
+        let txt = fs::read_to_string(&file).unwrap();
+        let _ = fs::remove_file(&file);
+        let rows : Vec<&str> = txt.lines().collect();
+        assert_eq!(rows, vec![
+            "{\"displayTimeUnit\":\"ns\",\"traceEvents\":[",
+            "{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{\"name\":\"thread 1 (gen1), 1 oldest spans dropped\"}},",
+            "{\"name\":\"gen1\",\"cat\":\"step\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":1.500,\"dur\":2.500},",
+            "{\"name\":\"barrier\",\"cat\":\"barrier\",\"ph\":\"X\",\"pid\":1,\"tid\":1,\"ts\":4.000,\"dur\":0.250}",
+            "]}",
+        ]);
+    }
+}
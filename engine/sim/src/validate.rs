@@ -0,0 +1,291 @@
+use crate::compose::Composition;
+use crate::connection::Transform;
+use crate::fault::{Fault, FaultOverride};
+use crate::metadata::{split_signal, ModelMetadata};
+use crate::plugin::PluginLoader;
+use crate::recorder::Recorded;
+use crate::scene::scene_from_table;
+use crate::timeline::Action;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Problem
+/// Something wrong with a scene, with the key of the scene file it was found at
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// e.g. `[schedule][1].lib`, empty if the problem is not at a key
+    pub key : String,
+    pub message : String,
+}
+
+impl Problem {
+    pub fn new(key : &str, message : &str) -> Problem {
+        Problem { key : key.to_string(), message : message.to_string() }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self.key.is_empty() {
+            true => write!(f, "{}", self.message),
+            false => write!(f, "{}: {}", self.key, self.message),
+        }
+    }
+}
+
+/// Validation
+/// Problems found in a scene file
+#[derive(Clone, Debug)]
+pub struct Validation {
+    pub file : PathBuf,
+    pub problems : Vec<Problem>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// One line per problem, prefixed with the scene file
+    pub fn report(&self) -> String {
+        let lines : Vec<String> = self.problems.iter().map(|p| format!("{}: {}", self.file.display(), p)).collect();
+        lines.join("\n")
+    }
+}
+
+// interfaces of the scheduled models, by instance name
+type Interfaces = HashMap<String, ModelMetadata>;
+
+// checks that a signal is a leaf field of a scheduled model
+fn check_signal(models : &Interfaces, signal : &str, key : &str, out : &mut Vec<Problem>) {
+    let (name, path) = match split_signal(signal) {
+        Ok(s) => s,
+        Err(e) => return out.push(Problem::new(key, &e)),
+    };
+    match models.get(name) {
+        Some(meta) => {
+            if let Err(e) = meta.find(path) {
+                out.push(Problem::new(key, &e));
+            }
+        },
+        None => out.push(Problem::new(key, &format!("model [{}] of [{}] is not scheduled", name, signal))),
+    }
+}
+
+/// Check a scene without running it, reporting every problem found: model libraries that
+/// do not load, duplicate instance names, rates that do not divide the thread rates,
+/// parameter files that do not match the model interfaces, connections whose fields do
+/// not exist or differ in type, dims or units, and signals of the faults, timeline, logs,
+/// monitors and recorder that do not exist. A scene without any of these is built, to
+/// report what only building finds
+pub fn validate(scene_file : &Path) -> Validation {
+    let mut v = Validation { file : scene_file.to_path_buf(), problems : Vec::new() };
+    // includes are resolved first, their problems are reported at the merged keys
+    let scene = match Composition::load(scene_file).and_then(|c| scene_from_table(&c.table, Path::new(""))) {
+        Ok(s) => s,
+        Err(e) => {
+            v.problems.push(Problem::new("", &e));
+            return v;
+        },
+    };
+    v.problems = scene.check();
+
+    // every library is loaded once, every instance is created to check its specialization
+    let mut loader = PluginLoader::new();
+    for p in scene.paths.iter() {
+        loader.add_path(p);
+    }
+    let mut models = Interfaces::new();
+    let mut missing = Vec::new();
+    for (i, m) in scene.schedule.iter().enumerate() {
+        let created = loader.load(&m.lib).map_err(|e| (format!("[schedule][{}].lib", i), e))
+            .and_then(|lib| lib.create(&m.generic).map_err(|e| (format!("[schedule][{}].generic", i), e)));
+        match created {
+            Ok(instance) => {
+                models.entry(m.name.clone()).or_insert(instance.meta.clone());
+            },
+            Err((key, e)) => {
+                v.problems.push(Problem::new(&key, &e));
+                missing.push(m.name.clone());
+            },
+        }
+    }
+    // signals of models that did not load are not checked again
+    let known = |s : &str| split_signal(s).map_or(true, |(name, _)| !missing.iter().any(|n| n == name));
+
+    // parameter files of unscheduled models are reported by the scene check
+    for p in scene.parameters.iter() {
+        for meta in p.models.iter().filter(|n| !missing.contains(n)).filter_map(|n| models.get(n)) {
+            if let Err(e) = p.values(meta) {
+                v.problems.push(Problem::new(&p.key, &e));
+            }
+        }
+    }
+
+    let mut index : HashMap<String, usize> = HashMap::new();
+    for c in scene.connections.iter() {
+        let pair = format!("{}:{}", c.src_model, c.dst_model);
+        let j = index.entry(pair.clone()).or_insert(0);
+        let key = format!("[connections].\"{}\"[{}]", pair, j);
+        *j += 1;
+        if !known(&c.src()) || !known(&c.dst()) {
+            continue;
+        }
+        let (src, dst) = match (models.get(&c.src_model), models.get(&c.dst_model)) {
+            (Some(s), Some(d)) => (s, d),
+            (None, _) => {
+                v.problems.push(Problem::new(&key, &format!("model [{}] is not scheduled", c.src_model)));
+                continue;
+            },
+            (_, None) => {
+                v.problems.push(Problem::new(&key, &format!("model [{}] is not scheduled", c.dst_model)));
+                continue;
+            },
+        };
+        match c.resolve(src, dst) {
+            Ok(pairs) => {
+                for (s, d) in pairs {
+                    if let Err(e) = Transform::new(c, &s.field, &d.field) {
+                        v.problems.push(Problem::new(&key, &e));
+                    }
+                }
+            },
+            Err(e) => v.problems.push(Problem::new(&key, &e)),
+        }
+    }
+
+    let mut signals : Vec<(String, String)> = Vec::new();
+    let mut faults : Vec<(String, Fault)> = Vec::new();
+    for (i, f) in scene.faults.iter().enumerate() {
+        faults.push((format!("[faults][{}]", i), f.clone()));
+    }
+    for (i, e) in scene.timeline.iter().enumerate() {
+        let key = format!("[timeline][{}]", i);
+        for s in e.signals() {
+            signals.push((key.clone(), s));
+        }
+        for a in e.actions.iter() {
+            match a {
+                Action::SET(s, _) | Action::CLEAR(s) => signals.push((key.clone(), s.clone())),
+                Action::FAULT(f, _) => faults.push((key.clone(), f.clone())),
+                _ => {},
+            }
+        }
+    }
+    for (i, l) in scene.logging.iter().enumerate() {
+        for (j, s) in l.signals.iter().enumerate() {
+            signals.push((format!("[logging][{}].signals[{}]", i, j), s.clone()));
+        }
+    }
+    for (i, m) in scene.monitors.iter().enumerate() {
+        for s in m.signals() {
+            signals.push((format!("[monitors][{}]", i), s));
+        }
+    }
+    if let Some(Recorded::LIST(list)) = scene.recorder.as_ref().map(|r| &r.recorded) {
+        for (j, s) in list.iter().enumerate() {
+            signals.push((format!("[recorder].signals[{}]", j), s.clone()));
+        }
+    }
+    // faults are resolved against the model interface without arming them
+    for (key, f) in faults.into_iter().filter(|(_, f)| known(&f.signal)) {
+        let checked = f.model().and_then(|name| match models.get(name) {
+            Some(meta) => FaultOverride::new(f.clone(), meta, false).map(|_| ()),
+            None => Err(format!("model [{}] of [{}] is not scheduled", name, f.signal)),
+        });
+        if let Err(e) = checked {
+            v.problems.push(Problem::new(&key, &e));
+        }
+    }
+    for (key, s) in signals.iter().filter(|(_, s)| known(s)) {
+        check_signal(&models, s, key, &mut v.problems);
+    }
+
+    // building checks rate transitions, delays and the order of the models
+    if v.problems.is_empty() {
+        let mut loader = PluginLoader::new();
+        if let Err(e) = scene.build(&mut loader) {
+            v.problems.push(Problem::new("", &e));
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_text(name : &str, txt : &str) -> Validation {
+        let dir = std::env::temp_dir().join(format!("rsis_validate_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("scene.toml");
+        std::fs::write(&file, txt).unwrap();
+        let v = validate(&file);
+        let _ = std::fs::remove_dir_all(&dir);
+        v
+    }
+
+    fn keys(v : &Validation) -> Vec<&str> {
+        v.problems.iter().map(|p| p.key.as_str()).collect()
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_its_key() {
+        let v = validate_text("keys", r#"
+[scene]
+name = "broken"
+engine = "sim"
+
+[[threads]]
+freq = 10.0
+[[threads]]
+freq = 3.0
+
+[[schedule]]
+lib = "no_such_model"
+name = "a"
+freq = 10.0
+
+[[schedule]]
+lib = "no_such_model"
+name = "a"
+freq = 4.0
+thread = 1
+
+[[logging]]
+rate = 1.0
+signals = ["a.output", "b.output"]
+
+[[timeline]]
+at = 1.0
+set = { "c.input" = 1.0 }
+"#);
+        assert!(!v.is_valid());
+        assert_eq!(keys(&v), vec![
+            "[threads][1].freq",
+            "[schedule][1].name",
+            "[schedule][1].freq",
+            "[schedule][0].lib",
+            "[schedule][1].lib",
+            "[timeline][0]",
+            "[logging][0].signals[1]",
+        ]);
+        assert!(v.problems[6].message.contains("[b] of [b.output] is not scheduled"));
+    }
+
+    #[test]
+    fn scenes_that_do_not_load_are_one_problem() {
+        let v = validate_text("load", "[scene]\nname = \"broken\"\n[[schedule]]\nname = \"a\"\n");
+        assert_eq!(keys(&v), vec![""]);
+        assert_eq!(v.report().lines().count(), 1);
+        assert!(v.report().starts_with(&v.file.display().to_string()));
+    }
+
+    #[test]
+    fn problems_print_with_their_key() {
+        assert_eq!(Problem::new("[scene].stop", "is negative").to_string(), "[scene].stop: is negative");
+        assert_eq!(Problem::new("", "no scene").to_string(), "no scene");
+    }
+}
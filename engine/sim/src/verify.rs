@@ -0,0 +1,157 @@
+use crate::connection::decode;
+use crate::engine::Engine;
+use crate::hash::StateHasher;
+use crate::plugin::PluginLoader;
+use crate::scene::{load_scene, ThreadEntry};
+use crate::serial::start_serial_engine;
+use crate::state::EngineState;
+use crate::runner::{advance, finish, init_failure, wait_init};
+use crate::{start_engine_boxed, TIME_EPS};
+
+use std::path::Path;
+
+/// How a verification run executes the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// the threads of the scene on a SerialEngine
+    SERIAL,
+    /// the threads of the scene on a SimEngine, one OS thread each
+    PARALLEL,
+    /// every model on a single thread, on a SerialEngine
+    SINGLE,
+}
+
+impl Layout {
+    pub fn parse(txt : &str) -> Result<Layout, String> {
+        match txt {
+            "serial" => Ok(Layout::SERIAL),
+            "parallel" => Ok(Layout::PARALLEL),
+            "single" => Ok(Layout::SINGLE),
+            _ => Err(format!("{} is not a layout, expected serial, parallel or single", txt)),
+        }
+    }
+}
+
+/// Mismatch
+/// First difference between two runs of a scene
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub frame : u64,
+    pub time : f64,
+    /// first diverging signal, None if the runs stopped at different frames
+    pub signal : Option<String>,
+    /// what each run has, a value or the frame it stopped at
+    pub first : String,
+    pub second : String,
+}
+
+impl Mismatch {
+    pub fn report(&self) -> String {
+        match &self.signal {
+            Some(s) => format!("diverged at frame {} (t={:.6}): [{}] is {} in the first run and {} in the second",
+                self.frame, self.time, s, self.first, self.second),
+            None => format!("diverged at frame {} (t={:.6}): the first run {} and the second {}",
+                self.frame, self.time, self.first, self.second),
+        }
+    }
+}
+
+/// Verification
+/// Outcome of running a scene twice in lockstep
+#[derive(Clone, Debug)]
+pub struct Verification {
+    /// frames whose state hashes were compared and matched
+    pub frames : u64,
+    /// first difference, None if the runs are identical
+    pub mismatch : Option<Mismatch>,
+}
+
+// builds and initializes the scene on an engine that hashes every N frames,
+// returning the engine and the duration of its frames
+fn start(scene_file : &Path, layout : Layout, every : u64) -> Result<(Box<dyn Engine + Send>, f64), String> {
+    let mut scene = load_scene(scene_file)?;
+    // both runs would write the same files
+    scene.logging.clear();
+    scene.hashing = Some(StateHasher::new(every, None));
+    // engines step at the rate of the fastest thread
+    let freq = scene.threads.iter().map(|t| t.freq).fold(0.0, f64::max);
+    if layout == Layout::SINGLE {
+        scene.threads = vec![ThreadEntry { freq }];
+        for m in scene.schedule.iter_mut() {
+            m.thread = 0;
+        }
+    }
+    let mut loader = PluginLoader::new();
+    let tcs = scene.build(&mut loader)?;
+    let mut engine : Box<dyn Engine + Send> = match layout {
+        Layout::PARALLEL => start_engine_boxed(tcs, false)?,
+        _ => Box::new(start_serial_engine(tcs)?),
+    };
+    engine.set_timeline(scene.timeline());
+    if engine.init() != 0 || !wait_init(&mut engine) {
+        let e = init_failure(engine.as_mut());
+        finish(&mut engine);
+        return Err(format!("{:?} layout: {}", layout, e));
+    }
+    Ok((engine, 1.0 / freq))
+}
+
+fn value(engine : &mut Box<dyn Engine + Send>, signal : &str) -> String {
+    match engine.get(signal).and_then(|mp| decode(&mp)) {
+        Ok(v) => v.to_string(),
+        Err(e) => format!("unavailable ({})", e),
+    }
+}
+
+/// Run a scene twice in lockstep, comparing the hashes of the model states every N frames
+/// @param[in] layouts - how the first and second runs execute the scene
+/// @param[in] stop - simulation time to verify, seconds
+/// @param[in] every - frames between comparisons
+/// @return number of compared frames and the first difference
+pub fn verify(scene_file : &Path, layouts : (Layout, Layout), stop : f64, every : u64) -> Result<Verification, String> {
+    let (mut first, delta) = start(scene_file, layouts.0, every)?;
+    let mut second = match start(scene_file, layouts.1, every) {
+        Ok((e, _)) => e,
+        Err(e) => {
+            finish(&mut first);
+            return Err(e);
+        },
+    };
+    let last = (stop / delta).round() as u64;
+    let mut frames = 0;
+    let mut mismatch = None;
+    let mut checked = 0;
+    while frames < last {
+        frames = (frames + every).min(last);
+        let target = frames as f64 * delta;
+        advance(&mut first, target);
+        advance(&mut second, target);
+        let (a, b) = (first.state_hash(), second.state_hash());
+        match (&a, &b) {
+            (Some(x), Some(y)) if x.frame == y.frame && x.hash != y.hash => {
+                let signal = x.diverging(y);
+                let (va, vb) = match &signal {
+                    Some(s) => (value(&mut first, s), value(&mut second, s)),
+                    None => ("".to_string(), "".to_string()),
+                };
+                mismatch = Some(Mismatch { frame : x.frame, time : x.time, signal, first : va, second : vb });
+                break;
+            },
+            (Some(x), Some(y)) if x.frame == y.frame => checked = x.frame + 1,
+            _ => {},
+        }
+        let (ta, tb) = (first.get_time(), second.get_time());
+        if (ta - tb).abs() > TIME_EPS {
+            let stopped = |t : f64| format!("stopped at t={:.6}", t);
+            let frame = ((ta.min(tb) / delta).round() as u64).max(1) - 1;
+            mismatch = Some(Mismatch { frame, time : frame as f64 * delta, signal : None, first : stopped(ta), second : stopped(tb) });
+            break;
+        }
+        if !matches!(first.get_state(), EngineState::PAUSED) {
+            break;
+        }
+    }
+    finish(&mut first);
+    finish(&mut second);
+    Ok(Verification { frames : checked, mismatch })
+}
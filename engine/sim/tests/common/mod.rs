@@ -0,0 +1,99 @@
+// Fixture shared by the integration tests: scenes of the sine model of the repository,
+// whose sources are generated and whose library is built on first use
+#![allow(dead_code)]
+
+use sim::engine::Engine;
+use sim::scene::{parse_scene, Scene};
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Directory of the sine model library, None if it could not be built
+pub fn sine_library() -> Option<&'static Path> {
+    static DIR : OnceLock<Option<PathBuf>> = OnceLock::new();
+    let dir = DIR.get_or_init(|| match build_sine() {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("skipping, the sine model could not be built: {}", e);
+            None
+        },
+    });
+    dir.as_deref()
+}
+
+// generates the sources of the model from its IDL and builds the library,
+// both are up to date after the first test of a run
+fn build_sine() -> Result<PathBuf, String> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let sine = root.join("utilities/sine");
+    let cargo = std::env::var("CARGO").unwrap_or("cargo".to_string());
+    for target in ["interface.rs", "msgpack.rs", "plugin.rs"] {
+        run(Command::new(&cargo)
+            .args(["run", "-q", "--manifest-path"]).arg(root.join("cargo-rsiswrap/Cargo.toml"))
+            .arg("--target-dir").arg(root.join("cargo-rsiswrap/target"))
+            .args(["--", "--target", target, "src/idl.toml"])
+            .current_dir(&sine))?;
+    }
+    run(Command::new(&cargo)
+        .args(["build", "-q", "--manifest-path"]).arg(sine.join("Cargo.toml"))
+        .arg("--target-dir").arg(sine.join("target")))?;
+    Ok(sine.join("target/debug"))
+}
+
+fn run(cmd : &mut Command) -> Result<(), String> {
+    match cmd.output() {
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => Err(format!("{:?} failed: {}", cmd, String::from_utf8_lossy(&out.stderr))),
+        Err(e) => Err(format!("{:?} failed: {}", cmd, e)),
+    }
+}
+
+/// Scene file contents with the sine library in the search paths of its [scene] table
+pub fn with_paths(txt : &str) -> Option<String> {
+    let dir = sine_library()?;
+    Some(txt.replacen("[scene]\n", &format!("[scene]\npaths = [{:?}]\n", dir.display().to_string()), 1))
+}
+
+/// Parse a scene of sine models, None if the model is not available
+pub fn scene(txt : &str) -> Option<Scene> {
+    Some(parse_scene(&with_paths(txt)?, Path::new(".")).unwrap())
+}
+
+/// Write a scene of sine models to a file of a temporary directory
+pub fn scene_file(dir : &str, txt : &str) -> Option<PathBuf> {
+    let txt = with_paths(txt)?;
+    let dir = temp_dir(dir);
+    let file = dir.join("scene.toml");
+    std::fs::write(&file, txt).unwrap();
+    Some(file)
+}
+
+/// Empty directory for the files of a test
+pub fn temp_dir(name : &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sim_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// One sine generator at 10 Hz
+pub fn sine_scene() -> Option<Scene> {
+    scene(r#"[scene]
+name = "sine"
+engine = "sim"
+[[schedule]]
+lib = "sine"
+name = "gen1"
+freq = 10.0
+"#)
+}
+
+pub fn read(engine : &mut dyn Engine, signal : &str) -> rmpv::Value {
+    let mp = engine.get(signal).unwrap();
+    rmpv::decode::read_value(&mut &mp[..]).unwrap()
+}
+
+pub fn read_f64(engine : &mut dyn Engine, signal : &str) -> f64 {
+    read(engine, signal).as_f64().unwrap()
+}
@@ -0,0 +1,72 @@
+use sim::engine::Engine;
+use sim::plugin::PluginLoader;
+use sim::serial::start_serial_engine;
+use sim::verify::{verify, Layout};
+
+mod common;
+
+use common::read_f64;
+
+// gen2 is modulated by gen1 through a connection between threads
+const TWO_THREADS : &str = r#"[scene]
+name = "layouts"
+engine = "sim"
+
+[[threads]]
+freq = 10.0
+
+[[threads]]
+freq = 10.0
+
+[[schedule]]
+lib = "sine"
+name = "gen1"
+freq = 10.0
+thread = 0
+
+[[schedule]]
+lib = "sine"
+name = "gen2"
+freq = 10.0
+thread = 1
+
+[connections]
+"gen1:gen2" = ["output input.amplitude"]
+
+[[timeline]]
+at = 0.0
+set = {"gen1.input.amplitude" = 1.0, "gen1.input.frequency" = 0.3, "gen2.input.frequency" = 0.7}
+"#;
+
+#[test]
+fn moving_models_between_threads_does_not_change_results() {
+    let Some(file) = common::scene_file("layouts", TWO_THREADS) else { return };
+    let result = verify(&file, (Layout::SERIAL, Layout::SINGLE), 2.0, 1).unwrap();
+    assert!(result.mismatch.is_none(), "{}", result.mismatch.unwrap().report());
+    assert_eq!(result.frames, 20);
+
+    // the comparison is not between idle models
+    let scene = common::scene(TWO_THREADS).unwrap();
+    let mut engine = start_serial_engine(scene.build(&mut PluginLoader::new()).unwrap()).unwrap();
+    engine.set_timeline(scene.timeline());
+    assert_eq!(engine.init(), 0);
+    engine.run_until(1.0);
+    assert!(read_f64(&mut engine, "gen2.output").abs() > 1e-3);
+    assert!(read_f64(&mut engine, "gen2.input.amplitude").abs() > 1e-3);
+}
+
+#[test]
+fn serial_and_threaded_engines_give_the_same_results() {
+    let Some(file) = common::scene_file("engines", TWO_THREADS) else { return };
+    let result = verify(&file, (Layout::SERIAL, Layout::PARALLEL), 2.0, 1).unwrap();
+    assert!(result.mismatch.is_none(), "{}", result.mismatch.unwrap().report());
+    assert_eq!(result.frames, 20);
+}
+
+#[test]
+fn direct_connections_between_threads_are_rejected() {
+    let txt = TWO_THREADS.replace(r#"["output input.amplitude"]"#, r#"[{fields = "output input.amplitude", delay = 0}]"#);
+    let Some(scene) = common::scene(&txt) else { return };
+    let e = scene.build(&mut PluginLoader::new()).err().unwrap();
+    assert!(e.contains("need a delay of at least 1"), "{}", e);
+}
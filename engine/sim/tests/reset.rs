@@ -0,0 +1,90 @@
+use sim::batch::Batch;
+use sim::engine::Engine;
+use sim::params::Parameters;
+use sim::plugin::PluginLoader;
+use sim::runner::parse_assignment;
+use sim::serial::start_serial_engine;
+
+mod common;
+
+use common::{read_f64, sine_scene, temp_dir};
+
+#[test]
+fn reset_reapplies_initial_values() {
+    let Some(mut scene) = sine_scene() else { return };
+    scene.initial.push(("gen1.params.amplitude".to_string(), rmpv::Value::F64(2.0)));
+    let mut loader = PluginLoader::new();
+    let mut engine = start_serial_engine(scene.build(&mut loader).unwrap()).unwrap();
+    assert_eq!(engine.init(), 0);
+    engine.step(3);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), 2.0);
+
+    assert_eq!(engine.reset(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), 2.0);
+    assert_eq!(engine.init(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.input.amplitude"), 2.0);
+}
+
+#[test]
+fn reset_keeps_the_last_initial_value_of_a_field() {
+    let Some(mut scene) = sine_scene() else { return };
+    scene.initial.push(("gen1.params.amplitude".to_string(), rmpv::Value::F64(2.0)));
+    scene.initial.push(("gen1.params.amplitude".to_string(), rmpv::Value::F64(5.0)));
+    let mut loader = PluginLoader::new();
+    let mut engine = start_serial_engine(scene.build(&mut loader).unwrap()).unwrap();
+    assert_eq!(engine.init(), 0);
+    assert_eq!(engine.reset(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), 5.0);
+}
+
+#[test]
+fn reset_reapplies_parameter_files() {
+    let dir = temp_dir("reset_params");
+    let file = dir.join("gen1.json");
+    std::fs::write(&file, r#"{"params": {"amplitude": 3.0, "bias": 0.5}}"#).unwrap();
+
+    let Some(mut scene) = sine_scene() else { return };
+    scene.parameters.push(Parameters { file, models : vec!["gen1".to_string()], key : "[schedule][0].params".to_string() });
+    let mut loader = PluginLoader::new();
+    let mut engine = start_serial_engine(scene.build(&mut loader).unwrap()).unwrap();
+    assert_eq!(engine.init(), 0);
+    engine.step(2);
+    assert_eq!(engine.reset(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), 3.0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.bias"), 0.5);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reset_reapplies_batch_case_values() {
+    let dir = temp_dir("reset_batch");
+    let file = dir.join("mc.toml");
+    std::fs::write(&file, "scene = \"none.toml\"\ncases = 4\nseed = 11\n[dispersions]\n\"gen1.params.amplitude\" = {dist = \"uniform\", min = 2.0, max = 3.0}\n").unwrap();
+    let case = Batch::load(&file).unwrap().case(3).unwrap();
+    let dispersed = case.values[0].1.as_float().unwrap();
+    assert!((2.0..3.0).contains(&dispersed));
+
+    let Some(mut scene) = sine_scene() else { return };
+    case.apply(&mut scene).unwrap();
+    let mut loader = PluginLoader::new();
+    let mut engine = start_serial_engine(scene.build(&mut loader).unwrap()).unwrap();
+    assert_eq!(engine.init(), 0);
+    engine.step(2);
+    assert_eq!(engine.reset(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), dispersed);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reset_reapplies_command_line_values() {
+    let Some(mut scene) = sine_scene() else { return };
+    scene.initial.push(parse_assignment("gen1.params.amplitude=2").unwrap());
+    scene.initial.push(parse_assignment("gen1.params.frequency = 0.5").unwrap());
+    let mut loader = PluginLoader::new();
+    let mut engine = start_serial_engine(scene.build(&mut loader).unwrap()).unwrap();
+    assert_eq!(engine.init(), 0);
+    engine.step(2);
+    assert_eq!(engine.reset(), 0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.amplitude"), 2.0);
+    assert_eq!(read_f64(&mut engine, "gen1.params.frequency"), 0.5);
+}
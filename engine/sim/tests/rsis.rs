@@ -0,0 +1,35 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+// runs the two sine scene of the repository with the rsis command line
+fn run_st_2m(name : &str, args : &[&str]) -> Option<Output> {
+    let models = common::sine_library()?;
+    let dir = common::temp_dir(name);
+    let scene = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../test/scenes/st_2m.toml");
+    let out = Command::new(env!("CARGO_BIN_EXE_rsis"))
+        .arg("run").arg(scene).args(["--stop", "10"]).arg("--log").arg(&dir).args(args)
+        .env("RSIS_MODEL_PATH", models)
+        .output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    Some(out)
+}
+
+#[test]
+fn a_passing_monitor_exits_with_0() {
+    let Some(out) = run_st_2m("rsis_pass", &[]) else { return };
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(0), "{}", stdout);
+    assert!(stdout.contains("pass gen2 amplitude"), "{}", stdout);
+}
+
+#[test]
+fn a_failing_monitor_exits_with_1() {
+    let Some(out) = run_st_2m("rsis_fail", &["--set", "gen1.params.amplitude=2"]) else { return };
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(out.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("FAIL gen2 amplitude"), "{}", stdout);
+}
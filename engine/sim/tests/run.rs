@@ -0,0 +1,50 @@
+use sim::batch::Batch;
+use sim::runner::{run_scene, RunOptions};
+
+mod common;
+
+const SINE : &str = r#"[scene]
+name = "run"
+engine = "sim"
+
+[[schedule]]
+lib = "sine"
+name = "gen1"
+freq = 10.0
+"#;
+
+fn run(name : &str, timeline : &str, serial : bool) -> Option<sim::runner::RunOutcome> {
+    let file = common::scene_file(name, &format!("{}{}", SINE, timeline))?;
+    let options = RunOptions { stop : Some(2.0), serial, ..Default::default() };
+    Some(run_scene(&file, &options).unwrap())
+}
+
+#[test]
+fn pauses_are_resumed_until_the_stop_time() {
+    for serial in [false, true] {
+        let Some(outcome) = run("run_pause", "[[timeline]]\nat = 0.5\npause = true\n", serial) else { return };
+        assert!((outcome.time - 2.0).abs() < 1e-9, "{}", outcome.time);
+        assert!(outcome.verdict.passed(), "{:?}", outcome.verdict);
+    }
+}
+
+#[test]
+fn a_run_ended_by_the_timeline_passes() {
+    for serial in [false, true] {
+        let Some(outcome) = run("run_end", "[[timeline]]\nat = 0.5\nend = true\n", serial) else { return };
+        assert!((outcome.time - 0.5).abs() < 1e-9, "{}", outcome.time);
+        assert_eq!(outcome.verdict.exit_code(), 0, "{:?}", outcome.verdict);
+    }
+}
+
+#[test]
+fn batch_cases_resume_pauses_until_the_stop_time() {
+    let Some(file) = common::scene_file("batch_pause", &format!("{}[[timeline]]\nat = 0.5\npause = true\n", SINE)) else { return };
+    let dir = file.parent().unwrap();
+    let batch_file = dir.join("batch.toml");
+    std::fs::write(&batch_file, "scene = \"scene.toml\"\ncases = 1\nstop = 2.0\noutput = \"cases\"\n").unwrap();
+    let results = Batch::load(&batch_file).unwrap().run(&[0], 1, &|_| {}).unwrap();
+    assert!((results[0].time - 2.0).abs() < 1e-9, "{}", results[0].time);
+    assert!(results[0].verdict.passed(), "{:?}", results[0].verdict);
+    std::fs::remove_dir_all(dir).unwrap();
+}
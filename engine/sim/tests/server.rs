@@ -0,0 +1,113 @@
+#![cfg(all(unix, feature = "server"))]
+
+use sim::plugin::PluginLoader;
+use sim::server::{start_server, Endpoint};
+use sim::start_engine_boxed;
+
+use rmpv::Value;
+
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod common;
+
+struct Client {
+    stream : UnixStream,
+}
+
+impl Client {
+    fn send(&mut self, cmd : &str, args : Vec<(&str, Value)>) {
+        let mut map = vec![(Value::from("cmd"), Value::from(cmd))];
+        map.extend(args.into_iter().map(|(k, v)| (Value::from(k), v)));
+        rmpv::encode::write_value(&mut self.stream, &Value::Map(map)).unwrap();
+    }
+
+    fn read(&mut self) -> (String, Value) {
+        let msg = rmpv::decode::read_value(&mut self.stream).unwrap();
+        let event = msg["event"].as_str().unwrap().to_string();
+        (event, msg)
+    }
+
+    // the answer to the last command, skipping the streamed messages
+    fn answer(&mut self) -> (String, Value) {
+        loop {
+            let (event, msg) = self.read();
+            if event != "state" && event != "signals" {
+                return (event, msg);
+            }
+        }
+    }
+
+    fn command(&mut self, cmd : &str, args : Vec<(&str, Value)>) -> (String, Value) {
+        self.send(cmd, args);
+        self.answer()
+    }
+
+    fn wait_state(&mut self, state : &str) {
+        // streamed state messages answer as well as the state command
+        self.send("state", vec![]);
+        loop {
+            let (event, msg) = self.read();
+            if event == "state" && msg["state"].as_str() == Some(state) {
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn get_set_and_stream_round_trip() {
+    let Some(scene) = common::sine_scene() else { return };
+    // frames are paced to wall time, so that signals are streamed while the engine runs
+    let engine = start_engine_boxed(scene.build(&mut PluginLoader::new()).unwrap(), true).unwrap();
+    let dir = common::temp_dir("server");
+    let socket = dir.join("rsis.sock");
+    start_server(Arc::new(Mutex::new(engine)), Endpoint::Unix(socket.clone())).unwrap();
+
+    let stream = UnixStream::connect(&socket).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(20))).unwrap();
+    let mut client = Client { stream };
+    assert_eq!(client.command("init", vec![]).0, "ok");
+    client.wait_state("initialized");
+
+    for (path, value) in [("gen1.input.amplitude", 2.0), ("gen1.input.frequency", 0.5)] {
+        assert_eq!(client.command("set", vec![("path", Value::from(path)), ("value", Value::F64(value))]).0, "ok");
+        let (event, msg) = client.command("get", vec![("path", Value::from(path))]);
+        assert_eq!(event, "value");
+        assert_eq!(msg["path"].as_str(), Some(path));
+        assert_eq!(msg["value"].as_f64(), Some(value));
+    }
+    let (event, msg) = client.command("get", vec![("path", Value::from("gen1.input.missing"))]);
+    assert_eq!(event, "error", "{}", msg);
+
+    let signals = Value::Array(vec![Value::from("gen1.phase"), Value::from("gen1.output")]);
+    assert_eq!(client.command("subscribe", vec![("signals", signals), ("period", Value::F64(0.01))]).0, "ok");
+    assert_eq!(client.command("run_until", vec![("time", Value::F64(1.0))]).0, "ok");
+
+    // every message holds the phase and output of the same frame, until the run pauses at t = 1
+    let mut running = 0;
+    loop {
+        let (event, msg) = client.read();
+        if event != "signals" {
+            continue;
+        }
+        let time = msg["time"].as_f64().unwrap();
+        let values = msg["values"].as_array().unwrap();
+        assert_eq!(values[0][0].as_str(), Some("gen1.phase"));
+        let (phase, output) = (values[0][1].as_f64().unwrap(), values[1][1].as_f64().unwrap());
+        let steps = (time / 0.1).round();
+        assert!((phase - 0.5 * steps).abs() < 1e-9, "phase {} at t={}", phase, time);
+        if steps > 0.0 {
+            let expected = 2.0 * (0.5 * (steps - 1.0)).sin();
+            assert!((output - expected).abs() < 1e-9, "output {} at t={}", output, time);
+        }
+        if (time - 1.0).abs() < 1e-9 {
+            break;
+        }
+        running += 1;
+    }
+    assert!(running > 0);
+    assert_eq!(client.command("end", vec![]).0, "ok");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,3 +1,5 @@
+pub mod units;
+
 #[repr(u32)]
 pub enum ConfigStatus {
     OK,
@@ -37,3 +39,47 @@ pub trait RModel {
     /// Hook is called upon termination of the model
     fn halt(&mut self, _: &mut Box<dyn RFrameWork>) -> RunStatus;
 }
+
+/// Version of the C-ABI exported by generated model plugins.
+/// Incremented whenever an exported symbol or its signature changes
+pub const RSIS_ABI_VERSION: u32 = 1;
+
+/// RFrameTime
+/// FFI-safe snapshot of the framework, passed across the plugin boundary
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct RFrameTime {
+    pub time: f64,
+    pub tick: i64,
+    pub delta: f64,
+}
+
+impl RFrameWork for RFrameTime {
+    fn get_time(&self) -> f64 {
+        self.time
+    }
+    fn get_tick(&self) -> i64 {
+        self.tick
+    }
+    fn get_tdelta(&self) -> f64 {
+        self.delta
+    }
+}
+
+/// RInterface
+/// MessagePack access to the model interface, using the index
+/// scheme of the generated `*_msgpack.rs` functions
+pub trait RInterface {
+    /// Encode the element at the index
+    fn get_msgpack(&mut self, ind: &[i32]) -> Result<Vec<u8>, String>;
+
+    /// Decode into the element at the index, returns 0 on success,
+    /// 1 if the index is empty, 2 if it is out of range and 3 if the value does not decode
+    fn set_msgpack(&mut self, ind: &[i32], mp: &[u8]) -> i32;
+}
+
+/// RPlugin
+/// Model instance held by a generated plugin
+pub trait RPlugin : RModel + RInterface {}
+
+impl<M: RModel + RInterface> RPlugin for M {}
@@ -0,0 +1,314 @@
+/// Exponents of the base quantities of a unit: length, mass, time, current,
+/// temperature, amount of substance, luminous intensity and plane angle.
+/// Angles are kept as a dimension so that degrees are not mixed with plain numbers
+pub type Dimension = [i8; 8];
+
+const NONE: Dimension = [0, 0, 0, 0, 0, 0, 0, 0];
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1, 0];
+const ANGLE: Dimension = [0, 0, 0, 0, 0, 0, 0, 1];
+const SPEED: Dimension = [1, 0, -1, 0, 0, 0, 0, 0];
+const ACCELERATION: Dimension = [1, 0, -2, 0, 0, 0, 0, 0];
+const ANGULAR_RATE: Dimension = [0, 0, -1, 0, 0, 0, 0, 1];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0, 0];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0, 0];
+const CHARGE: Dimension = [0, 0, 1, 1, 0, 0, 0, 0];
+const VOLTAGE: Dimension = [2, 1, -3, -1, 0, 0, 0, 0];
+const RESISTANCE: Dimension = [2, 1, -3, -2, 0, 0, 0, 0];
+
+// name, dimension, scale and offset to the SI unit, accepts SI prefixes
+const NAMED: &[(&str, Dimension, f64, f64, bool)] = &[
+    ("%", NONE, 0.01, 0.0, false),
+    // SI base units, the kilogram is the prefixed gram
+    ("m", LENGTH, 1.0, 0.0, true),
+    ("g", MASS, 1e-3, 0.0, true),
+    ("s", TIME, 1.0, 0.0, true),
+    ("A", CURRENT, 1.0, 0.0, true),
+    ("K", TEMPERATURE, 1.0, 0.0, true),
+    ("mol", AMOUNT, 1.0, 0.0, true),
+    ("cd", LUMINOSITY, 1.0, 0.0, true),
+    ("rad", ANGLE, 1.0, 0.0, true),
+    // SI derived units
+    ("Hz", FREQUENCY, 1.0, 0.0, true),
+    ("N", FORCE, 1.0, 0.0, true),
+    ("Pa", PRESSURE, 1.0, 0.0, true),
+    ("J", ENERGY, 1.0, 0.0, true),
+    ("W", POWER, 1.0, 0.0, true),
+    ("C", CHARGE, 1.0, 0.0, true),
+    ("V", VOLTAGE, 1.0, 0.0, true),
+    ("ohm", RESISTANCE, 1.0, 0.0, true),
+    ("L", VOLUME, 1e-3, 0.0, true),
+    ("bar", PRESSURE, 1e5, 0.0, true),
+    // time
+    ("min", TIME, 60.0, 0.0, false),
+    ("h", TIME, 3600.0, 0.0, false),
+    ("day", TIME, 86400.0, 0.0, false),
+    // temperature, offset units cannot be combined with others
+    ("degC", TEMPERATURE, 1.0, 273.15, false),
+    ("degF", TEMPERATURE, 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0, false),
+    ("degR", TEMPERATURE, 5.0 / 9.0, 0.0, false),
+    // angle
+    ("deg", ANGLE, std::f64::consts::PI / 180.0, 0.0, false),
+    ("rev", ANGLE, 2.0 * std::f64::consts::PI, 0.0, false),
+    ("rpm", ANGULAR_RATE, 2.0 * std::f64::consts::PI / 60.0, 0.0, false),
+    // imperial and aerospace
+    ("ft", LENGTH, 0.3048, 0.0, false),
+    ("in", LENGTH, 0.0254, 0.0, false),
+    ("mi", LENGTH, 1609.344, 0.0, false),
+    ("nmi", LENGTH, 1852.0, 0.0, false),
+    ("kt", SPEED, 1852.0 / 3600.0, 0.0, false),
+    ("g0", ACCELERATION, 9.80665, 0.0, false),
+    ("lb", MASS, 0.45359237, 0.0, false),
+    ("lbm", MASS, 0.45359237, 0.0, false),
+    ("slug", MASS, 14.593902937206364, 0.0, false),
+    ("lbf", FORCE, 4.4482216152605, 0.0, false),
+    ("psi", PRESSURE, 6894.757293168361, 0.0, false),
+    ("inHg", PRESSURE, 3386.389, 0.0, false),
+    ("atm", PRESSURE, 101325.0, 0.0, false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("da", 1e1), ("G", 1e9), ("M", 1e6), ("k", 1e3), ("h", 1e2),
+    ("d", 1e-1), ("c", 1e-2), ("m", 1e-3), ("u", 1e-6), ("µ", 1e-6), ("n", 1e-9), ("p", 1e-12),
+];
+
+/// Unit
+/// A unit as an affine map to the SI unit of its dimension, `si = value * scale + offset`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Unit {
+    pub dimension: Dimension,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Unit {
+    pub fn dimensionless() -> Unit {
+        Unit { dimension: NONE, scale: 1.0, offset: 0.0 }
+    }
+
+    /// Parse a unit expression, e.g. `m`, `km/h`, `kg*m^2`, `m/s^-2`, `N.m` or `kg/(m*s^2)`.
+    /// Names are SI units with an optional prefix, or common imperial and aerospace units
+    pub fn parse(txt: &str) -> Result<Unit, String> {
+        let mut parser = Parser { txt, pos: 0 };
+        let unit = match parser.product() {
+            Ok(u) => u,
+            Err(e) => return Err(format!("Invalid unit [{}]: {}", txt, e)),
+        };
+        parser.skip_space();
+        if parser.pos != txt.len() {
+            return Err(format!("Invalid unit [{}]: unexpected [{}]", txt, &txt[parser.pos..]));
+        }
+        Ok(unit)
+    }
+
+    /// True if values can be converted between the units
+    pub fn compatible(&self, other: &Unit) -> bool {
+        self.dimension == other.dimension
+    }
+
+    /// Scale and offset converting a value in this unit to the other unit,
+    /// `to = value * scale + offset`
+    pub fn conversion(&self, to: &Unit) -> Result<(f64, f64), String> {
+        if !self.compatible(to) {
+            return Err(format!("Units with dimensions {:?} and {:?} are not compatible", self.dimension, to.dimension));
+        }
+        Ok((self.scale / to.scale, (self.offset - to.offset) / to.scale))
+    }
+
+    fn combine(&self, other: &Unit, sign: i8) -> Result<Unit, String> {
+        if self.offset != 0.0 || other.offset != 0.0 {
+            return Err("units with an offset, such as degC, cannot be combined".to_string());
+        }
+        let mut dimension = self.dimension;
+        for (d, o) in dimension.iter_mut().zip(other.dimension.iter()) {
+            *d += sign * o;
+        }
+        Ok(Unit { dimension, scale: self.scale * other.scale.powi(sign as i32), offset: 0.0 })
+    }
+
+    fn pow(&self, n: i8) -> Result<Unit, String> {
+        if self.offset != 0.0 && n != 1 {
+            return Err("units with an offset, such as degC, cannot be raised to a power".to_string());
+        }
+        let mut dimension = self.dimension;
+        for d in dimension.iter_mut() {
+            *d *= n;
+        }
+        Ok(Unit { dimension, scale: self.scale.powi(n as i32), offset: self.offset })
+    }
+}
+
+// recursive descent over the unit grammar:
+//   product := power (('*' | '.' | '/') power)*
+//   power   := primary ('^' '-'? integer)?
+//   primary := '(' product ')' | '1' | name
+struct Parser<'a> {
+    txt: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        while self.txt[self.pos..].starts_with(' ') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_space();
+        self.txt[self.pos..].chars().next()
+    }
+
+    fn product(&mut self) -> Result<Unit, String> {
+        let mut unit = self.power()?;
+        loop {
+            match self.peek() {
+                Some('*') | Some('.') => {
+                    self.pos += 1;
+                    unit = unit.combine(&self.power()?, 1)?;
+                },
+                Some('/') => {
+                    self.pos += 1;
+                    unit = unit.combine(&self.power()?, -1)?;
+                },
+                _ => return Ok(unit),
+            }
+        }
+    }
+
+    fn power(&mut self) -> Result<Unit, String> {
+        let unit = self.primary()?;
+        if self.peek() != Some('^') {
+            return Ok(unit);
+        }
+        self.pos += 1;
+        self.skip_space();
+        let rest = &self.txt[self.pos..];
+        let len = rest.char_indices()
+            .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '-'))
+            .count();
+        match rest[..len].parse::<i8>() {
+            Ok(n) => {
+                self.pos += len;
+                unit.pow(n)
+            },
+            Err(_) => Err("expected an integer exponent after ^".to_string()),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Unit, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let unit = self.product()?;
+                if self.peek() != Some(')') {
+                    return Err("missing )".to_string());
+                }
+                self.pos += 1;
+                Ok(unit)
+            },
+            Some('1') => {
+                self.pos += 1;
+                Ok(Unit::dimensionless())
+            },
+            Some(c) if c.is_alphabetic() || c == '%' => {
+                let rest = &self.txt[self.pos..];
+                let len: usize = rest.chars()
+                    .enumerate()
+                    .take_while(|(i, c)| c.is_alphabetic() || *c == '%' || (*i > 0 && c.is_ascii_digit()))
+                    .map(|(_, c)| c.len_utf8())
+                    .sum();
+                let name = &rest[..len];
+                self.pos += len;
+                lookup(name)
+            },
+            Some(c) => Err(format!("unexpected [{}]", c)),
+            None => Err("expected a unit".to_string()),
+        }
+    }
+}
+
+// exact names take precedence over prefixed ones, `min` is minutes and not milli-inches
+fn lookup(name: &str) -> Result<Unit, String> {
+    if let Some((_, dimension, scale, offset, _)) = NAMED.iter().find(|u| u.0 == name) {
+        return Ok(Unit { dimension: *dimension, scale: *scale, offset: *offset });
+    }
+    for (prefix, factor) in PREFIXES {
+        if let Some(base) = name.strip_prefix(prefix) {
+            if let Some((_, dimension, scale, _, _)) = NAMED.iter().find(|u| u.0 == base && u.4) {
+                return Ok(Unit { dimension: *dimension, scale: scale * factor, offset: 0.0 });
+            }
+        }
+    }
+    Err(format!("unknown unit [{}]", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_unit(txt: &str, dimension: Dimension, scale: f64) {
+        let unit = Unit::parse(txt).unwrap();
+        assert_eq!(unit.dimension, dimension, "dimension of {}", txt);
+        assert!((unit.scale - scale).abs() <= 1e-12 * scale.abs(), "scale of {} is {}, not {}", txt, unit.scale, scale);
+        assert_eq!(unit.offset, 0.0);
+    }
+
+    #[test]
+    fn si_prefixes() {
+        assert_unit("km", LENGTH, 1e3);
+        assert_unit("mm", LENGTH, 1e-3);
+        assert_unit("kg", MASS, 1.0);
+        assert_unit("us", TIME, 1e-6);
+        assert_unit("µs", TIME, 1e-6);
+        assert_unit("MHz", FREQUENCY, 1e6);
+        assert_unit("dam", LENGTH, 10.0);
+        // exact names take precedence, and only SI units take prefixes
+        assert_unit("min", TIME, 60.0);
+        assert!(Unit::parse("kft").is_err());
+    }
+
+    #[test]
+    fn compound_units() {
+        assert_unit("km/h", SPEED, 1e3 / 3600.0);
+        assert_unit("m/s^2", ACCELERATION, 1.0);
+        assert_unit("m / s ^ 2", ACCELERATION, 1.0);
+        assert_unit("m*s^-2", ACCELERATION, 1.0);
+        assert_unit("N.m", ENERGY, 1.0);
+        assert_unit("kg*m^2/s^2", ENERGY, 1.0);
+        assert_unit("kg/(m*s^2)", PRESSURE, 1.0);
+        assert_unit("1/s", FREQUENCY, 1.0);
+        assert_unit("deg/s", ANGULAR_RATE, std::f64::consts::PI / 180.0);
+    }
+
+    #[test]
+    fn conversions() {
+        let (scale, offset) = Unit::parse("degC").unwrap().conversion(&Unit::parse("K").unwrap()).unwrap();
+        assert_eq!((scale, offset), (1.0, 273.15));
+        let (scale, offset) = Unit::parse("ft").unwrap().conversion(&Unit::parse("m").unwrap()).unwrap();
+        assert_eq!((scale, offset), (0.3048, 0.0));
+        assert!(Unit::parse("rpm").unwrap().compatible(&Unit::parse("rad/s").unwrap()));
+        assert!(Unit::parse("m").unwrap().conversion(&Unit::parse("s").unwrap()).is_err());
+    }
+
+    #[test]
+    fn invalid_units() {
+        assert_eq!(Unit::parse("furlong"), Err("Invalid unit [furlong]: unknown unit [furlong]".to_string()));
+        assert!(Unit::parse("").is_err());
+        assert!(Unit::parse("m^").is_err());
+        assert!(Unit::parse("(m").is_err());
+        assert!(Unit::parse("m)").is_err());
+        assert!(Unit::parse("kdegC").is_err());
+        assert!(Unit::parse("degC/s").is_err());
+        assert!(Unit::parse("degC^2").is_err());
+    }
+}
@@ -75,7 +75,8 @@ function _autogen_paths() :: Vector{String}
     n = projectinfo().name
     d = projectinfo().directory
     return [joinpath(d, "src", n * "_interface.rs"),
-            joinpath(d, "src", n * "_msgpack.rs")]
+            joinpath(d, "src", n * "_msgpack.rs"),
+            joinpath(d, "src", n * "_plugin.rs")]
 end
 """
 [NOEXPORT] interfaces_out_of_date()
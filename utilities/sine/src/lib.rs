@@ -8,6 +8,9 @@ use sine_interface::*;
 #[cfg(feature = "msgpack")]
 pub mod sine_msgpack;
 
+#[cfg(feature = "msgpack")]
+pub mod sine_plugin;
+
 extern crate rmodel;
 use rmodel::*;
 
@@ -55,8 +58,6 @@ impl RModel for sine<f64> {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {
         assert_eq!(4, 4);